@@ -0,0 +1,443 @@
+//! Tamper-evident audit log of operator actions.
+//!
+//! Every command typed, every request written to a device and every scan
+//! started is appended to a JSON Lines file, one object per line:
+//!
+//! ```json
+//! {"action":"command","detail":"scan ports 192.168.56.10","hash":"9f…","operator":"alice",
+//!  "prev_hash":"41…","result":"ok","seq":17,"target":"default","timestamp":"2026-10-17T14:03:11+02:00"}
+//! ```
+//!
+//! `hash` is the SHA-256 of `prev_hash` followed by the entry itself (keys
+//! sorted, without `hash`), and `prev_hash` is the hash of the line before,
+//! or all zeros for the first entry. Editing, reordering or deleting a line
+//! breaks the chain, which `audit verify` detects. Cutting lines off the end
+//! can only be detected against a hash noted elsewhere, so `audit verify`
+//! prints the last hash for inclusion in reports.
+
+use crate::error::CliError;
+use crate::output::formatter::print_error;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// `prev_hash` of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What kind of action an entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A command typed by the operator.
+    Command,
+    /// A request written to a device.
+    Serial,
+    /// A network or device scan being started.
+    Scan,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditAction::Command => write!(f, "command"),
+            AuditAction::Serial => write!(f, "serial"),
+            AuditAction::Scan => write!(f, "scan"),
+        }
+    }
+}
+
+/// One action to append.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub detail: String,
+    /// Bytes written, hex encoded, for [`AuditAction::Serial`].
+    pub bytes: Option<String>,
+    pub result: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(action: AuditAction, detail: &str) -> Self {
+        Self {
+            action,
+            target: None,
+            detail: detail.to_string(),
+            bytes: None,
+            result: None,
+        }
+    }
+
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn bytes(mut self, hex: String) -> Self {
+        self.bytes = Some(hex);
+        self
+    }
+
+    pub fn result(mut self, result: impl Into<String>) -> Self {
+        self.result = Some(result.into());
+        self
+    }
+}
+
+/// Handle to the session's audit log.
+///
+/// Cheap to clone; all clones append to the same chain. A disabled log
+/// accepts records and drops them.
+///
+/// Each append takes an exclusive lock on the file and continues from the
+/// entry it finds last, so several sessions can share one log without
+/// forking the chain.
+#[derive(Clone)]
+pub struct AuditLog {
+    file: Option<Arc<Mutex<File>>>,
+    operator: String,
+    path: String,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, continuing the chain already in it.
+    pub fn open(path: &str, operator: &str) -> Result<Self> {
+        if let Some(dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path))?;
+
+        // Refuse a log we couldn't continue rather than fail on every action
+        locked(&mut file, path, |file| last_entry(file, path))?;
+
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+            operator: operator.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// A log that records nothing.
+    pub fn disabled() -> Self {
+        Self {
+            file: None,
+            operator: String::new(),
+            path: String::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// File the log is written to; empty when disabled.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Append `record` to the chain.
+    ///
+    /// Failing to write is reported but doesn't stop the action being recorded.
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(e) = self.append(record) {
+            print_error(&format!("Audit log: {:#}", e));
+        }
+    }
+
+    fn append(&self, record: AuditRecord) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut file = file.lock().map_err(|_| anyhow!("Audit log is poisoned"))?;
+        locked(&mut file, &self.path, |file| {
+            // Another session may have appended since our last entry
+            let (seq, prev_hash) = match last_entry(file, &self.path)? {
+                Some(last) => (
+                    last["seq"].as_u64().unwrap_or_default() + 1,
+                    last["hash"].as_str().unwrap_or_default().to_string(),
+                ),
+                None => (0, GENESIS_HASH.to_string()),
+            };
+            self.write_entry(file, record, seq, &prev_hash)
+        })
+    }
+
+    fn write_entry(&self, file: &mut File, record: AuditRecord, seq: u64, prev_hash: &str) -> Result<()> {
+        let mut entry = json!({
+            "seq": seq,
+            "timestamp": Local::now().to_rfc3339(),
+            "operator": self.operator,
+            "action": record.action,
+            "target": record.target,
+            "detail": record.detail,
+            "result": record.result,
+            "prev_hash": prev_hash,
+        });
+        if let (Some(bytes), Value::Object(fields)) = (record.bytes, &mut entry) {
+            fields.insert("bytes".to_string(), Value::String(bytes));
+        }
+
+        let hash = entry_hash(prev_hash, &entry);
+        if let Value::Object(fields) = &mut entry {
+            fields.insert("hash".to_string(), Value::String(hash));
+        }
+
+        writeln!(file, "{}", entry).context("Failed to write audit entry")?;
+        file.flush().context("Failed to write audit entry")?;
+        Ok(())
+    }
+}
+
+/// The operator to record when the config doesn't name one: the login user.
+pub fn default_operator() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Result of checking a log's hash chain.
+#[derive(Debug, Serialize)]
+pub struct ChainReport {
+    pub path: String,
+    pub entries: u64,
+    /// Hash of the last entry, to quote in reports.
+    pub last_hash: String,
+}
+
+/// Check every link of the chain in `path`.
+///
+/// Fails at the first entry that was edited, inserted, reordered or removed.
+pub fn verify(path: &str) -> Result<ChainReport> {
+    let file = File::open(path).with_context(|| format!("Failed to open audit log {}", path))?;
+
+    let mut previous = GENESIS_HASH.to_string();
+    let mut expected_seq: Option<u64> = None;
+    let mut entries = 0;
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.with_context(|| format!("Failed to read {}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let broken = |reason: &str| {
+            anyhow::Error::from(CliError::AuditChainBroken(format!(
+                "Audit chain broken at line {}: {}",
+                line_number, reason
+            )))
+        };
+
+        let mut entry: Value = serde_json::from_str(&line).map_err(|_| broken("not a JSON object"))?;
+        let fields: &mut Map<String, Value> = entry.as_object_mut().ok_or_else(|| broken("not a JSON object"))?;
+        let hash = match fields.remove("hash") {
+            Some(Value::String(hash)) => hash,
+            _ => return Err(broken("missing hash")),
+        };
+        let seq = fields.get("seq").and_then(Value::as_u64).ok_or_else(|| broken("missing seq"))?;
+        let prev_hash = fields.get("prev_hash").and_then(Value::as_str).unwrap_or_default();
+
+        if let Some(expected) = expected_seq.filter(|expected| *expected != seq) {
+            return Err(broken(&format!("expected entry {}, found {}", expected, seq)));
+        }
+        if prev_hash != previous {
+            return Err(broken("does not follow the entry before it"));
+        }
+        if entry_hash(&previous, &entry) != hash {
+            return Err(broken("entry was modified"));
+        }
+
+        previous = hash;
+        expected_seq = Some(seq + 1);
+        entries += 1;
+    }
+
+    Ok(ChainReport {
+        path: path.to_string(),
+        entries,
+        last_hash: previous,
+    })
+}
+
+/// SHA-256 of `prev_hash` followed by `entry` (without its `hash`), hex encoded.
+fn entry_hash(prev_hash: &str, entry: &Value) -> String {
+    let mut input = prev_hash.as_bytes().to_vec();
+    // serde_json keeps object keys sorted, so this is canonical
+    input.extend_from_slice(entry.to_string().as_bytes());
+
+    digest(&SHA256, &input)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Run `f` holding an exclusive lock on the log, waiting for other sessions.
+fn locked<T>(file: &mut File, path: &str, f: impl FnOnce(&mut File) -> Result<T>) -> Result<T> {
+    file.lock_exclusive()
+        .with_context(|| format!("Failed to lock audit log {}", path))?;
+    let result = f(file);
+    FileExt::unlock(&*file).with_context(|| format!("Failed to unlock audit log {}", path))?;
+    result
+}
+
+/// The last entry in the log, if it has one.
+fn last_entry(file: &mut File, path: &str) -> Result<Option<Value>> {
+    let Some(line) = last_line(file).with_context(|| format!("Failed to read audit log {}", path))? else {
+        return Ok(None);
+    };
+
+    // Continuing from genesis would hide the damage behind a fresh, valid chain
+    match serde_json::from_slice::<Value>(&line) {
+        Ok(entry) if entry.get("seq").is_some_and(Value::is_u64) && entry.get("hash").is_some_and(Value::is_string) => {
+            Ok(Some(entry))
+        },
+        _ => Err(CliError::AuditChainBroken(format!(
+            "Audit log {} ends in a damaged entry; run 'audit verify' and move the file aside before recording more",
+            path
+        ))
+        .into()),
+    }
+}
+
+/// The last non-blank line of `file`, read backwards from the end so long
+/// logs aren't read whole on every append.
+fn last_line(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+    const BLOCK: u64 = 4096;
+
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&tail);
+        tail = block;
+        end = start;
+
+        let content = tail.trim_ascii_end();
+        if let Some(newline) = content.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(Some(content[newline + 1..].to_vec()));
+        }
+    }
+
+    let content = tail.trim_ascii_end();
+    Ok((!content.is_empty()).then(|| content.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A log of `count` entries in a fresh temporary file.
+    fn log_with(name: &str, count: usize) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("secot-audit-{}-{}.log", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = fs::remove_file(&path);
+
+        let log = AuditLog::open(&path, "tester").unwrap();
+        for index in 0..count {
+            log.append(AuditRecord::new(AuditAction::Command, &format!("command {}", index)).result("ok"))
+                .unwrap();
+        }
+        path
+    }
+
+    fn lines(path: &str) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn rewrite(path: &str, lines: &[String]) {
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn broken_at(path: &str) -> String {
+        let err = verify(path).unwrap_err();
+        assert!(matches!(err.downcast_ref::<CliError>(), Some(CliError::AuditChainBroken(_))));
+        err.to_string()
+    }
+
+    #[test]
+    fn appended_entries_verify() {
+        let path = log_with("intact", 5);
+        let report = verify(&path).unwrap();
+        assert_eq!(report.entries, 5);
+
+        let last: Value = serde_json::from_str(lines(&path).last().unwrap()).unwrap();
+        assert_eq!(last["seq"], 4);
+        assert_eq!(last["hash"].as_str(), Some(report.last_hash.as_str()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reopening_continues_the_chain() {
+        let path = log_with("reopen", 2);
+        let log = AuditLog::open(&path, "tester").unwrap();
+        log.append(AuditRecord::new(AuditAction::Scan, "scan network")).unwrap();
+
+        assert_eq!(verify(&path).unwrap().entries, 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edited_entry_breaks_the_chain() {
+        let path = log_with("edited", 5);
+        let mut lines = lines(&path);
+        lines[2] = lines[2].replace("command 2", "command x");
+        rewrite(&path, &lines);
+
+        assert!(broken_at(&path).contains("line 3: entry was modified"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn deleted_line_breaks_the_chain() {
+        let path = log_with("deleted", 5);
+        let mut lines = lines(&path);
+        lines.remove(1);
+        rewrite(&path, &lines);
+
+        assert!(broken_at(&path).contains("line 2: expected entry 1, found 2"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reordered_lines_break_the_chain() {
+        let path = log_with("reordered", 5);
+        let mut lines = lines(&path);
+        lines.swap(1, 3);
+        rewrite(&path, &lines);
+
+        assert!(broken_at(&path).contains("line 2: expected entry 1, found 3"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn damaged_tail_is_not_continued() {
+        let path = log_with("damaged", 3);
+        let mut lines = lines(&path);
+        lines[2].truncate(20);
+        rewrite(&path, &lines);
+
+        let err = AuditLog::open(&path, "tester").err().unwrap();
+        assert!(matches!(err.downcast_ref::<CliError>(), Some(CliError::AuditChainBroken(_))));
+        fs::remove_file(path).unwrap();
+    }
+}
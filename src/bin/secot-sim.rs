@@ -0,0 +1,91 @@
+#![allow(non_snake_case)]
+
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+use SECoT_CLI_Tool::simulator::SimConfig;
+
+/// Simulated SECoT device on a pseudo-terminal.
+#[derive(Parser)]
+#[command(name = "secot-sim", version)]
+struct Args {
+    /// JSON file with canned responses and faults
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Create a symlink to the pty at this path
+    #[arg(short, long)]
+    link: Option<PathBuf>,
+
+    /// Delay before every response line, in milliseconds
+    #[arg(long)]
+    delay_ms: Option<u64>,
+
+    /// Refuse the framed protocol, like older firmware
+    #[arg(long)]
+    text_only: bool,
+
+    /// Ignore request tags and answer without tags or OK/ERR, like the oldest firmware
+    #[arg(long)]
+    untagged: bool,
+
+    /// Probability (0.0-1.0) of ignoring a command
+    #[arg(long)]
+    drop_rate: Option<f64>,
+
+    /// Probability (0.0-1.0) of corrupting an outgoing line or frame
+    #[arg(long)]
+    corrupt_rate: Option<f64>,
+
+    /// Simulate a reset after this many commands
+    #[arg(long)]
+    reboot_after: Option<u32>,
+
+    /// Seed for fault injection
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut config = match &args.config {
+        Some(path) => SimConfig::load(path)?,
+        None => SimConfig::default(),
+    };
+
+    if let Some(delay_ms) = args.delay_ms {
+        config.delay_ms = delay_ms;
+    }
+    if args.text_only {
+        config.framed = false;
+    }
+    if args.untagged {
+        config.framed = false;
+        config.tagged = false;
+    }
+    if let Some(rate) = args.drop_rate {
+        config.faults.drop_rate = rate;
+    }
+    if let Some(rate) = args.corrupt_rate {
+        config.faults.corrupt_rate = rate;
+    }
+    if args.reboot_after.is_some() {
+        config.faults.reboot_after = args.reboot_after;
+    }
+    if args.seed.is_some() {
+        config.faults.seed = args.seed;
+    }
+
+    #[cfg(unix)]
+    {
+        SECoT_CLI_Tool::simulator::run(config, args.link.as_deref())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = config;
+        SECoT_CLI_Tool::output::formatter::print_error("The simulator needs a Unix pseudo-terminal");
+        std::process::exit(1);
+    }
+}
@@ -1,14 +1,17 @@
+use crate::{errln, outln};
+use crate::config::scope::{ScopeGuard, Target};
 use crate::models::network::MqttBroker;
 use crate::mqtt::broker_utils::test_mqtt_broker;
 use crate::output::formatter::{format_output, print_success, print_error};
 use anyhow::Result;
 use std::net::IpAddr;
 
-pub async fn run_broker_test(ip_str: &str, output_format: &str) -> Result<()> {
+pub async fn run_broker_test(ip_str: &str, output_format: &str, scope: &ScopeGuard<'_>) -> Result<()> {
     let ip: IpAddr = ip_str.parse()?;
+    scope.authorize(&format!("test MQTT broker {}", ip), &[Target::MqttHost(ip)])?;
     let port = 1883; // Default MQTT port
 
-    println!("Testing MQTT broker at {}:{}...", ip, port);
+    errln!("Testing MQTT broker at {}:{}...", ip, port);
 
     // Test direct connection to broker
     let is_accessible = test_mqtt_broker(&ip.to_string(), port).await?;
@@ -29,7 +32,7 @@ pub async fn run_broker_test(ip_str: &str, output_format: &str) -> Result<()> {
 
     // Format and display the result
     let output = format_output(&broker, output_format)?;
-    println!("{}", output);
+    outln!("{}", output);
 
     Ok(())
 }
\ No newline at end of file
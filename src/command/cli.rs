@@ -0,0 +1,101 @@
+//! Command-line arguments.
+//!
+//! Without a subcommand the interactive shell starts. The subcommands are
+//! generated from the [command registry](super::registry), one per REPL
+//! command, and their arguments go to the same handler the REPL uses
+//! (see [`handle_invocation`](super::cmd_handler::handle_invocation)):
+//!
+//! ```text
+//! secot-cli scan ports 10.0.0.5 --format json
+//! secot-cli secot attack deauth --duration 30 --port /dev/ttyUSB0
+//! ```
+//!
+//! `--script <file>` runs a whole file of REPL commands instead (see
+//! [`script`](super::script)). Commands that only make sense within a
+//! session, such as `serial record` or `source`, have no subcommand.
+
+use crate::config::scope::OVERRIDE_FLAG;
+use crate::error::exit_code;
+use crate::output::formatter::print_error;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use super::cmd_handler::COMMANDS;
+use super::registry::{self, Invocation};
+
+#[derive(Parser)]
+#[command(name = "secot-cli", version, about = "Secure Command Tool for IoT Security Testing")]
+pub struct Cli {
+    /// Configuration file
+    #[arg(short, long, global = true, default_value = "config.json")]
+    pub config: String,
+
+    /// Serial port of the SECoT device, instead of auto-detecting it
+    #[arg(short, long, visible_alias = "device", global = true)]
+    pub port: Option<String>,
+
+    /// Baud rate for --port [default: serial.baud_rate from the config]
+    #[arg(long, global = true)]
+    pub baud: Option<u32>,
+
+    /// Output format [default: output.default_format from the config]
+    #[arg(short, long, global = true, value_parser = ["table", "json"])]
+    pub format: Option<String>,
+
+    /// Start the local MQTT broker, also for a single command
+    #[arg(long, global = true, conflicts_with = "no_broker")]
+    pub broker: bool,
+
+    /// Don't start the local MQTT broker
+    #[arg(long, global = true)]
+    pub no_broker: bool,
+
+    /// Don't connect to a device at startup
+    #[arg(long, global = true)]
+    pub no_connect: bool,
+
+    /// Run the command even if the engagement scope refuses it (logged)
+    #[arg(long, global = true)]
+    pub override_scope: bool,
+
+    /// Run the REPL commands in a file, then exit
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<String>,
+
+    /// Set a script variable
+    #[arg(long = "var", value_name = "NAME=VALUE", requires = "script")]
+    pub vars: Vec<String>,
+
+    /// The subcommand with its arguments.
+    #[arg(skip)]
+    pub command: Option<Invocation>,
+}
+
+impl Cli {
+    /// Parse the arguments, exiting with a usage error if they don't make sense.
+    pub fn parse_args() -> Self {
+        let matches = Self::command().subcommands(registry::subcommands(COMMANDS)).get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+        match registry::invocation(COMMANDS, &matches) {
+            Some(Ok(mut invocation)) => {
+                if cli.override_scope {
+                    invocation.line.push(' ');
+                    invocation.line.push_str(OVERRIDE_FLAG);
+                    invocation.allow_override = true;
+                }
+                cli.command = Some(invocation);
+            }
+            Some(Err(e)) => {
+                print_error(&format!("Error: {}", e));
+                std::process::exit(exit_code::USAGE.into());
+            }
+            None => {}
+        }
+        if cli.script.is_some() && cli.command.is_some() {
+            Self::command()
+                .error(ErrorKind::ArgumentConflict, "--script cannot be used with a subcommand")
+                .exit();
+        }
+        cli
+    }
+}
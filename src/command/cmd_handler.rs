@@ -1,141 +1,895 @@
 use anyhow::{anyhow, Result};
-use super::scan_ports::run_port_scan;
-use super::scan_networks::run_network_scan;
+use crate::outln;
+use crate::audit::{self, AuditAction, AuditRecord};
+use crate::error::CliError;
+use super::registry::{self, ArgKind, ArgSpec, Args, CommandSpec, Ctx, DeviceUse, Group, Invocation, Scans};
+use super::scan_ports::{run_port_scan, ScanOptions, Timing, Transport, TIMINGS};
+use super::scan_udp::udp_ports;
+use super::scan_networks::{run_network_scan, DiscoveryOptions, DEFAULT_HOST_LIMIT, DISCOVERY_PORTS};
 use super::broker_test::run_broker_test;
-use crate::serial::serial_commands::SerialCommands;
+use super::jobs::Jobs;
+use super::script::{parse_vars, run_script};
+use crate::config::scope::{Scope, ScopeGuard, Target, OVERRIDE_FLAG};
+use crate::models::attack::{parse_duration, AttackKind, TargetKind};
+use crate::models::port::{parse_port_spec, COMMON_PORTS};
+use crate::serial::params::ParamType;
+use crate::serial::registry::{DeviceRegistry, ALL_DEVICES};
+use crate::serial::scan_results::WifiSort;
+use crate::serial::serial_commands::{SerialCommands, SCAN_IDLE_TIMEOUT};
+use crate::serial::serial_connection::DEFAULT_RESPONSE_TIMEOUT;
 use crate::output::formatter::{print_info, print_error, print_success, print_section};
+use futures::future::BoxFuture;
 use std::sync::Arc;
+use std::time::Duration;
 
+const SORT_COLUMNS: &[&str] = &["rssi", "channel", "encryption", "ssid"];
+const OUTPUT_FORMATS: &[&str] = &["table", "json"];
+
+/// Every REPL command, in the order `help` lists them.
+pub static COMMANDS: &[CommandSpec] = &[
+    // Network commands
+    CommandSpec::new(&["scan", "ports"], "Scan ports on a device", scan_ports)
+        .args(&[
+            ArgSpec::required("ip", ArgKind::Host, "Host to scan"),
+            ArgSpec::flag("udp", "Send UDP service probes instead of connecting over TCP"),
+            ArgSpec::keyword("ports", ArgKind::Ports, "Ports to scan, e.g. 1-1024,1883,top100 [default: common, or the probed UDP ports]"),
+            ArgSpec::keyword("timing", ArgKind::Choice(TIMINGS), "Timing profile [default: normal]"),
+            ArgSpec::keyword("timeout", ArgKind::Millis, "Connect timeout in milliseconds"),
+            ArgSpec::keyword("retries", ArgKind::Count, "Further connects after one gets no answer"),
+            ArgSpec::keyword("parallel", ArgKind::Count, "Connects in flight at once"),
+        ])
+        .details("A port spec joins ports, ranges, top<N> (the N most common TCP ports, up to 100), common and all with commas.\nTiming profiles: polite runs 4 connects at a time with a 3 s timeout and 2 retries, spaced 100 ms apart;\nnormal runs 100 with a 1 s timeout and 1 retry; aggressive runs 500 with a 300 ms timeout and no retries.\ntimeout, retries and parallel override the profile.\nOpen TCP ports are fingerprinted from their banner, an HTTP HEAD or an MQTT CONNECT.\nudp probes DNS 53, SNMP 161, MQTT-SN 1883, SSDP 1900, mDNS 5353 and CoAP 5683 and sends other ports an empty datagram.\nA UDP port that replies is open, one that draws an ICMP port unreachable is closed and a silent one is open|filtered.")
+        .group(Group::Network)
+        .scans(Scans::Arg("ip")),
+    CommandSpec::new(&["scan", "network"], "Scan local network for devices", scan_network)
+        .args(&[
+            ArgSpec::required("cidr", ArgKind::Network, "Network to sweep, e.g. 192.168.1.0/24"),
+            ArgSpec::keyword("ports", ArgKind::Ports, "TCP ports to look for hosts on [default: 22,23,80,443,1883,8080]"),
+            ArgSpec::keyword("timing", ArgKind::Choice(TIMINGS), "Timing profile [default: normal]"),
+            ArgSpec::keyword("timeout", ArgKind::Millis, "Wait for an answer in milliseconds"),
+            ArgSpec::keyword("retries", ArgKind::Count, "Further tries at a silent host"),
+            ArgSpec::keyword("parallel", ArgKind::Count, "Probes in flight at once"),
+            ArgSpec::keyword("limit", ArgKind::Count, "Most addresses to sweep [default: 65536]"),
+        ])
+        .details("Hosts are sent an ICMP echo request where the system allows ICMP sockets, then the silent ones\nare connected to on each of the ports; a connect that is accepted or refused shows a host.\nTiming profiles and overrides work as for scan ports.")
+        .group(Group::Network)
+        .scans(Scans::Arg("cidr")),
+    CommandSpec::new(&["broker", "test"], "Test MQTT broker accessibility", broker_test)
+        .args(&[ArgSpec::required("ip", ArgKind::Host, "Broker address")])
+        .group(Group::Network)
+        .scans(Scans::Arg("ip")),
+
+    // Serial port commands
+    CommandSpec::new(&["serial", "list"], "List available serial ports", serial_list)
+        .group(Group::Serial)
+        .device(DeviceUse::Any),
+    CommandSpec::new(&["serial", "connect"], "Connect to a serial port", serial_connect)
+        .args(&[
+            ArgSpec::required("port", ArgKind::SerialPort, "Serial port of the SECoT device"),
+            ArgSpec::optional("baud", ArgKind::Baud, "Baud rate [default: 115200]"),
+        ])
+        .group(Group::Serial)
+        .device(DeviceUse::Any),
+    CommandSpec::new(&["serial", "disconnect"], "Disconnect from serial port", serial_disconnect)
+        .group(Group::Serial)
+        .device(DeviceUse::Any),
+    CommandSpec::new(&["serial", "status"], "Show serial link health", serial_status)
+        .group(Group::Serial)
+        .device(DeviceUse::Connected),
+    CommandSpec::new(&["serial", "record"], "Record serial traffic to a file", serial_record)
+        .args(&[ArgSpec::required("file", ArgKind::File, "Recording to write")])
+        .group(Group::Serial)
+        .device(DeviceUse::Connected)
+        .shell_only(),
+    CommandSpec::new(&["serial", "record", "stop"], "Stop recording serial traffic", serial_record_stop)
+        .group(Group::Serial)
+        .device(DeviceUse::Connected)
+        .shell_only(),
+    CommandSpec::new(&["serial", "replay"], "Replay a recording as the device", serial_replay)
+        .args(&[
+            ArgSpec::required("file", ArgKind::File, "Recording to replay"),
+            ArgSpec::flag("realtime", "Keep the recorded timing"),
+        ])
+        .group(Group::Serial)
+        .device(DeviceUse::Any)
+        .shell_only(),
+
+    // SECoT commands via serial
+    CommandSpec::new(&["secot", "scan", "wifi"], "Scan for WiFi networks", secot_scan_wifi)
+        .args(&[ArgSpec::keyword("sort", ArgKind::Choice(SORT_COLUMNS), "Order by rssi, channel, encryption or ssid")])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .scans(Scans::Device)
+        .fan_out(scan_wifi_fan_out),
+    CommandSpec::new(&["secot", "scan", "mqtt"], "Scan for MQTT brokers", secot_scan_mqtt)
+        .args(&[ArgSpec::flag("verify", "Re-test every broker from this host")])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .scans(Scans::Device)
+        .fan_out(scan_mqtt_fan_out),
+    CommandSpec::new(&["secot", "attack"], "Start an attack using SECoT", secot_attack)
+        .args(&[
+            ArgSpec::required("attack", ArgKind::Attack, "Attack to start"),
+            ArgSpec::optional("duration", ArgKind::Duration, "How long to run: 90, 30s, 5m or 1h").long(),
+        ])
+        .details("Defaults to and is capped at safety.max_attack_secs.\nChecked against the engagement scope with the target parameters set on the device.")
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .check(authorize_attack),
+    CommandSpec::new(&["secot", "stop"], "Stop an attack using SECoT", secot_stop)
+        .args(&[ArgSpec::optional("attack", ArgKind::Attack, "Attack to stop [default: all]")])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected),
+    CommandSpec::new(&["panic"], "Stop every attack on every connected device now", panic)
+        .aliases(&["stopall"])
+        .group(Group::Secot)
+        .device(DeviceUse::Registry),
+    CommandSpec::new(&["secot", "status"], "Show status of SECoT", secot_status)
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .fan_out(status_fan_out),
+    CommandSpec::new(&["secot", "set"], "Set attack parameter", secot_set)
+        .args(&[
+            ArgSpec::required("attack", ArgKind::Attack, "Attack the parameter belongs to"),
+            ArgSpec::required("param", ArgKind::Param, "Parameter to set"),
+            ArgSpec::required("value", ArgKind::Value, "New value"),
+        ])
+        .details("Setting a target parameter is checked against the engagement scope.")
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .check(authorize_set),
+    CommandSpec::new(&["secot", "get"], "Get attack parameter", secot_get)
+        .args(&[
+            ArgSpec::required("attack", ArgKind::Attack, "Attack the parameter belongs to"),
+            ArgSpec::required("param", ArgKind::Param, "Parameter to read"),
+        ])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected)
+        .fan_out(get_fan_out),
+    CommandSpec::new(&["secot", "params"], "List an attack's parameters, types and ranges", secot_params)
+        .args(&[ArgSpec::required("attack", ArgKind::Attack, "Attack to describe")])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected),
+    CommandSpec::new(&["secot", "events"], "Show recent device events", secot_events)
+        .args(&[ArgSpec::default("count", ArgKind::Count, "20", "How many events to show")])
+        .group(Group::Secot)
+        .device(DeviceUse::Connected),
+    CommandSpec::new(&["secot", "events", "follow"], "Tail device events until Ctrl-C", secot_events_follow)
+        .group(Group::Secot)
+        .device(DeviceUse::Connected),
+
+    // Device commands
+    CommandSpec::new(&["device", "list"], "List registered SECoT devices", device_list)
+        .group(Group::Device)
+        .device(DeviceUse::Registry),
+    CommandSpec::new(&["device", "add"], "Register (and connect) a device", device_add)
+        .args(&[
+            ArgSpec::required("name", ArgKind::Word, "Name to address the device by"),
+            ArgSpec::optional("port", ArgKind::SerialPort, "Serial port to connect it on"),
+            ArgSpec::default("baud", ArgKind::Baud, "115200", "Baud rate"),
+        ])
+        .group(Group::Device)
+        .device(DeviceUse::Registry),
+    CommandSpec::new(&["device", "use"], "Send commands to <name> by default", device_use)
+        .args(&[ArgSpec::required("name", ArgKind::Device, "Registered device")])
+        .group(Group::Device)
+        .device(DeviceUse::Registry),
+    CommandSpec::new(&["device", "remove"], "Disconnect and forget a device", device_remove)
+        .args(&[ArgSpec::required("name", ArgKind::Device, "Registered device")])
+        .group(Group::Device)
+        .device(DeviceUse::Registry),
+
+    // Audit commands
+    CommandSpec::new(&["audit", "verify"], "Check the audit log's hash chain for tampering", audit_verify)
+        .args(&[ArgSpec::optional("file", ArgKind::File, "Audit log [default: this session's]")])
+        .group(Group::Audit),
+
+    // Scripts
+    CommandSpec::new(&["source"], "Run the REPL commands in a file", source)
+        .args(&[
+            ArgSpec::required("file", ArgKind::File, "Script to run"),
+            ArgSpec::rest("vars", ArgKind::Word, "Variables to set, as name=value"),
+        ])
+        .group(Group::Scripts)
+        .shell_only(),
+
+    // Background jobs
+    CommandSpec::new(&["jobs"], "List background jobs", jobs)
+        .group(Group::Jobs)
+        .shell_only(),
+    CommandSpec::new(&["fg"], "Show a job's output, waiting for it if it still runs", fg)
+        .args(&[ArgSpec::required("id", ArgKind::Job, "Job to show")])
+        .details("Ctrl-C while waiting cancels the job.")
+        .group(Group::Jobs)
+        .shell_only(),
+    CommandSpec::new(&["cancel"], "Cancel a background job", cancel)
+        .args(&[ArgSpec::required("id", ArgKind::Job, "Job to cancel")])
+        .group(Group::Jobs)
+        .shell_only(),
+
+    // General commands
+    CommandSpec::new(&["help"], "List commands, or describe one", help)
+        .args(&[ArgSpec::rest("command", ArgKind::Word, "Command to describe")])
+        .shell_only(),
+    CommandSpec::new(&["set", "output"], "Set output format to table/json", set_output)
+        .args(&[ArgSpec::required("format", ArgKind::Choice(OUTPUT_FORMATS), "Output format")])
+        .shell_only(),
+    CommandSpec::new(&["exit"], "Exit the tool", exit).shell_only(),
+];
+
+/// Run one REPL command.
+///
+/// A leading `@name` sends the command to that device instead of the active
+/// one; `@all` sends it to every device. Active operations are checked against
+/// `scope` unless the command carries `--override-scope`.
+///
+/// A trailing `&` runs the command as a background job in `jobs` instead.
+///
+/// The command, its result and any scan it starts are recorded in the audit log.
 pub async fn handle_command(
+    cmd: &str,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope
+) -> Result<()> {
+    if let Some(background) = cmd.trim_end().strip_suffix('&') {
+        return spawn_job(background.trim(), output_format, devices, jobs, scope);
+    }
+
+    let mut parts: Vec<&str> = cmd.split_whitespace().collect();
+    let allow_override = parts.contains(&OVERRIDE_FLAG);
+    parts.retain(|part| *part != OVERRIDE_FLAG);
+    if parts.is_empty() {
+        return Ok(());
+    }
+    let scope = scope.guard(cmd, allow_override);
+
+    let device = match parts.first() {
+        Some(target) if target.starts_with('@') => target[1..].to_string(),
+        _ => devices.active_name(),
+    };
+
+    let result = dispatch_command(cmd, &parts, &device, output_format, devices, jobs, &scope).await;
+    record_command(devices, cmd, &device, &result);
+    result
+}
+
+/// Run a command parsed from the command line on the active device.
+pub async fn handle_invocation(
+    invocation: Invocation,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope
+) -> Result<()> {
+    let Invocation { spec, args, line, allow_override } = invocation;
+    let scope = scope.guard(&line, allow_override);
+    let device = devices.active_name();
+
+    let result = run_parsed(&line, spec, args, None, &device, output_format, devices, jobs, &scope).await;
+    record_command(devices, &line, &device, &result);
+    result
+}
+
+fn record_command(devices: &DeviceRegistry, cmd: &str, device: &str, result: &Result<()>) {
+    let outcome = match result {
+        Ok(()) => "ok".to_string(),
+        Err(e) => format!("error: {}", e),
+    };
+    devices.audit().record(AuditRecord::new(AuditAction::Command, cmd).target(device).result(outcome));
+}
+
+/// Start `cmd` as a background job with its own copy of `output_format`.
+fn spawn_job(
     cmd: &str,
     output_format: &str,
-    serial_commands: &Arc<SerialCommands>
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope
 ) -> Result<()> {
-    let parts: Vec<&str> = cmd.trim().split_whitespace().collect();
-
-    match parts.as_slice() {
-        ["help"] => {
-            print_section("Available Commands");
-
-            print_section("Network Commands");
-            println!("  scan ports <ip>              - Scan ports on a device");
-            println!("  scan network <cidr>          - Scan local network for devices");
-            println!("  broker test <ip>             - Test MQTT broker accessibility");
-
-            print_section("Serial Port Commands");
-            println!("  serial list                  - List available serial ports");
-            println!("  serial connect <port> [baud] - Connect to a serial port");
-            println!("  serial disconnect            - Disconnect from serial port");
-            println!("  serial status                - Show serial connection status");
-
-            print_section("SECoT Commands");
-            println!("  secot scan wifi              - Scan for WiFi networks using SECoT");
-            println!("  secot scan mqtt              - Scan for MQTT brokers using SECoT");
-            println!("  secot attack <type> [dur]    - Start an attack using SECoT");
-            println!("  secot stop [type]            - Stop an attack using SECoT");
-            println!("  secot status                 - Show status of SECoT");
-            println!("  secot set <attack> <param> <value> - Set attack parameter");
-            println!("  secot get <attack> <param>   - Get attack parameter");
-
-            print_section("General Commands");
-            println!("  set output <fmt>             - Set output format to table/json");
-            println!("  exit                         - Exit the tool");
-        },
-        ["scan", "ports", ip] => {
-            print_info(&format!("Scanning ports on {}...", ip));
-            run_port_scan(ip, output_format).await?;
-        },
-        ["scan", "network", cidr] => {
-            print_info(&format!("Scanning network {}...", cidr));
-            run_network_scan(cidr, output_format).await?;
-        },
-        ["broker", "test", ip] => {
-            print_info(&format!("Testing MQTT broker at {}...", ip));
-            run_broker_test(ip, output_format).await?;
-        },
-
-        // Serial port commands
-        ["serial", "list"] => {
-            print_info("Listing available serial ports...");
-            serial_commands.list_ports(output_format).await?;
-        },
-        ["serial", "connect", port] => {
-            print_info(&format!("Connecting to {}...", port));
-            match serial_commands.connect_to_port(port, 115200).await {
-                Ok(_) => print_success(&format!("Connected to {}", port)),
-                Err(e) => print_error(&format!("Failed to connect: {}", e)),
-            }
-        },
-        ["serial", "connect", port, baud] => {
-            let baud_rate = baud.parse::<u32>().map_err(|_| anyhow!("Invalid baud rate"))?;
-            print_info(&format!("Connecting to {} at {} baud...", port, baud_rate));
-            match serial_commands.connect_to_port(port, baud_rate).await {
-                Ok(_) => print_success(&format!("Connected to {} at {} baud", port, baud_rate)),
-                Err(e) => print_error(&format!("Failed to connect: {}", e)),
-            }
-        },
-        ["serial", "disconnect"] => {
-            print_info("Disconnecting from serial port...");
-            match serial_commands.disconnect().await {
-                Ok(_) => print_success("Disconnected from serial port"),
-                Err(e) => print_error(&format!("Failed to disconnect: {}", e)),
-            }
-        },
-        ["serial", "status"] => {
-            if let Ok(true) = serial_commands.is_connected().await {
-                if let Ok(port_name) = serial_commands.get_port_name().await {
-                    print_success(&format!("Connected to {}", port_name));
+    if cmd.is_empty() {
+        return Err(CliError::Usage("Missing command before '&'".to_string()).into());
+    }
+
+    let line = cmd.to_string();
+    let mut output_format = output_format.to_string();
+    let (devices, job_table, scope) = (devices.clone(), jobs.clone(), scope.clone());
+    let id = jobs.spawn(cmd, Box::pin(async move {
+        handle_command(&line, &mut output_format, &devices, &job_table, &scope).await
+    }))?;
+
+    print_info(&format!("[{}] {}", id, cmd));
+    Ok(())
+}
+
+async fn dispatch_command(
+    cmd: &str,
+    parts: &[&str],
+    device: &str,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &ScopeGuard<'_>
+) -> Result<()> {
+    let (target, words) = match parts {
+        [target, rest @ ..] if target.starts_with('@') => (Some(&target[1..]), rest),
+        _ => (None, parts),
+    };
+    if words.is_empty() {
+        return Err(anyhow!("Missing command after {}", parts[0]));
+    }
+
+    let (spec, len) = registry::find(COMMANDS, words)?;
+    let args = spec.parse_args(&words[len..])?;
+    run_parsed(cmd, spec, args, target, device, output_format, devices, jobs, scope).await
+}
+
+/// Run `spec` on the device `target` names, or on `device` without one.
+#[allow(clippy::too_many_arguments)]
+async fn run_parsed(
+    cmd: &str,
+    spec: &CommandSpec,
+    args: Args,
+    target: Option<&str>,
+    device: &str,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &ScopeGuard<'_>
+) -> Result<()> {
+    if let Some(scans) = spec.scans {
+        let scanned = match scans {
+            Scans::Arg(name) => args.value(name),
+            Scans::Device => device,
+        };
+        devices.audit().record(AuditRecord::new(AuditAction::Scan, cmd).target(scanned));
+    }
+
+    match target {
+        Some(name) if !spec.addressable() => Err(CliError::Usage(format!(
+            "'{}' doesn't run on a single device; leave out @{}",
+            spec.name(),
+            name
+        ))
+        .into()),
+        Some(ALL_DEVICES) => run_on_all_devices(spec, args, output_format, devices, jobs, scope).await,
+        Some(name) => run_spec(spec, args, output_format, devices, jobs, devices.get(name)?, scope).await,
+        None => run_spec(spec, args, output_format, devices, jobs, devices.active()?, scope).await,
+    }
+}
+
+/// Run `spec` against `device`, once the scope allows it.
+async fn run_spec(
+    spec: &CommandSpec,
+    args: Args,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    device: Arc<SerialCommands>,
+    scope: &ScopeGuard<'_>
+) -> Result<()> {
+    if let Some(check) = spec.check {
+        check(&args, &device, scope).await?;
+    }
+
+    (spec.handler)(Ctx { args, output_format, devices, jobs, device, scope }).await
+}
+
+/// Run a command on every device.
+///
+/// Device commands are sent to all boards at once and their answers shown
+/// side by side; anything else runs on each device in turn.
+async fn run_on_all_devices(
+    spec: &CommandSpec,
+    args: Args,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &ScopeGuard<'_>
+) -> Result<()> {
+    if let Some(fan_out) = spec.fan_out {
+        let mut advertised = Vec::new();
+        for (_, device) in devices.all() {
+            advertised.extend(advertised_attacks(&device).await);
+        }
+
+        if let Some((command, idle_timeout)) = fan_out(&args, &advertised)? {
+            if let Some(check) = spec.check {
+                for (_, device) in devices.all() {
+                    check(&args, &device, scope).await?;
                 }
-            } else {
-                print_info("Not connected to any serial port");
             }
-        },
-
-        // SECoT commands via serial
-        ["secot", "scan", "wifi"] => {
-            print_info("Scanning for WiFi networks using SECoT...");
-            serial_commands.scan_wifi(output_format).await?;
-        },
-        ["secot", "scan", "mqtt"] => {
-            print_info("Scanning for MQTT brokers using SECoT...");
-            serial_commands.scan_mqtt(output_format).await?;
-        },
-        ["secot", "attack", attack_type] => {
-            print_info(&format!("Starting {} attack...", attack_type));
-            serial_commands.start_attack(attack_type, None).await?;
-        },
-        ["secot", "attack", attack_type, duration] => {
-            let dur = duration.parse::<u32>().map_err(|_| anyhow!("Invalid duration"))?;
-            print_info(&format!("Starting {} attack for {} seconds...", attack_type, dur));
-            serial_commands.start_attack(attack_type, Some(dur)).await?;
-        },
-        ["secot", "stop"] => {
-            print_info("Stopping all attacks...");
-            serial_commands.stop_attack(None).await?;
-        },
-        ["secot", "stop", attack_type] => {
-            print_info(&format!("Stopping {} attack...", attack_type));
-            serial_commands.stop_attack(Some(attack_type)).await?;
-        },
-        ["secot", "status"] => {
-            print_info("Getting SECoT status...");
-            serial_commands.get_status().await?;
-        },
-        ["secot", "set", attack, param, value] => {
-            print_info(&format!("Setting {} parameter {} to {}...", attack, param, value));
-            serial_commands.set_parameter(attack, param, value).await?;
-        },
-        ["secot", "get", attack, param] => {
-            print_info(&format!("Getting {} parameter {}...", attack, param));
-            serial_commands.get_parameter(attack, param).await?;
-        },
-
-        _ => {
-            print_error("Unknown or malformed command");
-            println!("Type 'help' for a list of available commands");
-            return Err(anyhow!("Unknown or malformed command"));
-        },
+            return devices.fan_out(&command, idle_timeout, output_format).await;
+        }
+    }
+
+    let all = devices.all();
+    let mut failed = 0;
+    for (name, device) in &all {
+        print_section(&format!("@{}", name));
+        if let Err(e) = run_spec(spec, args.clone(), output_format, devices, jobs, device.clone(), scope).await {
+            print_error(&format!("Error: {}", e));
+            failed += 1;
+        }
     }
 
+    if failed > 0 {
+        return Err(anyhow!("{} of {} devices failed", failed, all.len()));
+    }
     Ok(())
 }
+
+/// Stop attacks on every device, reporting each one that was stopped.
+///
+/// With `force`, every connected device is told to stop; otherwise only
+/// those this session started attacks on.
+pub async fn stop_all_attacks(devices: &DeviceRegistry, force: bool) {
+    let mut stopped = 0;
+    for (name, result) in devices.stop_all(force).await {
+        match result {
+            Ok(true) => {
+                stopped += 1;
+                print_success(&format!("Stopped all attacks on '{}'", name));
+            },
+            Ok(false) => {},
+            Err(e) => print_error(&format!("Could not stop attacks on '{}': {}", name, e)),
+        }
+    }
+    if force && stopped == 0 {
+        print_info("No connected device to stop");
+    }
+}
+
+/// Attacks the firmware on `serial_commands` says it supports.
+async fn advertised_attacks(serial_commands: &SerialCommands) -> Vec<String> {
+    serial_commands
+        .firmware()
+        .await
+        .map(|firmware| firmware.attacks)
+        .unwrap_or_default()
+}
+
+/// Parameters of `attack` that name a target.
+///
+/// Attacks this tool doesn't know are judged by their parameter types.
+async fn target_params(serial_commands: &SerialCommands, attack: &str) -> Vec<(String, TargetKind)> {
+    if let Some(kind) = AttackKind::known(attack) {
+        return kind
+            .target_params()
+            .iter()
+            .map(|(param, target)| (param.to_string(), *target))
+            .collect();
+    }
+
+    let Ok(schema) = serial_commands.param_schema(attack).await else {
+        return Vec::new();
+    };
+    schema
+        .params
+        .iter()
+        .filter_map(|spec| match spec.kind {
+            ParamType::Mac => Some((spec.name.clone(), TargetKind::Bssid)),
+            ParamType::Ip => Some((spec.name.clone(), TargetKind::Host)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check `secot attack` against the engagement scope, with the target
+/// parameters currently set on the device.
+fn authorize_attack<'a>(args: &'a Args, serial_commands: &'a SerialCommands, scope: &'a ScopeGuard<'a>) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        if !scope.is_restricted() {
+            return Ok(());
+        }
+
+        let attack = AttackKind::parse(args.value("attack"), &advertised_attacks(serial_commands).await)?;
+        let action = format!("start {}", attack);
+        let mut targets = Vec::new();
+        let mut unreadable = Vec::new();
+        for (param, kind) in target_params(serial_commands, attack.name()).await {
+            match serial_commands.param_value(attack.name(), &param).await {
+                Some(value) => targets.extend(Target::from_param(kind, &value)),
+                None => unreadable.push(format!("{}.{}", attack, param)),
+            }
+        }
+        if !unreadable.is_empty() {
+            scope.refuse(&action, &format!("could not read {} from the device", unreadable.join(", ")))?;
+        }
+        scope.authorize(&action, &targets)
+    })
+}
+
+/// Check a `secot set` of a target parameter against the engagement scope.
+fn authorize_set<'a>(args: &'a Args, serial_commands: &'a SerialCommands, scope: &'a ScopeGuard<'a>) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        if !scope.is_restricted() {
+            return Ok(());
+        }
+
+        let (attack, param, value) = (args.value("attack"), args.value("param"), args.value("value"));
+        let kind = target_params(serial_commands, attack)
+            .await
+            .into_iter()
+            .find(|(name, _)| name == param)
+            .map(|(_, kind)| kind);
+        match kind.and_then(|kind| Target::from_param(kind, value)) {
+            Some(target) => scope.authorize(&format!("aim {} at {}", attack, value), &[target]),
+            None => Ok(()),
+        }
+    })
+}
+
+fn scan_wifi_fan_out(args: &Args, _: &[String]) -> Result<Option<(String, Duration)>> {
+    Ok(args.get("sort").is_none().then(|| ("scan wifi".to_string(), SCAN_IDLE_TIMEOUT)))
+}
+
+fn scan_mqtt_fan_out(args: &Args, _: &[String]) -> Result<Option<(String, Duration)>> {
+    Ok((!args.flag("verify")).then(|| ("scan mqtt".to_string(), SCAN_IDLE_TIMEOUT)))
+}
+
+fn status_fan_out(_: &Args, _: &[String]) -> Result<Option<(String, Duration)>> {
+    Ok(Some(("status".to_string(), DEFAULT_RESPONSE_TIMEOUT)))
+}
+
+fn get_fan_out(args: &Args, _: &[String]) -> Result<Option<(String, Duration)>> {
+    let command = format!("get {} {}", args.value("attack"), args.value("param"));
+    Ok(Some((command, DEFAULT_RESPONSE_TIMEOUT)))
+}
+
+fn scan_ports(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let ip = ctx.args.value("ip");
+        print_info(&format!("Scanning ports on {}...", ip));
+        let transport = if ctx.args.flag("udp") { Transport::Udp } else { Transport::Tcp };
+        let ports = match (ctx.args.get("ports"), transport) {
+            (Some(spec), _) => parse_port_spec(spec)?,
+            (None, Transport::Tcp) => COMMON_PORTS.to_vec(),
+            (None, Transport::Udp) => udp_ports(),
+        };
+
+        let options = scan_options(&ctx.args)?;
+        let results = run_port_scan(ip, &ports, transport, options, ctx.output_format, ctx.scope).await?;
+        if results.results.iter().any(|port| port.status == "open") {
+            ctx.devices.remember_hosts([results.ip.0]);
+        }
+        Ok(())
+    })
+}
+
+/// The timing profile of a scan with the `timeout`, `retries` and `parallel` given over it.
+fn scan_options(args: &Args) -> Result<ScanOptions> {
+    let mut options = ScanOptions::from(args.parse::<Timing>("timing")?.unwrap_or_default());
+    if let Some(ms) = args.parse::<u64>("timeout")? {
+        options.timeout = Duration::from_millis(ms);
+    }
+    if let Some(retries) = args.parse::<u32>("retries")? {
+        options.retries = retries;
+    }
+    if let Some(parallel) = args.parse::<usize>("parallel")? {
+        if parallel == 0 {
+            return Err(CliError::Usage("parallel must be at least 1".to_string()).into());
+        }
+        options.concurrency = parallel;
+    }
+    Ok(options)
+}
+
+fn scan_network(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let cidr = ctx.args.value("cidr");
+        print_info(&format!("Scanning network {}...", cidr));
+        let options = DiscoveryOptions {
+            scan: scan_options(&ctx.args)?,
+            ports: match ctx.args.get("ports") {
+                Some(spec) => parse_port_spec(spec)?,
+                None => DISCOVERY_PORTS.to_vec(),
+            },
+            limit: ctx.args.parse::<usize>("limit")?.unwrap_or(DEFAULT_HOST_LIMIT),
+        };
+        let found = run_network_scan(cidr, &options, ctx.output_format, ctx.scope).await?;
+        ctx.devices.remember_hosts(found.iter().map(|device| device.ip));
+        Ok(())
+    })
+}
+
+fn broker_test(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let ip = ctx.args.value("ip");
+        print_info(&format!("Testing MQTT broker at {}...", ip));
+        run_broker_test(ip, ctx.output_format, ctx.scope).await
+    })
+}
+
+fn serial_list(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        print_info("Listing available serial ports...");
+        ctx.device.list_ports(ctx.output_format).await
+    })
+}
+
+fn serial_connect(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let port = ctx.args.value("port");
+        let baud_rate = ctx.args.parse::<u32>("baud")?;
+        match baud_rate {
+            Some(baud_rate) => print_info(&format!("Connecting to {} at {} baud...", port, baud_rate)),
+            None => print_info(&format!("Connecting to {}...", port)),
+        }
+        ctx.device
+            .connect_to_port(port, baud_rate.unwrap_or(115200))
+            .await
+            .map_err(|e| CliError::NotConnected(format!("Failed to connect: {}", e)))?;
+        match baud_rate {
+            Some(baud_rate) => print_success(&format!("Connected to {} at {} baud", port, baud_rate)),
+            None => print_success(&format!("Connected to {}", port)),
+        }
+        Ok(())
+    })
+}
+
+fn serial_disconnect(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        print_info("Disconnecting from serial port...");
+        ctx.device
+            .disconnect()
+            .await
+            .map_err(|e| anyhow!("Failed to disconnect: {}", e))?;
+        print_success("Disconnected from serial port");
+        Ok(())
+    })
+}
+
+fn serial_status(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move { ctx.device.show_status(ctx.output_format).await })
+}
+
+fn serial_record(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let path = ctx.args.value("file");
+        ctx.device.start_recording(path).await?;
+        print_success(&format!("Recording serial traffic to {}", path));
+        Ok(())
+    })
+}
+
+fn serial_record_stop(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let (path, chunks) = ctx.device.stop_recording().await?;
+        print_success(&format!("Saved {} chunks to {}", chunks, path));
+        Ok(())
+    })
+}
+
+fn serial_replay(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let path = ctx.args.value("file");
+        let realtime = ctx.args.flag("realtime");
+        ctx.device.replay(path, realtime).await?;
+        if realtime {
+            print_success(&format!("Replaying {} in real time", path));
+        } else {
+            print_success(&format!("Replaying {}", path));
+        }
+        Ok(())
+    })
+}
+
+fn secot_scan_wifi(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let sort = ctx.args.parse::<WifiSort>("sort")?.unwrap_or_default();
+        print_info("Scanning for WiFi networks using SECoT...");
+        ctx.device.scan_wifi(ctx.output_format, sort).await?;
+        Ok(())
+    })
+}
+
+fn secot_scan_mqtt(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        print_info("Scanning for MQTT brokers using SECoT...");
+        let brokers = ctx.device.scan_mqtt(ctx.output_format, ctx.args.flag("verify")).await?;
+        ctx.devices.remember_hosts(brokers.iter().map(|checked| checked.broker.ip));
+        Ok(())
+    })
+}
+
+fn secot_attack(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let attack = AttackKind::parse(ctx.args.value("attack"), &advertised_attacks(&ctx.device).await)?;
+        let duration = ctx.args.get("duration").map(parse_duration).transpose()?;
+        match duration {
+            Some(duration) => print_info(&format!("Starting {} attack for {} seconds...", attack, duration.as_secs())),
+            None => print_info(&format!("Starting {} attack...", attack)),
+        }
+        ctx.device.start_attack(&attack, duration).await
+    })
+}
+
+fn secot_stop(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        match ctx.args.get("attack") {
+            Some(attack) => {
+                let attack = AttackKind::parse(attack, &advertised_attacks(&ctx.device).await)?;
+                print_info(&format!("Stopping {} attack...", attack));
+                ctx.device.stop_attack(Some(&attack)).await
+            }
+            None => {
+                print_info("Stopping all attacks...");
+                ctx.device.stop_attack(None).await
+            }
+        }
+    })
+}
+
+fn panic(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        print_info("Stopping all attacks on every connected device...");
+        stop_all_attacks(ctx.devices, true).await;
+        Ok(())
+    })
+}
+
+fn secot_status(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        print_info("Getting SECoT status...");
+        ctx.device.get_status().await
+    })
+}
+
+fn secot_set(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let (attack, param, value) = (ctx.args.value("attack"), ctx.args.value("param"), ctx.args.value("value"));
+        print_info(&format!("Setting {} parameter {} to {}...", attack, param, value));
+        ctx.device.set_parameter(attack, param, value).await
+    })
+}
+
+fn secot_get(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let (attack, param) = (ctx.args.value("attack"), ctx.args.value("param"));
+        print_info(&format!("Getting {} parameter {}...", attack, param));
+        ctx.device.get_parameter(attack, param).await
+    })
+}
+
+fn secot_params(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move { ctx.device.show_params(ctx.args.value("attack"), ctx.output_format).await })
+}
+
+fn secot_events(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let count = ctx.args.parse::<usize>("count")?.unwrap_or_default();
+        ctx.device.show_events(count, ctx.output_format).await
+    })
+}
+
+fn secot_events_follow(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move { ctx.device.follow_events(ctx.output_format).await })
+}
+
+fn device_list(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move { ctx.devices.list(ctx.output_format).await })
+}
+
+fn device_add(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let name = ctx.args.value("name");
+        let device = ctx.devices.add(name)?;
+        let Some(port) = ctx.args.get("port") else {
+            print_success(&format!("Added device '{}'", name));
+            return Ok(());
+        };
+
+        let baud_rate = ctx.args.parse::<u32>("baud")?.unwrap_or(115200);
+        print_info(&format!("Connecting '{}' to {}...", name, port));
+        if let Err(e) = device.connect_to_port(port, baud_rate).await {
+            ctx.devices.remove(name)?;
+            return Err(anyhow!("Failed to connect: {}", e));
+        }
+        print_success(&format!("Added device '{}' on {}", name, port));
+        Ok(())
+    })
+}
+
+fn device_use(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let name = ctx.args.value("name");
+        ctx.devices.use_device(name)?;
+        print_success(&format!("Commands now go to '{}'", name));
+        Ok(())
+    })
+}
+
+fn device_remove(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let name = ctx.args.value("name");
+        let device = ctx.devices.remove(name)?;
+        device.disconnect().await?;
+        print_success(&format!("Removed device '{}'", name));
+        Ok(())
+    })
+}
+
+fn audit_verify(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let audit_log = ctx.devices.audit();
+        let path = match ctx.args.get("file") {
+            Some(path) => path.to_string(),
+            None if audit_log.is_enabled() => audit_log.path().to_string(),
+            None => return Err(anyhow!("Audit logging is off; use 'audit verify <file>'")),
+        };
+
+        let report = audit::verify(&path)?;
+        if ctx.output_format == "json" {
+            outln!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_success(&format!("Audit chain of {} is intact ({} entries)", report.path, report.entries));
+            print_info(&format!("Last hash: {}", report.last_hash));
+        }
+        Ok(())
+    })
+}
+
+fn source(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let vars = parse_vars(&ctx.args.words("vars"))?;
+        let summary = run_script(ctx.args.value("file"), &vars, ctx.output_format, ctx.devices, ctx.jobs, ctx.scope.scope()).await?;
+        summary.print(ctx.output_format)
+    })
+}
+
+fn jobs(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move { ctx.jobs.print_list(ctx.output_format) })
+}
+
+fn fg(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let id = ctx.args.parse::<usize>("id")?.unwrap_or_default();
+        ctx.jobs.wait(id).await
+    })
+}
+
+fn cancel(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let id = ctx.args.parse::<usize>("id")?.unwrap_or_default();
+        ctx.jobs.cancel(id)?;
+        print_success(&format!("Cancelled job {}", id));
+        Ok(())
+    })
+}
+
+fn help(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let topic = ctx.args.words("command");
+        if !topic.is_empty() {
+            return registry::print_command_help(COMMANDS, &topic);
+        }
+
+        registry::print_help(COMMANDS);
+        if let Some(firmware) = ctx.device.firmware().await {
+            if !firmware.attacks.is_empty() {
+                print_section("Firmware");
+                outln!("  Attacks supported by firmware {}: {}", firmware.firmware, firmware.attacks.join(", "));
+            }
+        }
+
+        print_section("Engagement Scope");
+        outln!("  scan, broker test, secot attack and targeting secot set are checked against");
+        outln!("  the scope in config.json. Append {} to run one anyway (logged).", OVERRIDE_FLAG);
+        Ok(())
+    })
+}
+
+fn set_output(ctx: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        *ctx.output_format = ctx.args.value("format").to_lowercase();
+        print_success(&format!("Output format set to '{}'", ctx.output_format));
+        Ok(())
+    })
+}
+
+/// The shell and scripts end on `exit` before it gets here.
+fn exit(_: Ctx<'_>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Ok(()) })
+}
@@ -0,0 +1,190 @@
+use crate::config::scope::OVERRIDE_FLAG;
+use super::cmd_handler::COMMANDS;
+use super::jobs::Jobs;
+use super::registry::{ArgKind, Args};
+use crate::serial::params::ParamType;
+use crate::serial::registry::{DeviceRegistry, ALL_DEVICES};
+use crate::serial::serial_connection::SerialConnection;
+use crate::serial::serial_commands::SerialCommands;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Tab completion for the REPL.
+///
+/// Completes command words, serial ports, device names, attack and
+/// parameter names, parameter values, files, job ids, and hosts found by
+/// earlier scans. Uses only what the device has already reported, so
+/// completing never has to talk to the device.
+pub struct ReplCompleter {
+    devices: Arc<DeviceRegistry>,
+    jobs: Arc<Jobs>,
+}
+
+impl ReplCompleter {
+    pub fn new(devices: Arc<DeviceRegistry>, jobs: Arc<Jobs>) -> Self {
+        Self { devices, jobs }
+    }
+
+    /// Completions for the word that ends `line`: the byte offset it starts
+    /// at and the sorted candidates that extend it.
+    pub fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let partial = &line[start..];
+        let mut words: Vec<&str> = line[..start].split_whitespace().collect();
+        words.retain(|word| *word != OVERRIDE_FLAG);
+
+        // `@name secot set ...` completes against that device
+        let target = match words.first() {
+            Some(first) if first.starts_with('@') => Some(&words.remove(0)[1..]),
+            _ => None,
+        };
+
+        let mut candidates = if partial.starts_with('@') && words.is_empty() && target.is_none() {
+            self.device_names(true).into_iter().map(|name| format!("@{}", name)).collect()
+        } else if partial.starts_with("--") {
+            vec![OVERRIDE_FLAG.to_string()]
+        } else {
+            self.candidates(target, &words, partial)
+        };
+
+        candidates.retain(|candidate| candidate.starts_with(partial));
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+
+    /// Candidates for the word after `words`.
+    fn candidates(&self, target: Option<&str>, words: &[&str], partial: &str) -> Vec<String> {
+        let mut candidates = Vec::new();
+        for spec in COMMANDS {
+            for path in spec.paths() {
+                if words.len() < path.len() {
+                    if path.iter().zip(words).all(|(expected, word)| expected == word) {
+                        candidates.push(path[words.len()].to_string());
+                    }
+                } else if words[..path.len()] == path[..] {
+                    let (next, names, args) = spec.next_word(&words[path.len()..]);
+                    candidates.extend(names.into_iter().map(String::from));
+                    if let Some(arg) = next {
+                        candidates.extend(self.argument(arg.kind, target, &args, partial));
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Values for an argument of `kind`; `args` are those typed before it.
+    fn argument(&self, kind: ArgKind, target: Option<&str>, args: &Args, partial: &str) -> Vec<String> {
+        match kind {
+            ArgKind::Choice(choices) => choices.iter().map(|choice| choice.to_string()).collect(),
+            ArgKind::Host => self.hosts(),
+            ArgKind::Network => self
+                .devices
+                .known_hosts()
+                .iter()
+                .filter(|host| host.is_ipv4())
+                .map(|host| {
+                    let octets: Vec<String> = host.to_string().split('.').take(3).map(String::from).collect();
+                    format!("{}.0/24", octets.join("."))
+                })
+                .collect(),
+            // Pseudo-terminals such as the simulator's aren't listed, so paths complete too
+            ArgKind::SerialPort if partial.contains('/') => complete_path(partial),
+            ArgKind::SerialPort => SerialConnection::list_available_ports()
+                .map(|ports| ports.into_iter().map(|port| port.port_name).collect())
+                .unwrap_or_default(),
+            ArgKind::Baud => ["9600", "57600", "115200", "230400", "460800", "921600"].map(String::from).to_vec(),
+            ArgKind::Device => self.device_names(false),
+            ArgKind::File => complete_path(partial),
+            ArgKind::Attack => self.device(target).map(|device| device.known_attacks()).unwrap_or_default(),
+            ArgKind::Param => match (self.device(target), args.get("attack")) {
+                (Some(device), Some(attack)) => device.known_params(attack),
+                _ => Vec::new(),
+            },
+            ArgKind::Value => match (self.device(target), args.get("attack"), args.get("param")) {
+                (Some(device), Some(attack), Some(param)) => self.param_values(&device, attack, param),
+                _ => Vec::new(),
+            },
+            ArgKind::Job => self.jobs.ids().iter().map(|id| id.to_string()).collect(),
+            ArgKind::Ports => ["common", "top20", "top100", "1-1024", "all"].map(String::from).to_vec(),
+            ArgKind::Word | ArgKind::Duration | ArgKind::Millis | ArgKind::Count => Vec::new(),
+        }
+    }
+
+    fn device(&self, target: Option<&str>) -> Option<Arc<SerialCommands>> {
+        match target {
+            Some(name) => self.devices.get(name).ok(),
+            None => self.devices.active().ok(),
+        }
+    }
+
+    fn device_names(&self, with_all: bool) -> Vec<String> {
+        let mut names: Vec<String> = self.devices.all().into_iter().map(|(name, _)| name).collect();
+        if with_all {
+            names.push(ALL_DEVICES.to_string());
+        }
+        names
+    }
+
+    fn hosts(&self) -> Vec<String> {
+        self.devices.known_hosts().iter().map(|host| host.to_string()).collect()
+    }
+
+    fn param_values(&self, device: &SerialCommands, attack: &str, param: &str) -> Vec<String> {
+        let Some(spec) = device.known_param(attack, param) else {
+            return Vec::new();
+        };
+        match spec.kind {
+            ParamType::Ip => self.hosts(),
+            ParamType::Bool => vec!["true".to_string(), "false".to_string()],
+            ParamType::Choice => spec.choices,
+            _ => spec.default.into_iter().collect(),
+        }
+    }
+}
+
+/// Files and directories starting with `partial`; directories end in `/`.
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(index) => (&partial[..=index], &partial[index + 1..]),
+        None => ("", partial),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect()
+}
+
+/// The longest prefix all `words` share.
+pub fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else {
+        return String::new();
+    };
+    let len = words
+        .iter()
+        .map(|word| {
+            first
+                .chars()
+                .zip(word.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum::<usize>()
+        })
+        .min()
+        .unwrap_or(0);
+    first[..len].to_string()
+}
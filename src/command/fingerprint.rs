@@ -0,0 +1,272 @@
+//! Service and version detection for open TCP ports.
+//!
+//! Many services announce themselves as soon as a client connects: SSH,
+//! FTP, SMTP, POP3, IMAP, Telnet and MySQL among them. Ports that stay
+//! silent are asked, first with an HTTP `HEAD` and then, on a new
+//! connection, with an MQTT CONNECT. Whatever comes back is matched
+//! against [`SIGNATURES`] for the service, product and version.
+
+use crate::models::port::Confidence;
+use regex::bytes::Regex;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Ports tried with the MQTT CONNECT before the HTTP `HEAD`.
+const MQTT_PORTS: &[u16] = &[1883, 1884, 8883, 8884];
+
+/// How long to wait for the rest of a reply once it has started.
+const MORE_WAIT: Duration = Duration::from_millis(150);
+
+/// The most of a reply that is read.
+const MAX_REPLY: usize = 4096;
+
+/// The longest banner kept.
+const MAX_BANNER: usize = 80;
+
+/// What one open port turned out to be.
+#[derive(Debug, Default)]
+pub struct Fingerprint {
+    /// The service, if a signature matched
+    pub service: Option<&'static str>,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    /// The first readable line of the reply
+    pub banner: Option<String>,
+    pub confidence: Option<Confidence>,
+}
+
+/// Where a signature takes the product or version from.
+enum Field {
+    Fixed(&'static str),
+    /// A capture group of the pattern
+    Group(usize),
+    Unknown,
+}
+
+use Field::{Fixed, Group, Unknown};
+
+struct Signature {
+    service: &'static str,
+    pattern: &'static str,
+    product: Field,
+    version: Field,
+    confidence: Confidence,
+}
+
+const fn sig(service: &'static str, pattern: &'static str, product: Field, version: Field, confidence: Confidence) -> Signature {
+    Signature { service, pattern, product, version, confidence }
+}
+
+/// Known replies, most specific first; the first match wins.
+static SIGNATURES: &[Signature] = &[
+    // SSH
+    sig("SSH", r"^SSH-[\d.]+-OpenSSH_(\S+)", Fixed("OpenSSH"), Group(1), Confidence::High),
+    sig("SSH", r"^SSH-[\d.]+-dropbear_(\S+)", Fixed("Dropbear SSH"), Group(1), Confidence::High),
+    sig("SSH", r"^SSH-[\d.]+-([^\s_]+)(?:_(\S+))?", Group(1), Group(2), Confidence::Medium),
+    // FTP
+    sig("FTP", r"^220[ -].*?vsFTPd ([\d.]+)", Fixed("vsftpd"), Group(1), Confidence::High),
+    sig("FTP", r"^220[ -].*?ProFTPD ([\w.]+)", Fixed("ProFTPD"), Group(1), Confidence::High),
+    sig("FTP", r"^220[ -].*?Pure-FTPd", Fixed("Pure-FTPd"), Unknown, Confidence::High),
+    sig("FTP", r"^220[ -].*?FileZilla Server(?: version)? ([\w.]+)", Fixed("FileZilla Server"), Group(1), Confidence::High),
+    sig("FTP", r"(?i)^220[ -].*?ftp", Unknown, Unknown, Confidence::Medium),
+    // SMTP
+    sig("SMTP", r"^220[ -]\S+ E?SMTP Postfix", Fixed("Postfix"), Unknown, Confidence::High),
+    sig("SMTP", r"^220[ -]\S+ E?SMTP Exim ([\d.]+)", Fixed("Exim"), Group(1), Confidence::High),
+    sig("SMTP", r"^220[ -]\S+ E?SMTP Sendmail ([\w.]+)", Fixed("Sendmail"), Group(1), Confidence::High),
+    sig("SMTP", r"^220[ -]\S+ (?:Microsoft )?E?SMTP", Unknown, Unknown, Confidence::Medium),
+    // POP3 and IMAP
+    sig("POP3", r"^\+OK.*?Dovecot", Fixed("Dovecot"), Unknown, Confidence::High),
+    sig("POP3", r"^\+OK", Unknown, Unknown, Confidence::Medium),
+    sig("IMAP", r"^\* OK.*?Dovecot", Fixed("Dovecot"), Unknown, Confidence::High),
+    sig("IMAP", r"^\* OK.*?IMAP", Unknown, Unknown, Confidence::Medium),
+    // MySQL greeting: 3-byte length, sequence 0, protocol 10, version
+    sig("MySQL", r"(?s-u)^.{3}\x00\x0a(?:5\.5\.5-)?([\d.]+)-MariaDB", Fixed("MariaDB"), Group(1), Confidence::High),
+    sig("MySQL", r"(?s-u)^.{3}\x00\x0a(\d+\.\d+\.\d+)", Fixed("MySQL"), Group(1), Confidence::High),
+    // Telnet option negotiation, or just a login prompt
+    sig("Telnet", r"(?-u)^\xff[\xfb-\xfe]", Unknown, Unknown, Confidence::Medium),
+    sig("Telnet", r"(?i)login:\s*$", Unknown, Unknown, Confidence::Low),
+    // HTTP, by the Server header of the HEAD reply
+    sig("HTTP", r"(?im)^Server:[ \t]*nginx/([\d.]+)", Fixed("nginx"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*Apache/([\d.]+)", Fixed("Apache httpd"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*lighttpd/([\d.]+)", Fixed("lighttpd"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*Microsoft-IIS/([\d.]+)", Fixed("Microsoft IIS"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*Boa/([\w.]+)", Fixed("Boa"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*mini_httpd/([\d.]+)", Fixed("mini_httpd"), Group(1), Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*GoAhead", Fixed("GoAhead WebServer"), Unknown, Confidence::High),
+    sig("HTTP", r"(?im)^Server:[ \t]*([^/\r\n]+)/(\S+)", Group(1), Group(2), Confidence::Medium),
+    sig("HTTP", r"(?im)^Server:[ \t]*([^\r\n]+?)[ \t]*\r?$", Group(1), Unknown, Confidence::Medium),
+    sig("HTTP", r"^HTTP/1\.[01] \d{3}", Unknown, Unknown, Confidence::Medium),
+    // MQTT CONNACK
+    sig("MQTT", r"(?s-u)^\x20\x02[\x00\x01][\x00-\x05]", Unknown, Unknown, Confidence::Medium),
+];
+
+static PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    SIGNATURES
+        .iter()
+        .map(|signature| Regex::new(signature.pattern).expect("invalid signature pattern"))
+        .collect()
+});
+
+/// A question for a port that says nothing on its own.
+#[derive(Debug, Clone, Copy)]
+enum Probe {
+    Http,
+    Mqtt,
+}
+
+impl Probe {
+    fn request(self, addr: SocketAddr) -> Vec<u8> {
+        match self {
+            Probe::Http => format!("HEAD / HTTP/1.0\r\nHost: {}\r\n\r\n", addr.ip()).into_bytes(),
+            Probe::Mqtt => {
+                // MQTT 3.1.1 CONNECT with a clean session and a 60 s keep-alive
+                let client_id = b"secot-probe";
+                let mut packet = vec![0x10, (12 + client_id.len()) as u8];
+                packet.extend([0x00, 0x04]);
+                packet.extend(b"MQTT");
+                packet.extend([0x04, 0x02, 0x00, 0x3c, 0x00, client_id.len() as u8]);
+                packet.extend(client_id);
+                packet
+            }
+        }
+    }
+
+    /// Sent after a reply, to leave the service as it was.
+    fn farewell(self) -> &'static [u8] {
+        match self {
+            Probe::Http => &[],
+            Probe::Mqtt => &[0xe0, 0x00], // DISCONNECT
+        }
+    }
+}
+
+/// Find out what listens on `addr`, given the `stream` the scan opened.
+///
+/// Each read waits up to `wait`. `None` if nothing ever answered.
+pub async fn fingerprint(mut stream: TcpStream, addr: SocketAddr, wait: Duration) -> Option<Fingerprint> {
+    let banner = read_reply(&mut stream, wait).await;
+    if !banner.is_empty() {
+        return Some(identify(&banner));
+    }
+
+    let probes = if MQTT_PORTS.contains(&addr.port()) {
+        [Probe::Mqtt, Probe::Http]
+    } else {
+        [Probe::Http, Probe::Mqtt]
+    };
+
+    let mut stream = Some(stream);
+    let mut unrecognised = None;
+    for probe in probes {
+        // A service that didn't understand one probe may have hung up, so later ones reconnect
+        let mut conn = match stream.take() {
+            Some(conn) => conn,
+            None => match timeout(wait, TcpStream::connect(addr)).await {
+                Ok(Ok(conn)) => conn,
+                _ => break,
+            },
+        };
+        if conn.write_all(&probe.request(addr)).await.is_err() {
+            continue;
+        }
+
+        let reply = read_reply(&mut conn, wait).await;
+        if reply.is_empty() {
+            continue;
+        }
+        let _ = conn.write_all(probe.farewell()).await;
+
+        let found = identify(&reply);
+        if found.service.is_some() {
+            return Some(found);
+        }
+        unrecognised.get_or_insert(found);
+    }
+    unrecognised
+}
+
+/// Match `reply` against the signatures.
+fn identify(reply: &[u8]) -> Fingerprint {
+    let mut found = Fingerprint {
+        banner: banner_text(reply),
+        ..Fingerprint::default()
+    };
+
+    let Some((signature, captures)) = SIGNATURES
+        .iter()
+        .zip(PATTERNS.iter())
+        .find_map(|(signature, pattern)| pattern.captures(reply).map(|captures| (signature, captures)))
+    else {
+        return found;
+    };
+
+    let field = |field: &Field| match field {
+        Fixed(text) => Some(text.to_string()),
+        Group(group) => captures
+            .get(*group)
+            .map(|text| String::from_utf8_lossy(text.as_bytes()).trim().to_string())
+            .filter(|text| !text.is_empty()),
+        Unknown => None,
+    };
+    found.service = Some(signature.service);
+    found.product = field(&signature.product);
+    found.version = field(&signature.version);
+    found.confidence = Some(signature.confidence);
+    if signature.service == "MQTT" {
+        found.banner = Some(connack_text(reply[3]).to_string());
+    }
+    found
+}
+
+/// Read what the peer sends within `wait`, and the rest of it once it starts.
+async fn read_reply(stream: &mut TcpStream, wait: Duration) -> Vec<u8> {
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 1024];
+    let mut wait = wait;
+    while reply.len() < MAX_REPLY {
+        match timeout(wait, stream.read(&mut buf)).await {
+            Ok(Ok(len)) if len > 0 => reply.extend(&buf[..len]),
+            _ => break,
+        }
+        wait = MORE_WAIT;
+    }
+    reply
+}
+
+/// The first run of readable text in `reply`, skipping Telnet negotiation.
+fn banner_text(reply: &[u8]) -> Option<String> {
+    let mut text = Vec::with_capacity(reply.len());
+    let mut bytes = reply.iter().copied();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            // IAC, command, option
+            0xff => {
+                bytes.next();
+                bytes.next();
+            }
+            b' '..=b'~' | b'\t' => text.push(byte),
+            _ => text.push(b'\n'),
+        }
+    }
+
+    String::from_utf8_lossy(&text)
+        .lines()
+        .map(str::trim)
+        .find(|line| line.len() >= 3)
+        .map(|line| line.chars().take(MAX_BANNER).collect())
+}
+
+fn connack_text(code: u8) -> &'static str {
+    match code {
+        0 => "CONNACK: connection accepted without credentials",
+        1 => "CONNACK: unacceptable protocol version",
+        2 => "CONNACK: client identifier rejected",
+        3 => "CONNACK: server unavailable",
+        4 => "CONNACK: bad user name or password",
+        _ => "CONNACK: not authorized",
+    }
+}
@@ -0,0 +1,298 @@
+//! Background jobs: REPL commands run with a trailing `&`.
+//!
+//! ```text
+//! SECoT> scan network 10.0.0.0/24 &
+//! [1] scan network 10.0.0.0/24
+//! SECoT> jobs
+//! SECoT> fg 1
+//! ```
+//!
+//! A job's output is captured rather than printed over the prompt, and kept
+//! with its result once it ends. `jobs` lists them, `fg <id>` shows a job's
+//! output, waiting for it while it still runs, and `cancel <id>` stops it.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use crate::outln;
+use crate::output::formatter::{print_info, print_warning, CapturedOutput};
+use crate::output::table::FormattedTable;
+use futures::future::BoxFuture;
+use prettytable::{Cell, Row, Table};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::line_editor::PromptLine;
+use tokio::task::AbortHandle;
+
+/// How often `fg` checks a running job for new output.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        };
+        f.write_str(state)
+    }
+}
+
+struct Job {
+    command: String,
+    started_at: DateTime<Local>,
+    started: Instant,
+    finished: Option<Instant>,
+    state: JobState,
+    error: Option<String>,
+    output: CapturedOutput,
+    abort: Option<AbortHandle>,
+}
+
+/// A job as `jobs` lists it.
+#[derive(Debug, Serialize)]
+pub struct JobInfo {
+    pub id: usize,
+    pub command: String,
+    pub state: JobState,
+    pub started_at: String,
+    pub elapsed_secs: f64,
+    pub error: Option<String>,
+}
+
+/// The session's background jobs, running and finished.
+pub struct Jobs {
+    jobs: Mutex<BTreeMap<usize, Job>>,
+    prompt_line: PromptLine,
+}
+
+impl Jobs {
+    /// Jobs that announce their end above `prompt_line`.
+    pub fn new(prompt_line: PromptLine) -> Self {
+        Self {
+            jobs: Mutex::new(BTreeMap::new()),
+            prompt_line,
+        }
+    }
+
+    /// Run `future` for `command` as a job, returning its id.
+    ///
+    /// Must be called within the runtime.
+    pub fn spawn(self: &Arc<Self>, command: &str, future: BoxFuture<'static, Result<()>>) -> Result<usize> {
+        let output = CapturedOutput::default();
+        let id = {
+            let mut jobs = self.lock()?;
+            let id = jobs.keys().next_back().map_or(1, |last| last + 1);
+            jobs.insert(id, Job {
+                command: command.to_string(),
+                started_at: Local::now(),
+                started: Instant::now(),
+                finished: None,
+                state: JobState::Running,
+                error: None,
+                output: output.clone(),
+                abort: None,
+            });
+            id
+        };
+
+        let jobs = self.clone();
+        let task = tokio::spawn(async move {
+            let result = output.capture(future).await;
+            jobs.finish(id, result);
+        });
+
+        if let Some(job) = self.lock()?.get_mut(&id).filter(|job| job.state == JobState::Running) {
+            job.abort = Some(task.abort_handle());
+        }
+        Ok(id)
+    }
+
+    /// Stop job `id`.
+    pub fn cancel(&self, id: usize) -> Result<()> {
+        let mut jobs = self.lock()?;
+        let job = jobs.get_mut(&id).ok_or_else(|| anyhow!("No job {}", id))?;
+        if job.state != JobState::Running {
+            return Err(anyhow!("Job {} has already ended ({})", id, job.state));
+        }
+
+        if let Some(abort) = job.abort.take() {
+            abort.abort();
+        }
+        job.state = JobState::Cancelled;
+        job.finished = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Stop every running job, returning how many there were.
+    pub fn cancel_all(&self) -> usize {
+        self.running_ids()
+            .into_iter()
+            .filter(|id| self.cancel(*id).is_ok())
+            .count()
+    }
+
+    /// Show job `id`'s output, following it until the job ends, then return
+    /// its result.
+    ///
+    /// If the caller stops waiting, for instance on Ctrl-C, the job is
+    /// cancelled: it was in the foreground.
+    pub async fn wait(&self, id: usize) -> Result<()> {
+        let output = self.lock()?.get(&id).map(|job| job.output.clone()).ok_or_else(|| anyhow!("No job {}", id))?;
+        let mut guard = CancelOnDrop { jobs: self, id, armed: true };
+        let mut shown = 0;
+
+        loop {
+            let state = self.lock()?.get(&id).map(|job| job.state.clone());
+            let text = output.since(shown);
+            shown += text.len();
+            for line in text.lines() {
+                outln!("{}", line);
+            }
+
+            match state {
+                Some(JobState::Running) => tokio::time::sleep(FOLLOW_INTERVAL).await,
+                _ => break,
+            }
+        }
+        guard.armed = false;
+
+        let jobs = self.lock()?;
+        let job = jobs.get(&id).ok_or_else(|| anyhow!("No job {}", id))?;
+        match job.state {
+            JobState::Failed => Err(anyhow!(job.error.clone().unwrap_or_default())),
+            JobState::Cancelled => Err(anyhow!("Job {} was cancelled", id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ids of every job, oldest first.
+    pub fn ids(&self) -> Vec<usize> {
+        self.lock().map(|jobs| jobs.keys().copied().collect()).unwrap_or_default()
+    }
+
+    /// Ids of the jobs still running.
+    pub fn running_ids(&self) -> Vec<usize> {
+        self.lock()
+            .map(|jobs| {
+                jobs.iter()
+                    .filter(|(_, job)| job.state == JobState::Running)
+                    .map(|(id, _)| *id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let Ok(jobs) = self.lock() else {
+            return Vec::new();
+        };
+        jobs.iter()
+            .map(|(id, job)| JobInfo {
+                id: *id,
+                command: job.command.clone(),
+                state: job.state.clone(),
+                started_at: job.started_at.format("%H:%M:%S").to_string(),
+                elapsed_secs: job.finished.unwrap_or_else(Instant::now).duration_since(job.started).as_secs_f64(),
+                error: job.error.clone(),
+            })
+            .collect()
+    }
+
+    pub fn print_list(&self, output_format: &str) -> Result<()> {
+        let jobs = self.list();
+        if output_format == "json" {
+            outln!("{}", serde_json::to_string_pretty(&jobs)?);
+            return Ok(());
+        }
+        if jobs.is_empty() {
+            print_info("No jobs");
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.add_row(Row::new(vec![
+            Cell::new("id"),
+            Cell::new("state"),
+            Cell::new("started"),
+            Cell::new("elapsed"),
+            Cell::new("command"),
+        ]));
+        for job in &jobs {
+            table.add_row(Row::new(vec![
+                Cell::new(&job.id.to_string()),
+                Cell::new(&job.state.to_string()),
+                Cell::new(&job.started_at),
+                Cell::new(&format!("{:.1} s", job.elapsed_secs)),
+                Cell::new(&job.command),
+            ]));
+        }
+        outln!("{}", FormattedTable::new(&format!("Jobs ({})", jobs.len()), table));
+        Ok(())
+    }
+
+    /// Record how job `id` ended and say so above the prompt.
+    fn finish(&self, id: usize, result: Result<()>) {
+        let Ok(mut jobs) = self.lock() else {
+            return;
+        };
+        let Some(job) = jobs.get_mut(&id).filter(|job| job.state == JobState::Running) else {
+            return;
+        };
+
+        job.finished = Some(Instant::now());
+        job.abort = None;
+        let message = match result {
+            Ok(()) => {
+                job.state = JobState::Done;
+                format!("[{}] Done: {}", id, job.command)
+            }
+            Err(e) => {
+                job.state = JobState::Failed;
+                job.error = Some(e.to_string());
+                format!("[{}] Failed: {}: {}", id, job.command, e)
+            }
+        };
+        let failed = job.state == JobState::Failed;
+        drop(jobs);
+
+        self.prompt_line.print_above(|| {
+            if failed {
+                print_warning(&message);
+            } else {
+                print_info(&message);
+            }
+        });
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<usize, Job>>> {
+        self.jobs.lock().map_err(|_| anyhow!("Job table is poisoned"))
+    }
+}
+
+/// Cancels a job when whoever waited for it in the foreground gives up.
+struct CancelOnDrop<'a> {
+    jobs: &'a Jobs,
+    id: usize,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.armed && self.jobs.cancel(self.id).is_ok() {
+            print_warning(&format!("[{}] Cancelled", self.id));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Line editor for the interactive shell.
+//!
+//! Emacs-style keys as in bash:
+//!
+//! | Key                   | Action                                      |
+//! |-----------------------|---------------------------------------------|
+//! | Left/Right, Ctrl-B/F  | Move one character                          |
+//! | Home/End, Ctrl-A/E    | Move to the start/end of the line           |
+//! | Up/Down, Ctrl-P/N     | Previous/next history entry                 |
+//! | Ctrl-R                | Search history backwards; again for older   |
+//! | Tab                   | Complete; twice to list the candidates      |
+//! | Ctrl-U/K/W            | Delete to the start/end, the word before    |
+//! | Ctrl-L                | Clear the screen                            |
+//! | Ctrl-C                | Discard the line                            |
+//! | Ctrl-D                | Quit on an empty line                       |
+//!
+//! History is kept per user in `~/.secot_history`.
+
+use console::{measure_text_width, Key, Term};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use super::completion::{common_prefix, ReplCompleter};
+
+/// Entries kept in the history file.
+const MAX_HISTORY: usize = 1000;
+
+const HISTORY_FILE: &str = ".secot_history";
+
+/// Previously entered lines, oldest first, saved as they are added.
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Load the history in `path`; without one nothing is saved.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut entries: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| content.lines().filter(|line| !line.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+
+        if entries.len() > MAX_HISTORY {
+            entries.drain(..entries.len() - MAX_HISTORY);
+            if let Some(path) = &path {
+                let _ = fs::write(path, entries.join("\n") + "\n");
+            }
+        }
+
+        Self { entries, path }
+    }
+
+    /// The current user's history file.
+    pub fn user_path() -> Option<PathBuf> {
+        std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+    }
+
+    /// Add `line` unless it is empty or repeats the last entry.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+
+        if let Some(path) = &self.path {
+            // Losing history isn't worth interrupting the session for
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Index of the newest entry before `before` that contains `query`.
+    fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+/// What the editor has on screen.
+struct Shown {
+    prompt: String,
+    line: String,
+    /// Text right of the cursor.
+    tail: String,
+}
+
+impl Shown {
+    fn draw(&self, term: &Term) -> io::Result<()> {
+        term.write_str(&format!("\r\x1b[2K{}{}", self.prompt, self.line))?;
+        let back = measure_text_width(&self.tail);
+        if back > 0 {
+            term.move_cursor_left(back)?;
+        }
+        term.flush()
+    }
+}
+
+/// Handle to the prompt line, for printing while the user is typing.
+#[derive(Clone, Default)]
+pub struct PromptLine {
+    shown: Arc<Mutex<Option<Shown>>>,
+}
+
+impl PromptLine {
+    /// Run `print` above the prompt if one is shown, then redraw it as it was.
+    pub fn print_above(&self, print: impl FnOnce()) {
+        let Ok(shown) = self.shown.lock() else {
+            return print();
+        };
+        match shown.as_ref() {
+            Some(line) => {
+                let term = Term::stdout();
+                let _ = term.write_str("\r\x1b[2K");
+                print();
+                let _ = line.draw(&term);
+            }
+            None => print(),
+        }
+    }
+
+    fn show(&self, term: &Term, prompt: &str, line: &[char], cursor: usize) -> io::Result<()> {
+        let shown = Shown {
+            prompt: prompt.to_string(),
+            line: line.iter().collect(),
+            tail: line[cursor..].iter().collect(),
+        };
+        let mut current = self.shown.lock().map_err(|_| io::Error::other("prompt state is poisoned"))?;
+        shown.draw(term)?;
+        *current = Some(shown);
+        Ok(())
+    }
+
+    fn hide(&self) {
+        if let Ok(mut shown) = self.shown.lock() {
+            *shown = None;
+        }
+    }
+}
+
+/// Reads lines from the terminal with editing, history and completion.
+pub struct LineEditor {
+    term: Term,
+    history: History,
+    completer: ReplCompleter,
+    prompt_line: PromptLine,
+}
+
+impl LineEditor {
+    pub fn new(completer: ReplCompleter, history: History) -> Self {
+        Self {
+            term: Term::stdout(),
+            history,
+            completer,
+            prompt_line: PromptLine::default(),
+        }
+    }
+
+    /// Share the prompt state with `prompt_line`, e.g. one made before the editor.
+    pub fn with_prompt_line(mut self, prompt_line: PromptLine) -> Self {
+        self.prompt_line = prompt_line;
+        self
+    }
+
+    /// Read one line after `prompt`, adding it to the history.
+    ///
+    /// Returns `None` on Ctrl-D at an empty line.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let result = self.edit(prompt);
+        self.prompt_line.hide();
+
+        if let Ok(Some(line)) = &result {
+            self.history.add(line);
+        }
+        result
+    }
+
+    fn edit(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Position in the history while browsing it, and the line being typed before
+        let mut browsing: Option<usize> = None;
+        let mut draft: Vec<char> = Vec::new();
+        let mut tabbed = false;
+
+        loop {
+            self.prompt_line.show(&self.term, prompt, &line, cursor)?;
+            let key = self.term.read_key_raw()?;
+            let tab = key == Key::Tab;
+
+            match key {
+                Key::Enter => {
+                    self.term.write_line("")?;
+                    return Ok(Some(line.into_iter().collect()));
+                }
+                Key::CtrlC => {
+                    self.term.write_line("^C")?;
+                    line.clear();
+                    cursor = 0;
+                    browsing = None;
+                }
+                Key::Char('\x04') if line.is_empty() => {
+                    self.term.write_line("")?;
+                    return Ok(None);
+                }
+                Key::Char('\x12') => {
+                    let (found, accept) = self.search_history(&line)?;
+                    line = found.chars().collect();
+                    cursor = line.len();
+                    if accept {
+                        self.term.write_line("")?;
+                        return Ok(Some(found));
+                    }
+                }
+                Key::Tab => {
+                    let (replaced, listed) = self.complete(prompt, &line, cursor, tabbed)?;
+                    if let Some((new_line, new_cursor)) = replaced {
+                        line = new_line;
+                        cursor = new_cursor;
+                    }
+                    if listed {
+                        tabbed = false;
+                        continue;
+                    }
+                }
+                Key::ArrowUp | Key::Char('\x10') => {
+                    let previous = match browsing {
+                        None if self.history.entries.is_empty() => None,
+                        None => {
+                            draft = line.clone();
+                            Some(self.history.entries.len() - 1)
+                        }
+                        Some(index) => Some(index.saturating_sub(1)),
+                    };
+                    if let Some(index) = previous {
+                        browsing = Some(index);
+                        line = self.history.entries[index].chars().collect();
+                        cursor = line.len();
+                    }
+                }
+                Key::ArrowDown | Key::Char('\x0e') => {
+                    if let Some(index) = browsing {
+                        if index + 1 < self.history.entries.len() {
+                            browsing = Some(index + 1);
+                            line = self.history.entries[index + 1].chars().collect();
+                        } else {
+                            browsing = None;
+                            line = std::mem::take(&mut draft);
+                        }
+                        cursor = line.len();
+                    }
+                }
+                Key::ArrowLeft | Key::Char('\x02') => cursor = cursor.saturating_sub(1),
+                Key::ArrowRight | Key::Char('\x06') => cursor = (cursor + 1).min(line.len()),
+                Key::Home => cursor = 0,
+                Key::End => cursor = line.len(),
+                Key::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Del | Key::Char('\x04') if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Char('\x15') => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                Key::Char('\x0b') => line.truncate(cursor),
+                Key::Char('\x17') => {
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                }
+                Key::Char('\x0c') => self.term.clear_screen()?,
+                Key::Char(c) if !c.is_control() => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                _ => {}
+            }
+
+            tabbed = tab;
+        }
+    }
+
+    /// Complete the word before the cursor.
+    ///
+    /// Returns the new line and cursor if anything was inserted, and whether
+    /// the candidates were listed (on a second Tab that couldn't insert).
+    #[allow(clippy::type_complexity)]
+    fn complete(
+        &self,
+        prompt: &str,
+        line: &[char],
+        cursor: usize,
+        tabbed: bool,
+    ) -> io::Result<(Option<(Vec<char>, usize)>, bool)> {
+        let before: String = line[..cursor].iter().collect();
+        let (start, candidates) = self.completer.complete(&before);
+        let partial = &before[start..];
+
+        let insert = match candidates.as_slice() {
+            [] => return Ok((None, false)),
+            [only] if only.ends_with('/') => only.clone(),
+            [only] => format!("{} ", only),
+            _ => common_prefix(&candidates),
+        };
+
+        if insert.len() > partial.len() {
+            let mut completed: Vec<char> = before[..start].chars().chain(insert.chars()).collect();
+            let new_cursor = completed.len();
+            completed.extend_from_slice(&line[cursor..]);
+            return Ok((Some((completed, new_cursor)), false));
+        }
+
+        if !tabbed {
+            return Ok((None, false));
+        }
+
+        // Nothing to add: show what there is to choose from
+        self.prompt_line.hide();
+        self.term.write_line("")?;
+        let width = candidates.iter().map(|c| measure_text_width(c)).max().unwrap_or(0) + 2;
+        let columns = (self.term.size().1 as usize / width).max(1);
+        for row in candidates.chunks(columns) {
+            let cells: Vec<String> = row.iter().map(|candidate| format!("{:width$}", candidate, width = width)).collect();
+            self.term.write_line(cells.join("").trim_end())?;
+        }
+        self.prompt_line.show(&self.term, prompt, line, cursor)?;
+        Ok((None, true))
+    }
+
+    /// Incremental reverse search, started from `line`.
+    ///
+    /// Returns the line to continue with and whether Enter accepted it.
+    fn search_history(&self, line: &[char]) -> io::Result<(String, bool)> {
+        let original: String = line.iter().collect();
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+        let mut failed = false;
+
+        loop {
+            let matched = found.map(|index| self.history.entries[index].as_str()).unwrap_or("");
+            let label = if failed { "failed reverse-i-search" } else { "reverse-i-search" };
+            let prompt = format!("({})`{}': ", label, query);
+            let shown: Vec<char> = matched.chars().collect();
+            let cursor = matched
+                .find(query.as_str())
+                .filter(|_| !query.is_empty())
+                .map_or(shown.len(), |index| matched[..index].chars().count());
+            self.prompt_line.show(&self.term, &prompt, &shown, cursor)?;
+
+            match self.term.read_key_raw()? {
+                Key::Char('\x12') => {
+                    let before = found.unwrap_or(self.history.entries.len());
+                    match self.history.search(&query, before) {
+                        Some(index) => found = Some(index),
+                        None => failed = true,
+                    }
+                }
+                Key::Backspace => {
+                    query.pop();
+                    found = self.history.search(&query, self.history.entries.len());
+                    failed = found.is_none() && !query.is_empty();
+                }
+                Key::Char(c) if !c.is_control() => {
+                    query.push(c);
+                    let from = found.map_or(self.history.entries.len(), |index| index + 1);
+                    found = self.history.search(&query, from);
+                    failed = found.is_none();
+                }
+                Key::Enter if found.is_some() => return Ok((matched.to_string(), true)),
+                Key::Enter => return Ok((original, false)),
+                Key::CtrlC | Key::Char('\x07') => return Ok((original, false)),
+                // Any other key leaves the match on the line for editing
+                _ => return Ok((if found.is_some() { matched.to_string() } else { original }, false)),
+            }
+        }
+    }
+}
@@ -1,4 +1,12 @@
+pub mod cli;
 pub mod cmd_handler;
+pub mod completion;
+pub mod fingerprint;
+pub mod jobs;
+pub mod line_editor;
+pub mod registry;
 pub mod broker_test;
 pub mod scan_ports;
+pub mod scan_udp;
 pub mod scan_networks;
+pub mod script;
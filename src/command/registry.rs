@@ -0,0 +1,841 @@
+//! The REPL's commands, declared once.
+//!
+//! Every command is a [`CommandSpec`]: the words it is typed with, its
+//! arguments with their types and defaults, and a description. The table of
+//! them lives in [`cmd_handler`](super::cmd_handler); dispatch, `help`,
+//! `help <command>`, argument errors, tab completion and the command-line
+//! subcommands are all derived from it, so adding a command means adding an
+//! entry and its handler there.
+//!
+//! Arguments are positional unless they are flags or keywords:
+//!
+//! ```text
+//! secot scan wifi sort channel    # keyword: the name, then the value
+//! secot scan mqtt verify          # flag: the name alone
+//! secot attack deauth 30s         # positional
+//! ```
+//!
+//! On the command line flags and keywords become `--name` options, as do
+//! positional arguments declared [`long`](ArgSpec::long). The prompt and
+//! scripts take that spelling as well, so a line copied from either works
+//! in the other:
+//!
+//! ```text
+//! secot attack deauth --duration 30s
+//! secot scan wifi --sort=channel
+//! ```
+
+use anyhow::Result;
+use clap::builder::PossibleValuesParser;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use crate::outln;
+use crate::config::scope::ScopeGuard;
+use crate::error::CliError;
+use crate::models::attack::parse_duration;
+use crate::models::port::parse_port_spec;
+use crate::output::formatter::print_section;
+use crate::serial::registry::DeviceRegistry;
+use crate::serial::serial_commands::SerialCommands;
+use futures::future::BoxFuture;
+use super::jobs::Jobs;
+use ipnetwork::IpNetwork;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Runs a command once its arguments have been checked.
+pub type Handler = for<'a> fn(Ctx<'a>) -> BoxFuture<'a, Result<()>>;
+
+/// Checks a device command against the engagement scope before it runs on `device`.
+pub type CheckFn = for<'a> fn(&'a Args, &'a SerialCommands, &'a ScopeGuard<'a>) -> BoxFuture<'a, Result<()>>;
+
+/// The firmware command to send to every device at once for `@all`, with
+/// how long to wait between its lines. Gets the attacks any of the devices
+/// advertises; `None` runs the command on each device in turn instead.
+pub type FanOutFn = fn(&Args, &[String]) -> Result<Option<(String, Duration)>>;
+
+/// What a command is given to run.
+pub struct Ctx<'a> {
+    pub args: Args,
+    pub output_format: &'a mut String,
+    pub devices: &'a Arc<DeviceRegistry>,
+    pub jobs: &'a Arc<Jobs>,
+    /// The device the command was addressed to with `@name`, or the active one.
+    pub device: Arc<SerialCommands>,
+    pub scope: &'a ScopeGuard<'a>,
+}
+
+/// The section of `help` a command is listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    Network,
+    Serial,
+    Secot,
+    Device,
+    Audit,
+    Scripts,
+    Jobs,
+    General,
+}
+
+impl Group {
+    const ALL: [Group; 8] = [
+        Group::Network,
+        Group::Serial,
+        Group::Secot,
+        Group::Device,
+        Group::Audit,
+        Group::Scripts,
+        Group::Jobs,
+        Group::General,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            Group::Network => "Network Commands",
+            Group::Serial => "Serial Port Commands",
+            Group::Secot => "SECoT Commands",
+            Group::Device => "Device Commands",
+            Group::Audit => "Audit Commands",
+            Group::Scripts => "Scripts",
+            Group::Jobs => "Background Jobs",
+            Group::General => "General Commands",
+        }
+    }
+
+    /// Lines printed under the group's commands.
+    fn notes(self) -> &'static [&'static str] {
+        match self {
+            Group::Device => &[
+                "  @<name> <command>            - Run one command on <name>",
+                "  @all <command>               - Run a command on every device",
+            ],
+            Group::Scripts => &[
+                "  In scripts: # comment, let name = value, $name, sleep <dur>,",
+                "  on-error continue|abort, exit",
+            ],
+            Group::Jobs => &[
+                "  <command> &                  - Run a command in the background",
+                "  Ctrl-C cancels the command in the foreground, background jobs keep running",
+            ],
+            _ => &[],
+        }
+    }
+}
+
+/// How a command uses the SECoT devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceUse {
+    /// Not at all; `@name` is refused.
+    None,
+    /// Runs on the addressed device, which must be connected.
+    Connected,
+    /// Runs on the addressed device, connected or not.
+    Any,
+    /// Works on the registered devices as a whole; `@name` is refused.
+    Registry,
+}
+
+/// What a command scans, for the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scans {
+    /// The host or network in this argument.
+    Arg(&'static str),
+    /// Around the device it runs on.
+    Device,
+}
+
+/// What an argument's value is, which decides how it is checked and completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Any word.
+    Word,
+    /// An IP address.
+    Host,
+    /// A network in CIDR notation.
+    Network,
+    SerialPort,
+    Baud,
+    File,
+    /// A registered device's name.
+    Device,
+    Attack,
+    /// A parameter of the command's `attack` argument.
+    Param,
+    /// A value for the command's `param` argument.
+    Value,
+    /// 90, 30s, 5m or 1h.
+    Duration,
+    /// A time in milliseconds, at least 1.
+    Millis,
+    Count,
+    /// Ports such as 1-1024,1883,top100.
+    Ports,
+    /// A background job's id.
+    Job,
+    /// One of these words.
+    Choice(&'static [&'static str]),
+}
+
+impl ArgKind {
+    /// The placeholder shown for a keyword's value.
+    fn placeholder(self) -> String {
+        match self {
+            ArgKind::Word | ArgKind::Value => "value".to_string(),
+            ArgKind::Host => "ip".to_string(),
+            ArgKind::Network => "cidr".to_string(),
+            ArgKind::SerialPort => "port".to_string(),
+            ArgKind::Baud => "baud".to_string(),
+            ArgKind::File => "file".to_string(),
+            ArgKind::Device => "name".to_string(),
+            ArgKind::Attack => "attack".to_string(),
+            ArgKind::Param => "param".to_string(),
+            ArgKind::Duration => "dur".to_string(),
+            ArgKind::Millis => "ms".to_string(),
+            ArgKind::Count => "count".to_string(),
+            ArgKind::Ports => "ports".to_string(),
+            ArgKind::Job => "id".to_string(),
+            ArgKind::Choice(choices) => choices.join("|"),
+        }
+    }
+
+    /// Why `value` isn't one of these, if it isn't.
+    fn check(self, value: &str) -> Option<String> {
+        let valid = match self {
+            ArgKind::Host => value.parse::<IpAddr>().is_ok(),
+            ArgKind::Network => value.contains('/') && value.parse::<IpNetwork>().is_ok(),
+            ArgKind::Baud => value.parse::<u32>().is_ok(),
+            ArgKind::Count | ArgKind::Job => value.parse::<usize>().is_ok(),
+            ArgKind::Duration => return parse_duration(value).err().map(|e| e.to_string()),
+            ArgKind::Millis => value.parse::<u64>().is_ok_and(|ms| ms > 0),
+            ArgKind::Ports => return parse_port_spec(value).err().map(|e| e.to_string()),
+            ArgKind::Choice(choices) => choices.iter().any(|choice| choice.eq_ignore_ascii_case(value)),
+            _ => true,
+        };
+        if valid {
+            return None;
+        }
+
+        Some(match self {
+            ArgKind::Host => format!("'{}' is not an IP address", value),
+            ArgKind::Network => format!("'{}' is not a network such as 192.168.1.0/24", value),
+            ArgKind::Choice(choices) => format!("'{}' is not one of {}", value, choices.join(", ")),
+            _ => format!("'{}' is not a valid {}", value, self.placeholder()),
+        })
+    }
+}
+
+/// Whether an argument must be given, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    Required,
+    Optional,
+    /// Optional, taking this value when left out.
+    Default(&'static str),
+    /// Its name alone, or nothing.
+    Flag,
+    /// Optional, given as its name followed by the value.
+    Keyword,
+    /// All remaining words, possibly none.
+    Rest,
+}
+
+/// One argument of a command.
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub presence: Presence,
+    pub help: &'static str,
+    /// Given as `--name <value>` on the command line instead of by position.
+    pub long: bool,
+}
+
+impl ArgSpec {
+    const fn new(name: &'static str, kind: ArgKind, presence: Presence, help: &'static str) -> Self {
+        Self { name, kind, presence, help, long: false }
+    }
+
+    pub const fn required(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self::new(name, kind, Presence::Required, help)
+    }
+
+    pub const fn optional(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self::new(name, kind, Presence::Optional, help)
+    }
+
+    pub const fn default(name: &'static str, kind: ArgKind, value: &'static str, help: &'static str) -> Self {
+        Self::new(name, kind, Presence::Default(value), help)
+    }
+
+    pub const fn flag(name: &'static str, help: &'static str) -> Self {
+        Self::new(name, ArgKind::Word, Presence::Flag, help)
+    }
+
+    pub const fn keyword(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self::new(name, kind, Presence::Keyword, help)
+    }
+
+    pub const fn rest(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self::new(name, kind, Presence::Rest, help)
+    }
+
+    /// Take the argument as `--name <value>` on the command line.
+    pub const fn long(self) -> Self {
+        Self { long: true, ..self }
+    }
+
+    fn is_positional(&self) -> bool {
+        !matches!(self.presence, Presence::Flag | Presence::Keyword)
+    }
+
+    fn usage(&self) -> String {
+        match self.presence {
+            Presence::Required => format!("<{}>", self.name),
+            Presence::Optional | Presence::Default(_) => format!("[{}]", self.name),
+            Presence::Flag => format!("[{}]", self.name),
+            Presence::Keyword => format!("[{} <{}>]", self.name, self.kind.placeholder()),
+            Presence::Rest => format!("[{}...]", self.name),
+        }
+    }
+}
+
+/// The checked arguments of one command, defaults filled in.
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    values: HashMap<&'static str, String>,
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// A required argument, or one with a default.
+    pub fn value(&self, name: &str) -> &str {
+        self.get(name).unwrap_or_default()
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// The words of a [`Presence::Rest`] argument.
+    pub fn words(&self, name: &str) -> Vec<&str> {
+        self.get(name).map(|words| words.split_whitespace().collect()).unwrap_or_default()
+    }
+
+    /// An argument parsed as `T`, if it was given.
+    pub fn parse<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(name)
+            .map(|value| value.parse::<T>().map_err(|e| anyhow::anyhow!("Invalid {}: {}", name, e)))
+            .transpose()
+    }
+}
+
+/// Where reading a command's words got to.
+struct Walk {
+    values: HashMap<&'static str, String>,
+    /// A keyword still waiting for its value.
+    pending: Option<&'static ArgSpec>,
+    /// The positional argument the next word goes to.
+    next: Option<&'static ArgSpec>,
+    /// A word no argument takes.
+    unexpected: Option<String>,
+}
+
+/// One REPL command.
+pub struct CommandSpec {
+    pub path: &'static [&'static str],
+    /// Other words for the last word of `path`.
+    pub aliases: &'static [&'static str],
+    pub args: &'static [ArgSpec],
+    pub about: &'static str,
+    /// More explanation for `help <command>`.
+    pub details: &'static str,
+    pub group: Group,
+    pub device: DeviceUse,
+    /// Only makes sense within a session, so there's no subcommand for it.
+    pub shell_only: bool,
+    pub scans: Option<Scans>,
+    pub check: Option<CheckFn>,
+    pub fan_out: Option<FanOutFn>,
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    pub const fn new(path: &'static [&'static str], about: &'static str, handler: Handler) -> Self {
+        Self {
+            path,
+            aliases: &[],
+            args: &[],
+            about,
+            details: "",
+            group: Group::General,
+            device: DeviceUse::None,
+            shell_only: false,
+            scans: None,
+            check: None,
+            fan_out: None,
+            handler,
+        }
+    }
+
+    pub const fn aliases(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    pub const fn args(self, args: &'static [ArgSpec]) -> Self {
+        Self { args, ..self }
+    }
+
+    pub const fn details(self, details: &'static str) -> Self {
+        Self { details, ..self }
+    }
+
+    pub const fn group(self, group: Group) -> Self {
+        Self { group, ..self }
+    }
+
+    pub const fn device(self, device: DeviceUse) -> Self {
+        Self { device, ..self }
+    }
+
+    pub const fn shell_only(self) -> Self {
+        Self { shell_only: true, ..self }
+    }
+
+    pub const fn scans(self, scans: Scans) -> Self {
+        Self { scans: Some(scans), ..self }
+    }
+
+    pub const fn check(self, check: CheckFn) -> Self {
+        Self { check: Some(check), ..self }
+    }
+
+    pub const fn fan_out(self, fan_out: FanOutFn) -> Self {
+        Self { fan_out: Some(fan_out), ..self }
+    }
+
+    /// The words the command is typed with.
+    pub fn name(&self) -> String {
+        self.path.join(" ")
+    }
+
+    /// The command's words, followed by its arguments.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name();
+        for arg in self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+
+    /// Whether a device has to be connected for the command.
+    pub fn needs_device(&self) -> bool {
+        matches!(self.device, DeviceUse::Connected | DeviceUse::Registry)
+    }
+
+    /// Whether the command can be sent to one device with `@name`.
+    pub fn addressable(&self) -> bool {
+        matches!(self.device, DeviceUse::Connected | DeviceUse::Any)
+    }
+
+    /// Every way of typing the command's words: `path`, then each alias.
+    pub fn paths(&self) -> Vec<Vec<&'static str>> {
+        let Some((_, parent)) = self.path.split_last() else {
+            return Vec::new();
+        };
+        let mut paths = vec![self.path.to_vec()];
+        for alias in self.aliases {
+            let mut path = parent.to_vec();
+            path.push(alias);
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// How many of `words` are the command's own, if they start with it.
+    fn matched(&self, words: &[&str]) -> Option<usize> {
+        self.paths()
+            .into_iter()
+            .find(|path| words.len() >= path.len() && words[..path.len()] == path[..])
+            .map(|path| path.len())
+    }
+
+    /// Read `words` into argument values. Flags and keywords are taken by
+    /// their bare name, as typed at the prompt, or as `--name [value]` or
+    /// `--name=value`, as given on the command line; `--name` also sets a
+    /// positional argument out of turn.
+    fn walk(&self, words: &[&str]) -> Walk {
+        let mut values: HashMap<&'static str, String> = HashMap::new();
+        let mut positional = self.args.iter().filter(|arg| arg.is_positional()).peekable();
+        let mut words = words.iter();
+
+        while let Some(word) = words.next() {
+            let (name, inline) = match word.strip_prefix("--") {
+                Some(option) => match option.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (option, None),
+                },
+                None => (*word, None),
+            };
+            let named = self.args.iter().find(|arg| {
+                arg.name == name
+                    && (word.starts_with("--") || !arg.is_positional())
+                    && arg.presence != Presence::Rest
+                    && !values.contains_key(arg.name)
+            });
+            if let Some(arg) = named {
+                if arg.presence == Presence::Flag {
+                    values.insert(arg.name, arg.name.to_string());
+                    continue;
+                }
+                match inline.or_else(|| words.next().copied()) {
+                    Some(value) => values.insert(arg.name, value.to_string()),
+                    None => {
+                        return Walk { values, pending: Some(arg), next: None, unexpected: None };
+                    }
+                };
+                continue;
+            }
+
+            while positional.next_if(|arg| values.contains_key(arg.name) && arg.presence != Presence::Rest).is_some() {}
+            match positional.peek() {
+                Some(arg) if arg.presence == Presence::Rest => {
+                    let rest = values.entry(arg.name).or_default();
+                    if !rest.is_empty() {
+                        rest.push(' ');
+                    }
+                    rest.push_str(word);
+                }
+                Some(arg) => {
+                    values.insert(arg.name, word.to_string());
+                    positional.next();
+                }
+                None => {
+                    return Walk { values, pending: None, next: None, unexpected: Some(word.to_string()) };
+                }
+            }
+        }
+
+        while positional.next_if(|arg| values.contains_key(arg.name) && arg.presence != Presence::Rest).is_some() {}
+        Walk { values, pending: None, next: positional.next(), unexpected: None }
+    }
+
+    /// Check `words`, the arguments typed after the command, against its
+    /// arguments.
+    pub fn parse_args(&self, words: &[&str]) -> Result<Args> {
+        let walk = self.walk(words);
+        if let Some(word) = walk.unexpected {
+            return Err(self.usage_error(&format!("Unexpected '{}'", word)));
+        }
+        if let Some(arg) = walk.pending {
+            return Err(self.usage_error(&format!("Missing {} after '{}'", arg.kind.placeholder(), arg.name)));
+        }
+
+        self.finish_args(walk.values)
+    }
+
+    /// Fill in defaults and check the values of `values` against their kinds.
+    fn finish_args(&self, mut values: HashMap<&'static str, String>) -> Result<Args> {
+        for arg in self.args {
+            match (values.get(arg.name), arg.presence) {
+                (None, Presence::Required) => {
+                    return Err(self.usage_error(&format!("Missing <{}>", arg.name)));
+                }
+                (None, Presence::Default(value)) => {
+                    values.insert(arg.name, value.to_string());
+                }
+                (Some(value), presence) if presence != Presence::Flag => {
+                    if let Some(reason) = arg.kind.check(value) {
+                        return Err(self.usage_error(&format!("Invalid {}: {}", arg.name, reason)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Args { values })
+    }
+
+    /// For completing the word after `words`: the argument it is a value
+    /// of, the flags and keywords that may still be typed, and the values
+    /// typed so far.
+    pub fn next_word(&self, words: &[&str]) -> (Option<&'static ArgSpec>, Vec<&'static str>, Args) {
+        let walk = self.walk(words);
+        if walk.unexpected.is_some() {
+            return (None, Vec::new(), Args { values: walk.values });
+        }
+        if walk.pending.is_some() {
+            return (walk.pending, Vec::new(), Args { values: walk.values });
+        }
+
+        let names = self
+            .args
+            .iter()
+            .filter(|arg| !arg.is_positional() && !walk.values.contains_key(arg.name))
+            .map(|arg| arg.name)
+            .collect();
+        (walk.next, names, Args { values: walk.values })
+    }
+
+    fn usage_error(&self, message: &str) -> anyhow::Error {
+        CliError::Usage(format!("{}. Usage: {}", message, self.usage())).into()
+    }
+
+    fn print_help(&self) {
+        print_section(&self.usage());
+        outln!("  {}", self.about);
+        for line in self.details.lines() {
+            outln!("  {}", line);
+        }
+        if !self.aliases.is_empty() {
+            outln!("  Also: {}", self.aliases.join(", "));
+        }
+
+        if !self.args.is_empty() {
+            outln!();
+            for arg in self.args {
+                let default = match arg.presence {
+                    Presence::Default(value) => format!(" [default: {}]", value),
+                    _ => String::new(),
+                };
+                outln!("  {:<12} {}{}", arg.name, arg.help, default);
+            }
+        }
+    }
+}
+
+/// The command `words` start with, and how many of them name it.
+///
+/// The longest match wins, so `serial record stop` isn't taken for
+/// `serial record <file>`.
+pub fn find<'s>(specs: &'s [CommandSpec], words: &[&str]) -> Result<(&'s CommandSpec, usize)> {
+    if let Some(found) = specs
+        .iter()
+        .filter_map(|spec| spec.matched(words).map(|len| (spec, len)))
+        .max_by_key(|(_, len)| *len)
+    {
+        return Ok(found);
+    }
+
+    // Suggest the commands sharing the most leading words
+    let shared = |spec: &CommandSpec| spec.path.iter().zip(words).take_while(|(a, b)| a == b).count();
+    let best = specs.iter().map(shared).max().unwrap_or(0);
+    let line = words.join(" ");
+    if best == 0 {
+        return Err(CliError::Usage(format!("Unknown command '{}'. Type 'help' for a list of commands", line)).into());
+    }
+
+    let usages: Vec<String> = specs
+        .iter()
+        .filter(|spec| shared(spec) == best)
+        .map(|spec| format!("  {}", spec.usage()))
+        .collect();
+    let problem = if best == words.len() { "Incomplete" } else { "Unknown" };
+    Err(CliError::Usage(format!("{} command '{}'. Did you mean:\n{}", problem, line, usages.join("\n"))).into())
+}
+
+/// Print every command, by group.
+pub fn print_help(specs: &[CommandSpec]) {
+    print_section("Available Commands");
+    for group in Group::ALL {
+        print_section(group.title());
+        for spec in specs.iter().filter(|spec| spec.group == group) {
+            let mut usage = spec.usage();
+            for alias in spec.aliases {
+                usage.push_str(" | ");
+                usage.push_str(alias);
+            }
+            outln!("  {:<28} - {}", usage, spec.about);
+        }
+        for note in group.notes() {
+            outln!("{}", note);
+        }
+    }
+}
+
+/// Print `help <topic>`: the command `topic` names in full, or every command
+/// starting with it.
+pub fn print_command_help(specs: &[CommandSpec], topic: &[&str]) -> Result<()> {
+    if let Some(spec) = specs.iter().find(|spec| spec.paths().iter().any(|path| path[..] == topic[..])) {
+        spec.print_help();
+        return Ok(());
+    }
+
+    let starting: Vec<&CommandSpec> = specs
+        .iter()
+        .filter(|spec| spec.path.len() > topic.len() && spec.path[..topic.len()] == topic[..])
+        .collect();
+    if starting.is_empty() {
+        return Err(CliError::Usage(format!("No command '{}'. Type 'help' for a list of commands", topic.join(" "))).into());
+    }
+
+    print_section(&format!("{} Commands", topic.join(" ")));
+    for spec in starting {
+        outln!("  {:<28} - {}", spec.usage(), spec.about);
+    }
+    Ok(())
+}
+
+/// A command-line subcommand for every command that isn't shell-only.
+pub fn subcommands(specs: &'static [CommandSpec]) -> Vec<Command> {
+    let specs: Vec<&'static CommandSpec> = specs.iter().filter(|spec| !spec.shell_only).collect();
+    subcommands_under(&specs, &[])
+}
+
+fn subcommands_under(specs: &[&'static CommandSpec], prefix: &[&'static str]) -> Vec<Command> {
+    let mut words: Vec<&'static str> = Vec::new();
+    for spec in specs {
+        if spec.path.len() > prefix.len() && spec.path[..prefix.len()] == prefix[..] && !words.contains(&spec.path[prefix.len()]) {
+            words.push(spec.path[prefix.len()]);
+        }
+    }
+
+    words
+        .into_iter()
+        .map(|word| {
+            let mut path = prefix.to_vec();
+            path.push(word);
+            let children = subcommands_under(specs, &path);
+            let mut command = Command::new(word);
+
+            match specs.iter().find(|spec| spec.path == &path[..]) {
+                Some(spec) => {
+                    command = command
+                        .about(spec.about)
+                        .visible_aliases(spec.aliases.iter().copied())
+                        .args(spec.args.iter().map(clap_arg));
+                    if !children.is_empty() {
+                        command = command.args_conflicts_with_subcommands(true);
+                    }
+                }
+                None => {
+                    command = command
+                        .about(format!("{} commands", path.join(" ")))
+                        .subcommand_required(true)
+                        .arg_required_else_help(true);
+                }
+            }
+            command.subcommands(children)
+        })
+        .collect()
+}
+
+fn clap_arg(arg: &ArgSpec) -> Arg {
+    let mut help = arg.help.to_string();
+    if let Presence::Default(value) = arg.presence {
+        help.push_str(&format!(" [default: {}]", value));
+    }
+    let mut clap_arg = Arg::new(arg.name).help(help);
+    if let ArgKind::Choice(choices) = arg.kind {
+        clap_arg = clap_arg.value_parser(PossibleValuesParser::new(choices.iter().copied()));
+    }
+
+    match arg.presence {
+        Presence::Flag => clap_arg.long(arg.name).action(ArgAction::SetTrue),
+        Presence::Keyword => clap_arg.long(arg.name),
+        Presence::Rest => clap_arg.num_args(0..).trailing_var_arg(true),
+        _ if arg.long => clap_arg.long(arg.name).required(arg.presence == Presence::Required),
+        Presence::Required => clap_arg.required(true),
+        _ => clap_arg,
+    }
+}
+
+/// A command given on the command line, already parsed by clap.
+#[derive(Clone)]
+pub struct Invocation {
+    pub spec: &'static CommandSpec,
+    pub args: Args,
+    /// The command as it would be typed at the prompt, for the audit log.
+    pub line: String,
+    /// Run it even if the engagement scope refuses it.
+    pub allow_override: bool,
+}
+
+/// The subcommand in `matches` with its checked arguments.
+pub fn invocation(specs: &'static [CommandSpec], matches: &ArgMatches) -> Option<Result<Invocation>> {
+    let mut path = Vec::new();
+    let mut matches = matches;
+    while let Some((name, sub)) = matches.subcommand() {
+        path.push(name);
+        matches = sub;
+    }
+    let spec = specs.iter().find(|spec| !path.is_empty() && spec.path == &path[..])?;
+
+    let mut values = HashMap::new();
+    let mut words: Vec<String> = path.iter().map(|word| word.to_string()).collect();
+    for arg in spec.args {
+        let value = match arg.presence {
+            Presence::Flag => matches.get_flag(arg.name).then(|| arg.name.to_string()),
+            Presence::Rest => matches
+                .get_many::<String>(arg.name)
+                .map(|rest| rest.cloned().collect::<Vec<_>>().join(" "))
+                .filter(|rest| !rest.is_empty()),
+            _ => matches.get_one::<String>(arg.name).cloned(),
+        };
+        let Some(value) = value else {
+            continue;
+        };
+
+        match arg.presence {
+            Presence::Flag => words.push(arg.name.to_string()),
+            Presence::Keyword => words.extend([arg.name.to_string(), value.clone()]),
+            _ => words.push(value.clone()),
+        }
+        values.insert(arg.name, value);
+    }
+
+    let line = words.join(" ");
+    Some(spec.finish_args(values).map(|args| Invocation { spec, args, line, allow_override: false }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::cmd_handler::COMMANDS;
+
+    fn parse(line: &str) -> Result<Args> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (spec, taken) = find(COMMANDS, &words)?;
+        spec.parse_args(&words[taken..])
+    }
+
+    #[test]
+    fn positional_and_long_spellings_agree() {
+        for line in [
+            "secot attack deauth 30s",
+            "secot attack deauth --duration 30s",
+            "secot attack deauth --duration=30s",
+            "secot attack --duration 30s deauth",
+        ] {
+            let args = parse(line).unwrap();
+            assert_eq!(args.get("attack"), Some("deauth"), "{}", line);
+            assert_eq!(args.get("duration"), Some("30s"), "{}", line);
+        }
+    }
+
+    #[test]
+    fn keywords_and_flags_take_long_options() {
+        let args = parse("secot scan wifi --sort channel").unwrap();
+        assert_eq!(args.get("sort"), Some("channel"));
+        let args = parse("secot scan wifi --sort=channel").unwrap();
+        assert_eq!(args.get("sort"), Some("channel"));
+        assert!(parse("secot scan mqtt --verify").unwrap().get("verify").is_some());
+    }
+
+    #[test]
+    fn long_option_without_value_is_a_usage_error() {
+        let err = parse("secot attack deauth --duration").unwrap_err();
+        assert!(matches!(err.downcast_ref::<CliError>(), Some(CliError::Usage(_))));
+    }
+}
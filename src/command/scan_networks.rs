@@ -1,96 +1,329 @@
+use crate::{errln, outln};
+use crate::config::scope::{ScopeGuard, Target};
 use crate::models::network::DeviceInfo;
+use crate::output::formatter::print_progress;
 use crate::output::table::{create_table, FormattedTable};
+use super::scan_ports::ScanOptions;
 use anyhow::{anyhow, Result};
-use std::io::Write;
-use std::net::{IpAddr, Ipv4Addr};
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use ipnetwork::{IpNetwork, Ipv4Network};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{BTreeMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::process::Command;
+use std::time::Duration;
 use regex::Regex;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, timeout};
 
-pub async fn run_network_scan(cidr: &str, output_format: &str) -> Result<()> {
-    println!("Scanning network {}...", cidr);
+/// Ports a host is looked for on when no spec is given.
+pub const DISCOVERY_PORTS: &[u16] = &[22, 23, 80, 443, 1883, 8080];
 
-    // Parse CIDR notation
-    let parts: Vec<&str> = cidr.split('/').collect();
-    if parts.len() != 2 {
-        return Err(anyhow!("Invalid CIDR format. Expected format: 192.168.1.0/24"));
-    }
+/// The most addresses a sweep covers unless told otherwise: a /16.
+pub const DEFAULT_HOST_LIMIT: usize = 65536;
 
-    let ip_str = parts[0];
-    let prefix_len: u8 = parts[1].parse()?;
+/// Roughly how many progress dots each phase of a sweep prints.
+const PROGRESS_DOTS: usize = 40;
 
-    if prefix_len > 32 {
-        return Err(anyhow!("Invalid prefix length. Must be between 0 and 32"));
-    }
+/// Hostname lookups run at once.
+const HOSTNAME_LOOKUPS: usize = 16;
 
-    // For simplicity, we'll just scan a few IPs in the subnet
-    let base_ip: Ipv4Addr = ip_str.parse()?;
-    let base_ip_u32 = u32::from(base_ip);
-    let mask = !0u32 << (32 - prefix_len);
-    let network = base_ip_u32 & mask;
+/// Pause after a failed receive on the ICMP socket before trying again.
+const RECEIVE_BACKOFF: Duration = Duration::from_millis(10);
 
-    let mut devices = Vec::new();
+/// What a host sweep sends and how much of it.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// Connects in flight at once, and echo requests sent at a time
+    pub scan: ScanOptions,
+    /// TCP ports tried on hosts that didn't answer an echo request
+    pub ports: Vec<u16>,
+    /// The most addresses to sweep
+    pub limit: usize,
+}
 
-    // Scan a limited number of IPs for demonstration
-    let max_hosts = std::cmp::min(1 << (32 - prefix_len), 20); // Limit to 20 hosts max
+/// Find the hosts of `cidr` that answer an ICMP echo or a TCP connect, and print them.
+pub async fn run_network_scan(
+    cidr: &str,
+    options: &DiscoveryOptions,
+    output_format: &str,
+    scope: &ScopeGuard<'_>,
+) -> Result<Vec<DeviceInfo>> {
+    let network: Ipv4Network = cidr
+        .parse()
+        .map_err(|_| anyhow!("Invalid CIDR format. Expected format: 192.168.1.0/24"))?;
+    let network = Ipv4Network::new(network.network(), network.prefix())?;
+    scope.authorize(&format!("scan network {}", cidr), &[Target::Network(IpNetwork::V4(network))])?;
 
-    for i in 1..max_hosts {
-        let ip_u32 = network + i;
-        let ip = Ipv4Addr::from(ip_u32);
+    // Point-to-point and single-host networks have no network or broadcast address
+    let whole = network.prefix() >= 31;
+    let addresses = (1u64 << (32 - network.prefix())) - if whole { 0 } else { 2 };
+    if addresses > options.limit as u64 {
+        return Err(anyhow!(
+            "{} has {} addresses, more than the limit of {}; raise it with limit <count> or sweep a smaller network",
+            network,
+            addresses,
+            options.limit
+        ));
+    }
+    let hosts: Vec<Ipv4Addr> = network
+        .iter()
+        .filter(|ip| whole || (*ip != network.network() && *ip != network.broadcast()))
+        .collect();
 
-        print!(".");
-        std::io::stdout().flush().unwrap();
+    errln!("Scanning network {} ({} addresses)...", network, hosts.len());
 
-        if ping_host(&ip.to_string()) {
-            let hostname = get_hostname(&ip.to_string()).unwrap_or_else(|| "unknown".to_string());
+    let mut found: BTreeMap<Ipv4Addr, String> = BTreeMap::new();
+    match Pinger::open() {
+        Some(pinger) => {
+            errln!("Sending ICMP echo requests...");
+            for ip in pinger.sweep(&hosts, &options.scan).await {
+                found.insert(ip, "ICMP echo reply".to_string());
+            }
+            errln!("\n{} hosts answered an echo request", found.len());
+        }
+        None => errln!("ICMP sockets are not permitted here; finding hosts by TCP only"),
+    }
+
+    let silent: Vec<Ipv4Addr> = hosts.iter().copied().filter(|ip| !found.contains_key(ip)).collect();
+    if !silent.is_empty() && !options.ports.is_empty() {
+        errln!("Trying TCP ports {:?} on {} hosts...", options.ports, silent.len());
+        let before = found.len();
+        found.extend(tcp_sweep(&silent, options).await);
+        errln!("\n{} hosts answered a TCP connect", found.len() - before);
+    }
 
-            devices.push(DeviceInfo {
+    let mut devices: Vec<DeviceInfo> = stream::iter(found)
+        .map(|(ip, evidence)| async move {
+            let hostname = tokio::task::spawn_blocking(move || get_hostname(&ip.to_string()))
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| "unknown".to_string());
+            DeviceInfo {
                 ip: IpAddr::V4(ip),
                 hostname,
-            });
-        }
-    }
+                evidence: Some(evidence),
+            }
+        })
+        .buffer_unordered(HOSTNAME_LOOKUPS)
+        .collect()
+        .await;
+    devices.sort_by_key(|device| device.ip);
 
-    println!("\nScan complete! Found {} devices", devices.len());
+    errln!("Scan complete! Found {} devices", devices.len());
 
     // Format and display the results
     if output_format == "json" {
         let json = serde_json::to_string_pretty(&devices)?;
-        println!("{}", json);
+        outln!("{}", json);
     } else {
-        match create_table(&devices, &["ip", "hostname"]) {
+        match create_table(&devices, &["ip", "hostname", "evidence"]) {
             Ok(table) => {
                 let formatted_table = FormattedTable::new("Discovered Devices", table);
-                println!("{}", formatted_table);
+                outln!("{}", formatted_table);
             },
             Err(e) => {
-                println!("Error creating table: {}", e);
+                outln!("Error creating table: {}", e);
                 // Fallback to simple output
-                println!("Discovered Devices:");
+                outln!("Discovered Devices:");
                 for device in &devices {
-                    println!("  IP: {}, Hostname: {}", device.ip, device.hostname);
+                    outln!("  {}", device);
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(devices)
 }
 
-fn ping_host(ip: &str) -> bool {
-    #[cfg(target_os = "windows")]
-    let output = Command::new("ping")
-        .args(&["-n", "1", "-w", "500", ip])
-        .output();
+/// Connect to each host's ports, several hosts at a time, returning the hosts that answered.
+async fn tcp_sweep(hosts: &[Ipv4Addr], options: &DiscoveryOptions) -> Vec<(Ipv4Addr, String)> {
+    // Every port of a host is tried at once, so the slots are shared out by host
+    let slots = Semaphore::new((options.scan.concurrency / options.ports.len()).max(1));
+    let mut probes: FuturesUnordered<_> = hosts
+        .iter()
+        .map(|&ip| {
+            let slots = &slots;
+            async move {
+                let _slot = slots.acquire().await;
+                let evidence = probe_host(ip, &options.ports, &options.scan).await;
+                sleep(options.scan.delay).await;
+                (ip, evidence)
+            }
+        })
+        .collect();
 
-    #[cfg(not(target_os = "windows"))]
-    let output = Command::new("ping")
-        .args(&["-c", "1", "-W", "1", ip])
-        .output();
+    let dot_every = (hosts.len() / PROGRESS_DOTS).max(1);
+    let mut done = 0;
+    let mut found = Vec::new();
+    while let Some((ip, evidence)) = probes.next().await {
+        found.extend(evidence.map(|evidence| (ip, evidence)));
+        done += 1;
+        if done % dot_every == 0 {
+            print_progress(".");
+        }
+    }
+    found
+}
 
-    match output {
-        Ok(output) => output.status.success(),
-        Err(_) => false,
+/// Connect to all of `ports` on `ip` at once; the first to answer shows the host is up.
+async fn probe_host(ip: Ipv4Addr, ports: &[u16], options: &ScanOptions) -> Option<String> {
+    let mut probes: FuturesUnordered<_> = ports
+        .iter()
+        .map(|&port| probe_port(SocketAddr::new(IpAddr::V4(ip), port), options))
+        .collect();
+    while let Some(evidence) = probes.next().await {
+        if evidence.is_some() {
+            return evidence;
+        }
     }
+    None
+}
+
+/// Whether anything answered a connect to `addr`: a refusal comes from a live host too.
+async fn probe_port(addr: SocketAddr, options: &ScanOptions) -> Option<String> {
+    for _ in 0..=options.retries {
+        match timeout(options.timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => return Some(format!("TCP {} open", addr.port())),
+            Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+                return Some(format!("TCP {} refused", addr.port()));
+            }
+            // Unreachable: no one to retry
+            Ok(Err(_)) => return None,
+            Err(_) => {}
+        }
+    }
+    None
+}
+
+/// Sends ICMP echo requests and collects the replies.
+struct Pinger {
+    socket: UdpSocket,
+    /// A raw socket, which sees IP headers and every process's replies
+    raw: bool,
+    id: u16,
+}
+
+impl Pinger {
+    /// An ICMP socket, if the system lets this process have one.
+    ///
+    /// Datagram ICMP sockets work unprivileged where `net.ipv4.ping_group_range`
+    /// allows them; raw sockets need root or `CAP_NET_RAW`.
+    fn open() -> Option<Self> {
+        [(Type::DGRAM, false), (Type::RAW, true)].into_iter().find_map(|(kind, raw)| {
+            let socket = Socket::new(Domain::IPV4, kind, Some(Protocol::ICMPV4)).ok()?;
+            socket.set_nonblocking(true).ok()?;
+            let socket = UdpSocket::from_std(socket.into()).ok()?;
+            Some(Self {
+                socket,
+                raw,
+                id: std::process::id() as u16,
+            })
+        })
+    }
+
+    /// Send every host an echo request, `concurrency` at a time, and return those that reply.
+    ///
+    /// Hosts still silent `timeout` after the last request are asked again, up to `retries` times.
+    async fn sweep(&self, hosts: &[Ipv4Addr], options: &ScanOptions) -> HashSet<Ipv4Addr> {
+        let targets: HashSet<Ipv4Addr> = hosts.iter().copied().collect();
+        let mut answered = HashSet::new();
+        let batch = options.concurrency.max(1);
+        let dot_every = (hosts.len() / PROGRESS_DOTS).max(1);
+
+        for attempt in 0..=options.retries {
+            let pending: Vec<Ipv4Addr> = hosts.iter().copied().filter(|ip| !answered.contains(ip)).collect();
+            if pending.is_empty() {
+                break;
+            }
+
+            let send = async {
+                for (index, ip) in pending.iter().enumerate() {
+                    let request = echo_request(self.id, attempt as u16);
+                    let _ = self.socket.send_to(&request, SocketAddr::new(IpAddr::V4(*ip), 0)).await;
+                    if attempt == 0 && (index + 1) % dot_every == 0 {
+                        print_progress(".");
+                    }
+                    if (index + 1) % batch == 0 {
+                        sleep(options.delay.max(Duration::from_millis(1))).await;
+                    }
+                }
+                sleep(options.timeout).await;
+            };
+            let receive = async {
+                let mut buf = [0u8; 1500];
+                loop {
+                    match self.socket.recv_from(&mut buf).await {
+                        Ok((len, from)) => {
+                            if let IpAddr::V4(from) = from.ip() {
+                                if targets.contains(&from) && self.is_reply(&buf[..len]) {
+                                    answered.insert(from);
+                                }
+                            }
+                        }
+                        // e.g. an ICMP error about an earlier request; don't starve the sends
+                        Err(_) => sleep(RECEIVE_BACKOFF).await,
+                    }
+                }
+            };
+            tokio::select! {
+                _ = send => {}
+                _ = receive => {}
+            }
+        }
+        answered
+    }
+
+    /// Whether `packet` is an echo reply to this pinger.
+    fn is_reply(&self, packet: &[u8]) -> bool {
+        match icmp_message(packet) {
+            // The kernel rewrites the identifier of a datagram socket's requests, and only
+            // hands it its own replies
+            [0, 0, _, _, id_high, id_low, ..] => !self.raw || u16::from_be_bytes([*id_high, *id_low]) == self.id,
+            _ => false,
+        }
+    }
+}
+
+/// The ICMP message in a received packet, past the IPv4 header if there is
+/// one.
+///
+/// Raw sockets always pass the header along, and so do datagram sockets on
+/// macOS, while Linux strips it from those. An ICMP message can't start with
+/// version nibble 4 (there is no type 0x40-0x4f), so the first byte tells
+/// which it is.
+fn icmp_message(packet: &[u8]) -> &[u8] {
+    match packet.first() {
+        Some(byte) if byte >> 4 == 4 => {
+            let header_len = usize::from(byte & 0x0f) * 4;
+            if header_len < 20 {
+                return &[];
+            }
+            packet.get(header_len..).unwrap_or_default()
+        }
+        _ => packet,
+    }
+}
+
+/// An ICMP echo request.
+fn echo_request(id: u16, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![8, 0, 0, 0];
+    packet.extend(id.to_be_bytes());
+    packet.extend(sequence.to_be_bytes());
+    packet.extend(b"secot-discovery");
+
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    packet[2..4].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    packet
 }
 
 fn get_hostname(ip: &str) -> Option<String> {
@@ -128,4 +361,45 @@ fn get_hostname(ip: &str) -> Option<String> {
         },
         Err(_) => None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_reply() -> Vec<u8> {
+        let mut reply = echo_request(0x1234, 7);
+        reply[0] = 0;
+        reply
+    }
+
+    fn ipv4_header(options: usize) -> Vec<u8> {
+        let mut header = vec![0; 20 + options];
+        header[0] = 0x40 | (5 + options / 4) as u8;
+        header[9] = 1;
+        header
+    }
+
+    #[test]
+    fn bare_icmp_is_left_alone() {
+        let reply = echo_reply();
+        assert_eq!(icmp_message(&reply), &reply[..]);
+    }
+
+    #[test]
+    fn ipv4_header_is_stripped() {
+        let reply = echo_reply();
+        for options in [0, 4, 40] {
+            let mut packet = ipv4_header(options);
+            packet.extend(&reply);
+            assert_eq!(icmp_message(&packet), &reply[..], "{} bytes of options", options);
+        }
+    }
+
+    #[test]
+    fn truncated_or_bogus_headers_yield_nothing() {
+        assert!(icmp_message(&[]).is_empty());
+        assert!(icmp_message(&ipv4_header(0)[..12]).is_empty());
+        assert!(icmp_message(&[0x44, 0, 0, 0, 0, 0, 0, 0]).is_empty());
+    }
+}
@@ -1,60 +1,208 @@
+use crate::{errln, outln};
+use crate::config::scope::{ScopeGuard, Target};
 use crate::models::port::{IpAddress, PortScanResults, PortStatus};
-use crate::output::formatter::format_output;
-use anyhow::Result;
-use std::io::Write;
-use std::net::IpAddr;
+use crate::output::formatter::{format_output, print_progress};
+use anyhow::{anyhow, Result};
+use super::fingerprint::{fingerprint, Fingerprint};
+use super::scan_udp::{self, scan_udp_port};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use tokio::net::TcpStream;
-use tokio::time::timeout;
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, timeout};
 use std::time::Duration;
 
-pub async fn run_port_scan(ip_str: &str, output_format: &str) -> Result<()> {
+/// Names of the timing profiles, as `timing` takes them.
+pub const TIMINGS: &[&str] = &["polite", "normal", "aggressive"];
+
+/// Roughly how many progress dots a scan prints, however many ports it checks.
+const PROGRESS_DOTS: usize = 40;
+
+/// How hard a port scan pushes the target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// A few connects at a time, spaced out, for fragile embedded stacks.
+    Polite,
+    #[default]
+    Normal,
+    /// Many short connects at once, for hosts on the local network.
+    Aggressive,
+}
+
+impl FromStr for Timing {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "polite" => Ok(Timing::Polite),
+            "normal" => Ok(Timing::Normal),
+            "aggressive" => Ok(Timing::Aggressive),
+            _ => Err(anyhow!("Unknown timing '{}'; use {}", name, TIMINGS.join(", "))),
+        }
+    }
+}
+
+/// What a port scan sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Connect to each port
+    #[default]
+    Tcp,
+    /// Send each port a probe its service answers
+    Udp,
+}
+
+impl Transport {
+    fn name(self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        }
+    }
+}
+
+/// How one port scan connects; start from a [`Timing`] profile.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanOptions {
+    /// Connects in flight at once
+    pub concurrency: usize,
+    /// How long a connect may take before the port counts as timed out
+    pub timeout: Duration,
+    /// Further connects to a port after the first failed without an answer
+    pub retries: u32,
+    /// Pause after each port before its slot is reused
+    pub delay: Duration,
+}
+
+impl From<Timing> for ScanOptions {
+    fn from(timing: Timing) -> Self {
+        match timing {
+            Timing::Polite => Self {
+                concurrency: 4,
+                timeout: Duration::from_secs(3),
+                retries: 2,
+                delay: Duration::from_millis(100),
+            },
+            Timing::Normal => Self {
+                concurrency: 100,
+                timeout: Duration::from_secs(1),
+                retries: 1,
+                delay: Duration::ZERO,
+            },
+            Timing::Aggressive => Self {
+                concurrency: 500,
+                timeout: Duration::from_millis(300),
+                retries: 0,
+                delay: Duration::ZERO,
+            },
+        }
+    }
+}
+
+/// Probe each of `ports` on `ip_str` over `transport` and print the results.
+pub async fn run_port_scan(
+    ip_str: &str,
+    ports: &[u16],
+    transport: Transport,
+    options: ScanOptions,
+    output_format: &str,
+    scope: &ScopeGuard<'_>,
+) -> Result<PortScanResults> {
     let ip: IpAddr = ip_str.parse()?;
+    scope.authorize(&format!("scan ports on {}", ip), &[Target::Host(ip)])?;
     let ip_address = IpAddress(ip);
 
-    println!("Scanning ports on {}...", ip);
+    errln!(
+        "Scanning {} {} ports on {}, {} at a time, {} ms timeout...",
+        ports.len(),
+        transport.name().to_uppercase(),
+        ip,
+        options.concurrency,
+        options.timeout.as_millis()
+    );
 
-    // Common ports to scan
-    let common_ports = vec![
-        21, 22, 23, 25, 53, 80, 110, 143, 443, 465, 587, 993, 995, 1883, 3306, 5432, 8080, 8883
-    ];
+    // Every probe is polled from this task, so its output stays with a background job
+    let slots = Semaphore::new(options.concurrency.max(1));
+    let mut probes: FuturesUnordered<_> = ports
+        .iter()
+        .map(|&port| {
+            let slots = &slots;
+            async move {
+                let _slot = slots.acquire().await;
+                let addr = SocketAddr::new(ip, port);
+                let (status, evidence, found) = match transport {
+                    Transport::Tcp => match scan_port(addr, &options).await {
+                        Ok(stream) => ("open", None, fingerprint(stream, addr, options.timeout).await),
+                        Err(status) => (status, None, None),
+                    },
+                    Transport::Udp => {
+                        let (status, evidence) = scan_udp_port(addr, &options).await;
+                        (status, Some(evidence), None)
+                    }
+                };
+                sleep(options.delay).await;
+                (port, status, evidence, found.unwrap_or_default())
+            }
+        })
+        .collect();
 
-    let mut results = Vec::new();
-
-    for &port in &common_ports {
-        let status = scan_port(ip, port).await;
-        let service = get_service_name(port);
+    let dot_every = (ports.len() / PROGRESS_DOTS).max(1);
+    let mut results = Vec::with_capacity(ports.len());
 
+    while let Some((port, status, evidence, found)) = probes.next().await {
+        let Fingerprint { service, product, version, banner, confidence } = found;
+        let service = service.unwrap_or_else(|| match transport {
+            Transport::Tcp => get_service_name(port),
+            Transport::Udp => scan_udp::service_name(port).unwrap_or("unknown"),
+        });
         results.push(PortStatus {
             port,
             status: status.to_string(),
             service: Some(service.to_string()),
+            evidence,
+            product,
+            version,
+            banner,
+            confidence,
         });
 
-        print!(".");
-        std::io::stdout().flush().unwrap();
+        if results.len() % dot_every == 0 {
+            print_progress(".");
+        }
     }
+    results.sort_by_key(|result| result.port);
 
-    println!("\nScan complete!");
+    errln!("\nScan complete!");
 
     let result = PortScanResults {
         ip: ip_address,
+        protocol: transport.name().to_string(),
         results,
     };
 
     // Format and display the result
     let output = format_output(&result, output_format)?;
-    println!("{}", output);
+    outln!("{}", output);
 
-    Ok(())
+    Ok(result)
 }
 
-async fn scan_port(ip: IpAddr, port: u16) -> &'static str {
-    let addr = format!("{}:{}", ip, port);
-    match timeout(Duration::from_secs(2), TcpStream::connect(&addr)).await {
-        Ok(Ok(_)) => "open",
-        Ok(Err(_)) => "closed",
-        Err(_) => "timeout",
+/// Connect to `addr`, trying again while nothing definite comes back.
+///
+/// The connection when the port is open, otherwise its status.
+async fn scan_port(addr: SocketAddr, options: &ScanOptions) -> Result<TcpStream, &'static str> {
+    let mut status = "timeout";
+    for _ in 0..=options.retries {
+        match timeout(options.timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => return Err("closed"),
+            Ok(Err(_)) => status = "closed",
+            Err(_) => status = "timeout",
+        }
     }
+    Err(status)
 }
 
 fn get_service_name(port: u16) -> &'static str {
@@ -0,0 +1,261 @@
+//! UDP probes for `scan ports <ip> udp`.
+//!
+//! UDP has no handshake, so each port is sent a datagram its service is
+//! expected to answer. A reply means open, an ICMP port unreachable means
+//! closed, and silence means open|filtered: the service may be ignoring the
+//! probe, or a firewall may be dropping it.
+
+use super::scan_ports::ScanOptions;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+/// Transaction id of the DNS query and message id of the CoAP request.
+const PROBE_ID: [u8; 2] = [0x5e, 0xc0];
+
+/// The community string the SNMP probe tries.
+const SNMP_COMMUNITY: &str = "public";
+
+/// What to send to one UDP service and how to recognise its answer.
+struct Probe {
+    port: u16,
+    service: &'static str,
+    request: fn(SocketAddr) -> Vec<u8>,
+    /// Describes a reply from the service, or `None` if it isn't one.
+    recognise: fn(&[u8]) -> Option<String>,
+    /// Sent after a reply, to release whatever the request set up.
+    farewell: &'static [u8],
+}
+
+static PROBES: &[Probe] = &[
+    Probe { port: 53, service: "DNS", request: dns_request, recognise: dns_reply, farewell: &[] },
+    Probe { port: 161, service: "SNMP", request: snmp_request, recognise: snmp_reply, farewell: &[] },
+    // MQTT-SN DISCONNECT
+    Probe { port: 1883, service: "MQTT-SN", request: mqtt_sn_request, recognise: mqtt_sn_reply, farewell: &[0x02, 0x18] },
+    Probe { port: 1900, service: "SSDP", request: ssdp_request, recognise: ssdp_reply, farewell: &[] },
+    Probe { port: 5353, service: "mDNS", request: mdns_request, recognise: mdns_reply, farewell: &[] },
+    Probe { port: 5683, service: "CoAP", request: coap_request, recognise: coap_reply, farewell: &[] },
+];
+
+/// The ports a UDP scan checks when no spec is given: those with a probe.
+pub fn udp_ports() -> Vec<u16> {
+    PROBES.iter().map(|probe| probe.port).collect()
+}
+
+/// The service probed on UDP `port`, if there is a probe for it.
+pub fn service_name(port: u16) -> Option<&'static str> {
+    find_probe(port).map(|probe| probe.service)
+}
+
+/// Probe UDP `addr`, returning its status and what decided it.
+pub async fn scan_udp_port(addr: SocketAddr, options: &ScanOptions) -> (&'static str, String) {
+    let probe = find_probe(addr.port());
+    let request = probe.map(|probe| (probe.request)(addr)).unwrap_or_default();
+
+    let local: IpAddr = match addr {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    // A connected socket is told about ICMP port unreachable as a refused receive
+    let socket = match UdpSocket::bind(SocketAddr::new(local, 0)).await {
+        Ok(socket) => socket,
+        Err(e) => return ("open|filtered", format!("Could not open a UDP socket: {}", e)),
+    };
+    if let Err(e) = socket.connect(addr).await {
+        return ("open|filtered", format!("Could not reach the host: {}", e));
+    }
+
+    let attempts = options.retries + 1;
+    let mut buf = [0u8; 2048];
+    for _ in 0..attempts {
+        if let Err(e) = socket.send(&request).await {
+            if e.kind() == ErrorKind::ConnectionRefused {
+                return ("closed", "ICMP port unreachable".to_string());
+            }
+            return ("open|filtered", format!("Send failed: {}", e));
+        }
+
+        match timeout(options.timeout, socket.recv(&mut buf)).await {
+            Ok(Ok(len)) => {
+                let reply = &buf[..len];
+                let evidence = match probe {
+                    Some(probe) => {
+                        if !probe.farewell.is_empty() {
+                            let _ = socket.send(probe.farewell).await;
+                        }
+                        (probe.recognise)(reply)
+                            .unwrap_or_else(|| format!("{}-byte reply that isn't {}", len, probe.service))
+                    }
+                    None => format!("{}-byte reply to an empty datagram", len),
+                };
+                return ("open", evidence);
+            }
+            Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+                return ("closed", "ICMP port unreachable".to_string());
+            }
+            Ok(Err(e)) => return ("open|filtered", format!("Receive failed: {}", e)),
+            Err(_) => continue,
+        }
+    }
+
+    let what = match probe {
+        Some(probe) => format!("{} probe", probe.service),
+        None => "empty datagram".to_string(),
+    };
+    let plural = if attempts == 1 { "" } else { "s" };
+    ("open|filtered", format!("No reply to {} {}{}", attempts, what, plural))
+}
+
+fn find_probe(port: u16) -> Option<&'static Probe> {
+    PROBES.iter().find(|probe| probe.port == port)
+}
+
+/// A DNS query for the root name servers.
+fn dns_request(_: SocketAddr) -> Vec<u8> {
+    let mut query = dns_header(PROBE_ID, 0x0100);
+    query.push(0);
+    query.extend([0x00, 0x02, 0x00, 0x01]); // NS, IN
+    query
+}
+
+fn dns_reply(reply: &[u8]) -> Option<String> {
+    let (rcode, answers) = dns_answer(reply, PROBE_ID)?;
+    Some(format!("DNS reply: {}, {} answers", rcode, answers))
+}
+
+/// A DNS-SD query for the services the host advertises, asking for a unicast answer.
+fn mdns_request(_: SocketAddr) -> Vec<u8> {
+    let mut query = dns_header([0, 0], 0x0000);
+    for label in ["_services", "_dns-sd", "_udp", "local"] {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend([0x00, 0x0c, 0x80, 0x01]); // PTR, unicast response + IN
+    query
+}
+
+fn mdns_reply(reply: &[u8]) -> Option<String> {
+    let (_, answers) = dns_answer(reply, [0, 0])?;
+    Some(format!("mDNS reply with {} service records", answers))
+}
+
+/// A DNS header for one question.
+fn dns_header(id: [u8; 2], flags: u16) -> Vec<u8> {
+    let mut header = id.to_vec();
+    header.extend(flags.to_be_bytes());
+    header.extend([0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    header
+}
+
+/// The response code and answer count of a DNS response to query `id`.
+fn dns_answer(reply: &[u8], id: [u8; 2]) -> Option<(&'static str, u16)> {
+    if reply.len() < 12 || reply[..2] != id || reply[2] & 0x80 == 0 {
+        return None;
+    }
+    let rcode = match reply[3] & 0x0f {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        _ => "error",
+    };
+    Some((rcode, u16::from_be_bytes([reply[6], reply[7]])))
+}
+
+/// An SSDP search for every device and service.
+fn ssdp_request(addr: SocketAddr) -> Vec<u8> {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n",
+        addr
+    )
+    .into_bytes()
+}
+
+fn ssdp_reply(reply: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(reply);
+    if !text.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+    let server = text
+        .lines()
+        .find_map(|line| line.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("server")))
+        .map(|(_, value)| value.trim().to_string());
+    Some(match server {
+        Some(server) => format!("SSDP reply from {}", server),
+        None => "SSDP reply".to_string(),
+    })
+}
+
+/// The OID of sysDescr.0, 1.3.6.1.2.1.1.1.0.
+const SYS_DESCR: [u8; 8] = [0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00];
+
+/// An SNMPv1 GetRequest for sysDescr.0.
+fn snmp_request(_: SocketAddr) -> Vec<u8> {
+    let varbind = [&[0x30, 0x0c, 0x06, 0x08][..], &SYS_DESCR, &[0x05, 0x00]].concat();
+    let varbinds = [&[0x30, varbind.len() as u8][..], &varbind].concat();
+    // request-id 1, error-status 0, error-index 0
+    let pdu_body = [&[0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00][..], &varbinds].concat();
+    let pdu = [&[0xa0, pdu_body.len() as u8][..], &pdu_body].concat();
+    let community = [&[0x04, SNMP_COMMUNITY.len() as u8][..], SNMP_COMMUNITY.as_bytes()].concat();
+    let message = [&[0x02, 0x01, 0x00][..], &community, &pdu].concat();
+    [&[0x30, message.len() as u8][..], &message].concat()
+}
+
+fn snmp_reply(reply: &[u8]) -> Option<String> {
+    if reply.first() != Some(&0x30) || !reply.contains(&0xa2) {
+        return None;
+    }
+    let descr = reply
+        .windows(SYS_DESCR.len())
+        .position(|window| window == SYS_DESCR)
+        .and_then(|at| {
+            let value = &reply[at + SYS_DESCR.len()..];
+            let len = *value.get(1)? as usize;
+            (value.first() == Some(&0x04) && len < 0x80)
+                .then(|| value.get(2..2 + len))
+                .flatten()
+        })
+        .map(|text| String::from_utf8_lossy(text).trim().to_string());
+    Some(match descr {
+        Some(descr) => format!("SNMP reply to community '{}': {}", SNMP_COMMUNITY, descr),
+        None => format!("SNMP reply to community '{}'", SNMP_COMMUNITY),
+    })
+}
+
+/// A confirmable CoAP GET of /.well-known/core.
+fn coap_request(_: SocketAddr) -> Vec<u8> {
+    let mut request = vec![0x40, 0x01, PROBE_ID[0], PROBE_ID[1]];
+    request.push(0xb0 | 11); // Uri-Path, 11 bytes
+    request.extend(b".well-known");
+    request.push(0x04); // Uri-Path, 4 bytes
+    request.extend(b"core");
+    request
+}
+
+fn coap_reply(reply: &[u8]) -> Option<String> {
+    if reply.len() < 4 || reply[0] >> 6 != 1 || reply[2..4] != PROBE_ID {
+        return None;
+    }
+    let code = reply[1];
+    Some(format!("CoAP {}.{:02} reply", code >> 5, code & 0x1f))
+}
+
+/// An MQTT-SN CONNECT with a clean session.
+fn mqtt_sn_request(_: SocketAddr) -> Vec<u8> {
+    let client_id = b"secot-probe";
+    let mut request = vec![(6 + client_id.len()) as u8, 0x04, 0x04, 0x01, 0x00, 0x3c];
+    request.extend(client_id);
+    request
+}
+
+fn mqtt_sn_reply(reply: &[u8]) -> Option<String> {
+    match reply {
+        [_, 0x05, code, ..] => Some(format!("MQTT-SN CONNACK, return code {}", code)),
+        [_, 0x02, gateway, ..] => Some(format!("MQTT-SN GWINFO from gateway {}", gateway)),
+        _ => None,
+    }
+}
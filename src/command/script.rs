@@ -0,0 +1,351 @@
+//! Script files: REPL commands run one after another.
+//!
+//! A script is run with `source <file>` in the REPL or `--script <file>` on
+//! the command line. Each line is one REPL command and goes through
+//! [`handle_command`], like a typed one. A few lines are handled by the
+//! script runner itself:
+//!
+//! ```text
+//! # Site survey
+//! let broker = 192.168.56.20
+//! on-error continue
+//! secot status
+//! secot scan wifi
+//! sleep 5s
+//! scan ports ${broker}
+//! source more.secot site=north
+//! ```
+//!
+//! Only whole lines can be comments. `on-error continue` keeps going after a
+//! failed command, `on-error abort` (the default) stops the script there;
+//! `exit` ends it early. `sleep` takes the same durations as `secot attack`.
+//!
+//! `$name` and `${name}` are replaced by the variable's value. Variables come
+//! from `let` (spaces around `=` are optional), from `name=value` arguments to
+//! `source`, or from `--var` on the command line. A summary is printed once
+//! the script ends.
+//!
+//! A `source` within a script finds its file relative to that script.
+//!
+//! A line ending in `&` starts a background job and goes straight on; with
+//! `--script`, each job's output is shown once the script and the job have
+//! ended.
+
+use anyhow::{anyhow, Context, Result};
+use crate::outln;
+use crate::config::scope::Scope;
+use crate::error::{exit_code, exit_code_for};
+use crate::models::attack::parse_duration;
+use crate::output::formatter::{print_error, print_info, print_success, print_warning};
+use crate::output::table::FormattedTable;
+use crate::serial::registry::DeviceRegistry;
+use prettytable::{Cell, Row, Table};
+use serde::Serialize;
+use std::collections::HashMap;
+use futures::future::BoxFuture;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use super::cmd_handler::handle_command;
+use super::jobs::Jobs;
+
+/// How deeply scripts may `source` each other.
+const MAX_SOURCE_DEPTH: usize = 8;
+
+/// What to do when a command fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnError {
+    Abort,
+    Continue,
+}
+
+/// A command that failed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptFailure {
+    pub file: String,
+    pub line: usize,
+    pub command: String,
+    pub error: String,
+    #[serde(skip)]
+    exit_code: u8,
+}
+
+/// Outcome of a script run.
+#[derive(Debug, Default, Serialize)]
+pub struct ScriptSummary {
+    pub file: String,
+    pub commands: usize,
+    pub succeeded: usize,
+    pub failures: Vec<ScriptFailure>,
+    /// Whether a failure stopped the script early.
+    pub aborted: bool,
+    pub elapsed_secs: f64,
+}
+
+impl ScriptSummary {
+    /// Exit code for the run: that of the first failure, if any.
+    pub fn exit_code(&self) -> u8 {
+        self.failures.first().map_or(exit_code::SUCCESS, |failure| failure.exit_code)
+    }
+
+    pub fn print(&self, output_format: &str) -> Result<()> {
+        if output_format == "json" {
+            outln!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+
+        let message = format!(
+            "{}: {} of {} commands succeeded in {:.1} s",
+            self.file, self.succeeded, self.commands, self.elapsed_secs
+        );
+        if self.failures.is_empty() {
+            print_success(&message);
+            return Ok(());
+        }
+        print_warning(&message);
+        if self.aborted {
+            print_warning("Stopped at the first failure (on-error abort)");
+        }
+
+        let mut table = Table::new();
+        table.add_row(Row::new(vec![Cell::new("line"), Cell::new("command"), Cell::new("error")]));
+        for failure in &self.failures {
+            table.add_row(Row::new(vec![
+                Cell::new(&format!("{}:{}", failure.file, failure.line)),
+                Cell::new(&failure.command),
+                Cell::new(&failure.error),
+            ]));
+        }
+        outln!("{}", FormattedTable::new("Failed Commands", table));
+        Ok(())
+    }
+}
+
+/// Run the script in `path` with `vars` defined.
+///
+/// `set output` in the script changes `output_format` for the rest of the
+/// session. Failing commands are reported in the summary; only a script that
+/// can't be read is an error.
+pub async fn run_script(
+    path: &str,
+    vars: &HashMap<String, String>,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope,
+) -> Result<ScriptSummary> {
+    let started = Instant::now();
+    let mut summary = ScriptSummary {
+        file: path.to_string(),
+        ..Default::default()
+    };
+    let session = Session { devices, jobs, scope };
+    run_file(path, vars.clone(), output_format, session, 0, &mut summary).await?;
+    summary.elapsed_secs = started.elapsed().as_secs_f64();
+    Ok(summary)
+}
+
+/// Parse `name=value` script arguments.
+pub fn parse_vars<S: AsRef<str>>(args: &[S]) -> Result<HashMap<String, String>> {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            match arg.split_once('=') {
+                Some((name, value)) if is_var_name(name) => Ok((name.to_string(), value.to_string())),
+                _ => Err(anyhow!("Invalid variable '{}'; use name=value", arg)),
+            }
+        })
+        .collect()
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace `$name` and `${name}` in `line` with their values.
+fn expand(line: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut expanded = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or_else(|| anyhow!("Unclosed '${{' in '{}'", line))?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+
+        // A lone '$' stays as it is
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            let value = vars.get(name).ok_or_else(|| anyhow!("Undefined variable '{}'", name))?;
+            expanded.push_str(value);
+        }
+        rest = after;
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// What the script's commands run against.
+#[derive(Clone, Copy)]
+struct Session<'a> {
+    devices: &'a Arc<DeviceRegistry>,
+    jobs: &'a Arc<Jobs>,
+    scope: &'a Scope,
+}
+
+fn run_file<'a>(
+    path: &'a str,
+    mut vars: HashMap<String, String>,
+    output_format: &'a mut String,
+    session: Session<'a>,
+    depth: usize,
+    summary: &'a mut ScriptSummary,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        if depth >= MAX_SOURCE_DEPTH {
+            return Err(anyhow!("Scripts are nested more than {} deep", MAX_SOURCE_DEPTH));
+        }
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read script {}", path))?;
+        let mut on_error = OnError::Abort;
+
+        for (index, raw) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let trimmed = raw.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let line = match expand(trimmed, &vars) {
+                Ok(line) => line,
+                Err(e) => {
+                    summary.commands += 1;
+                    if fail(summary, path, line_number, trimmed, e, on_error) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            // Script directives
+            if let Some((name, value)) = parse_let(&line) {
+                vars.insert(name.to_string(), value.to_string());
+                continue;
+            }
+            match parts.as_slice() {
+                ["on-error", "continue"] => {
+                    on_error = OnError::Continue;
+                    continue;
+                }
+                ["on-error", "abort"] => {
+                    on_error = OnError::Abort;
+                    continue;
+                }
+                ["exit"] => {
+                    print_info(&format!("{}:{}: exit", path, line_number));
+                    return Ok(());
+                }
+                _ => {}
+            }
+
+            print_info(&format!("{}:{}: {}", path, line_number, line));
+
+            // Another script; its commands count on their own
+            if let ["source", file, args @ ..] = parts.as_slice() {
+                let nested = parse_vars(args).map(|args| {
+                    let mut nested = vars.clone();
+                    nested.extend(args);
+                    nested
+                });
+                let failures = summary.failures.len();
+                let result = match nested {
+                    Ok(nested) => {
+                        let file = nested_path(path, file);
+                        run_file(&file, nested, output_format, session, depth + 1, summary).await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    summary.commands += 1;
+                    if fail(summary, path, line_number, &line, e, on_error) {
+                        return Ok(());
+                    }
+                } else if summary.aborted || (summary.failures.len() > failures && on_error == OnError::Abort) {
+                    summary.aborted = true;
+                    return Ok(());
+                }
+                continue;
+            }
+
+            summary.commands += 1;
+            let result = match parts.as_slice() {
+                ["sleep", duration] => match parse_duration(duration) {
+                    Ok(duration) => {
+                        tokio::time::sleep(duration).await;
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                },
+                _ => handle_command(&line, output_format, session.devices, session.jobs, session.scope).await,
+            };
+
+            match result {
+                Ok(()) => summary.succeeded += 1,
+                Err(e) => {
+                    if fail(summary, path, line_number, &line, e, on_error) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// The name and value of a `let name = value` line.
+fn parse_let(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("let").filter(|rest| rest.starts_with(char::is_whitespace))?;
+    let (name, value) = rest.split_once('=')?;
+    let name = name.trim();
+    is_var_name(name).then(|| (name, value.trim()))
+}
+
+/// Where `file`, named by a `source` in the script `parent`, is: relative
+/// paths start from the directory `parent` is in.
+fn nested_path(parent: &str, file: &str) -> String {
+    match Path::new(parent).parent() {
+        Some(dir) if Path::new(file).is_relative() => dir.join(file).to_string_lossy().into_owned(),
+        _ => file.to_string(),
+    }
+}
+
+/// Record a failed command, returning whether the script stops here.
+fn fail(summary: &mut ScriptSummary, path: &str, line: usize, command: &str, error: anyhow::Error, on_error: OnError) -> bool {
+    print_error(&format!("{}:{}: {}", path, line, error));
+    summary.failures.push(ScriptFailure {
+        file: path.to_string(),
+        line,
+        command: command.to_string(),
+        error: error.to_string(),
+        exit_code: exit_code_for(&error),
+    });
+
+    let abort = on_error == OnError::Abort;
+    summary.aborted |= abort;
+    abort
+}
@@ -3,11 +3,22 @@ use std::fs;
 use std::path::Path;
 use anyhow::{Result, Context};
 
+pub mod scope;
+
+use scope::ScopeConfig;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub mqtt: MqttConfig,
     pub serial: SerialConfig,
     pub output: OutputConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    /// Rules of engagement; see [`scope`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ScopeConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,12 +28,22 @@ pub struct MqttConfig {
     pub client_id: String,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Start a local mosquitto with the interactive shell
+    #[serde(default = "default_start_broker")]
+    pub start_broker: bool,
+}
+
+fn default_start_broker() -> bool {
+    true
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SerialConfig {
     pub baud_rate: u32,
     pub auto_connect: bool,
+    /// Port tried first by auto-connect, e.g. a `secot-sim --link` path
+    #[serde(default)]
+    pub port: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +51,54 @@ pub struct OutputConfig {
     pub default_format: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Hash-chained JSON Lines file every operator action is appended to
+    #[serde(default = "default_audit_path")]
+    pub path: String,
+    /// Name recorded with each action; defaults to the login user
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+/// `audit.log` in the user's data directory, so every session appends to
+/// the same chain whichever directory it was started from.
+fn default_audit_path() -> String {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("secot").join("audit.log"))
+        .unwrap_or_else(|| "audit.log".into())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    /// Longest an attack may run before the CLI stops it, in seconds
+    #[serde(default = "default_max_attack_secs")]
+    pub max_attack_secs: u64,
+}
+
+fn default_max_attack_secs() -> u64 {
+    600
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            max_attack_secs: default_max_attack_secs(),
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: default_audit_path(),
+            operator: None,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -39,14 +108,19 @@ impl Default for Config {
                 client_id: "secot_cli_tool".to_string(),
                 username: None,
                 password: None,
+                start_broker: true,
             },
             serial: SerialConfig {
                 baud_rate: 115200,
                 auto_connect: true,
+                port: None,
             },
             output: OutputConfig {
                 default_format: "table".to_string(),
             },
+            audit: AuditConfig::default(),
+            safety: SafetyConfig::default(),
+            scope: None,
         }
     }
 }
@@ -0,0 +1,417 @@
+//! Rules of engagement: which targets active operations may touch, and when.
+//!
+//! The scope lives in the `scope` section of `config.json`:
+//!
+//! ```json
+//! "scope": {
+//!   "name": "ACME lab, RoE signed 2026-10-01",
+//!   "cidrs": ["192.168.56.0/24"],
+//!   "bssids": ["00:11:22:33:44:55"],
+//!   "ssids": ["ACME-Lab"],
+//!   "mqtt_hosts": ["192.168.56.20"],
+//!   "windows": [{ "start": "2026-10-17T09:00:00+02:00", "end": "2026-10-17T17:00:00+02:00" }]
+//! }
+//! ```
+//!
+//! Without a `scope` section nothing is restricted. With one, every list is
+//! exhaustive: an empty `bssids` means no BSSID may be targeted. Leaving
+//! `windows` empty allows any time.
+//!
+//! A refused command can be forced with `--override-scope`; each override is
+//! appended to `override_log` (default `scope_overrides.log`) as one JSON line.
+
+use crate::models::attack::TargetKind;
+use crate::output::formatter::print_warning;
+use crate::error::CliError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::IpAddr;
+
+/// Word that forces a command past the scope check.
+pub const OVERRIDE_FLAG: &str = "--override-scope";
+
+const DEFAULT_OVERRIDE_LOG: &str = "scope_overrides.log";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScopeConfig {
+    /// Engagement this scope belongs to, shown in refusals.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Networks hosts may be scanned or attacked in. Single addresses are `/32`.
+    #[serde(default)]
+    pub cidrs: Vec<IpNetwork>,
+    /// Access points and stations WiFi attacks may target.
+    #[serde(default)]
+    pub bssids: Vec<String>,
+    /// Networks that may be cloned or attacked by name.
+    #[serde(default)]
+    pub ssids: Vec<String>,
+    /// Brokers that may be tested or spoofed.
+    #[serde(default)]
+    pub mqtt_hosts: Vec<IpNetwork>,
+    /// When active operations are allowed.
+    #[serde(default)]
+    pub windows: Vec<TimeWindow>,
+    #[serde(default)]
+    pub override_log: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.start.format("%Y-%m-%d %H:%M %:z"), self.end.format("%Y-%m-%d %H:%M %:z"))
+    }
+}
+
+/// Something an active operation is aimed at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Host(IpAddr),
+    Network(IpNetwork),
+    MqttHost(IpAddr),
+    Bssid(String),
+    Ssid(String),
+}
+
+impl Target {
+    /// The target an attack parameter value names, or `None` if the value
+    /// isn't one (parameter validation reports that).
+    pub fn from_param(kind: TargetKind, value: &str) -> Option<Self> {
+        match kind {
+            TargetKind::Host => value.parse().ok().map(Target::Host),
+            TargetKind::MqttHost => value.parse().ok().map(Target::MqttHost),
+            TargetKind::Bssid => Some(Target::Bssid(normalize_mac(value))),
+            TargetKind::Ssid => Some(Target::Ssid(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Host(ip) => write!(f, "host {}", ip),
+            Target::Network(network) => write!(f, "network {}", network),
+            Target::MqttHost(ip) => write!(f, "MQTT broker {}", ip),
+            Target::Bssid(bssid) => write!(f, "BSSID {}", bssid),
+            Target::Ssid(ssid) => write!(f, "SSID '{}'", ssid),
+        }
+    }
+}
+
+fn normalize_mac(mac: &str) -> String {
+    mac.replace('-', ":").to_uppercase()
+}
+
+/// The engagement scope in force for this session.
+#[derive(Debug, Default, Clone)]
+pub struct Scope {
+    config: Option<ScopeConfig>,
+}
+
+impl Scope {
+    pub fn new(config: Option<ScopeConfig>) -> Self {
+        Self { config }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// One-line summary for the startup banner.
+    pub fn summary(&self) -> String {
+        let Some(config) = &self.config else {
+            return "No engagement scope configured; active operations are not restricted".to_string();
+        };
+        format!(
+            "Engagement scope{}: {} networks, {} BSSIDs, {} SSIDs, {} MQTT hosts, {} time windows",
+            config.name.as_deref().map(|name| format!(" '{}'", name)).unwrap_or_default(),
+            config.cidrs.len(),
+            config.bssids.len(),
+            config.ssids.len(),
+            config.mqtt_hosts.len(),
+            config.windows.len()
+        )
+    }
+
+    /// Why `target` is out of scope, or `None` if it may be touched.
+    pub fn violation(&self, target: &Target) -> Option<String> {
+        let config = self.config.as_ref()?;
+
+        let allowed = match target {
+            Target::Host(ip) => config.cidrs.iter().any(|cidr| cidr.contains(*ip)),
+            Target::Network(network) => config
+                .cidrs
+                .iter()
+                .any(|cidr| cidr.contains(network.network()) && cidr.contains(network.broadcast())),
+            Target::MqttHost(ip) => config.mqtt_hosts.iter().any(|host| host.contains(*ip)),
+            Target::Bssid(bssid) => config.bssids.iter().any(|allowed| normalize_mac(allowed) == *bssid),
+            Target::Ssid(ssid) => config.ssids.iter().any(|allowed| allowed == ssid),
+        };
+
+        (!allowed).then(|| format!("{} is not in scope", target))
+    }
+
+    /// Why now is outside every engagement window, or `None` if it isn't.
+    pub fn time_violation(&self) -> Option<String> {
+        let config = self.config.as_ref()?;
+        if config.windows.is_empty() {
+            return None;
+        }
+
+        let now = Local::now();
+        if config.windows.iter().any(|window| window.start <= now && now < window.end) {
+            return None;
+        }
+
+        let windows: Vec<String> = config.windows.iter().map(|window| window.to_string()).collect();
+        Some(format!("outside the engagement windows ({})", windows.join(", ")))
+    }
+
+    /// A check for one command, forced through if it carried [`OVERRIDE_FLAG`].
+    pub fn guard<'a>(&'a self, command: &'a str, allow_override: bool) -> ScopeGuard<'a> {
+        ScopeGuard {
+            scope: self,
+            command,
+            allow_override,
+        }
+    }
+
+    fn override_log(&self) -> &str {
+        self.config
+            .as_ref()
+            .and_then(|config| config.override_log.as_deref())
+            .unwrap_or(DEFAULT_OVERRIDE_LOG)
+    }
+
+    fn engagement(&self) -> Option<&str> {
+        self.config.as_ref().and_then(|config| config.name.as_deref())
+    }
+}
+
+/// Checks the targets of one command against the [`Scope`].
+#[derive(Clone, Copy)]
+pub struct ScopeGuard<'a> {
+    scope: &'a Scope,
+    command: &'a str,
+    allow_override: bool,
+}
+
+impl<'a> ScopeGuard<'a> {
+    /// The scope this guard checks against.
+    pub fn scope(&self) -> &'a Scope {
+        self.scope
+    }
+
+    /// Whether any scope is configured at all.
+    pub fn is_restricted(&self) -> bool {
+        self.scope.is_configured()
+    }
+
+    /// Refuse `action` unless it is inside an engagement window and every
+    /// target is in scope.
+    ///
+    /// With an override the action is allowed, but a warning is printed and
+    /// the override logged.
+    pub fn authorize(&self, action: &str, targets: &[Target]) -> Result<()> {
+        let mut reasons: Vec<String> = targets.iter().filter_map(|target| self.scope.violation(target)).collect();
+        reasons.extend(self.scope.time_violation());
+        self.enforce(action, &reasons)
+    }
+
+    /// Refuse `action` because its targets can't be checked, e.g. when a
+    /// target parameter couldn't be read from the device.
+    ///
+    /// An override forces it through like any other violation.
+    pub fn refuse(&self, action: &str, reason: &str) -> Result<()> {
+        self.enforce(action, &[reason.to_string()])
+    }
+
+    fn enforce(&self, action: &str, reasons: &[String]) -> Result<()> {
+        if reasons.is_empty() {
+            return Ok(());
+        }
+
+        if !self.allow_override {
+            let engagement = self
+                .scope
+                .engagement()
+                .map(|name| format!(" of '{}'", name))
+                .unwrap_or_default();
+            return Err(CliError::OutOfScope(format!(
+                "Refusing to {}: {} (engagement scope{}). Add {} to run it anyway; the override is logged",
+                action,
+                reasons.join("; "),
+                engagement,
+                OVERRIDE_FLAG
+            ))
+            .into());
+        }
+
+        print_warning(&format!("Scope override: {} although {}", action, reasons.join("; ")));
+        self.log_override(action, reasons)
+    }
+
+    fn log_override(&self, action: &str, reasons: &[String]) -> Result<()> {
+        let path = self.scope.override_log();
+        let entry = json!({
+            "timestamp": Local::now().to_rfc3339(),
+            "engagement": self.scope.engagement(),
+            "command": self.command,
+            "action": action,
+            "reasons": reasons,
+        });
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open scope override log {}", path))?;
+        writeln!(file, "{}", entry).with_context(|| format!("Failed to write scope override log {}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::fs;
+
+    fn scope(config: serde_json::Value) -> Scope {
+        Scope::new(Some(serde_json::from_value(config).unwrap()))
+    }
+
+    fn lab() -> Scope {
+        scope(json!({
+            "name": "lab",
+            "cidrs": ["192.168.56.0/24", "10.0.0.5/32"],
+            "bssids": ["00:11:22:aa:bb:cc"],
+            "ssids": ["ACME-Lab"],
+            "mqtt_hosts": ["192.168.56.20"],
+        }))
+    }
+
+    fn host(ip: &str) -> Target {
+        Target::Host(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn hosts_and_networks_match_cidrs() {
+        let scope = lab();
+        assert_eq!(scope.violation(&host("192.168.56.1")), None);
+        assert_eq!(scope.violation(&host("192.168.56.255")), None);
+        assert_eq!(scope.violation(&host("10.0.0.5")), None);
+        assert!(scope.violation(&host("10.0.0.6")).is_some());
+        assert!(scope.violation(&host("192.168.57.1")).is_some());
+
+        assert_eq!(scope.violation(&Target::Network("192.168.56.128/25".parse().unwrap())), None);
+        assert!(scope.violation(&Target::Network("192.168.0.0/16".parse().unwrap())).is_some());
+    }
+
+    #[test]
+    fn mqtt_hosts_are_listed_separately() {
+        let scope = lab();
+        assert_eq!(scope.violation(&Target::MqttHost("192.168.56.20".parse().unwrap())), None);
+        assert!(scope.violation(&Target::MqttHost("192.168.56.21".parse().unwrap())).is_some());
+    }
+
+    #[test]
+    fn bssids_match_in_any_case_and_separator() {
+        let scope = lab();
+        for bssid in ["00:11:22:AA:BB:CC", "00-11-22-aa-bb-cc"] {
+            let target = Target::from_param(TargetKind::Bssid, bssid).unwrap();
+            assert_eq!(scope.violation(&target), None, "{}", bssid);
+        }
+        let other = Target::from_param(TargetKind::Bssid, "00:11:22:AA:BB:CD").unwrap();
+        assert!(scope.violation(&other).is_some());
+    }
+
+    #[test]
+    fn ssids_match_exactly() {
+        let scope = lab();
+        assert_eq!(scope.violation(&Target::Ssid("ACME-Lab".to_string())), None);
+        assert!(scope.violation(&Target::Ssid("acme-lab".to_string())).is_some());
+        assert!(scope.violation(&Target::Ssid("ACME-Lab-Guest".to_string())).is_some());
+    }
+
+    #[test]
+    fn no_scope_allows_everything() {
+        let scope = Scope::new(None);
+        assert_eq!(scope.violation(&host("8.8.8.8")), None);
+        assert_eq!(scope.time_violation(), None);
+        assert!(scope.guard("attack", false).authorize("attack", &[host("8.8.8.8")]).is_ok());
+    }
+
+    #[test]
+    fn empty_scope_allows_nothing() {
+        let scope = scope(json!({}));
+        assert!(scope.violation(&host("192.168.56.1")).is_some());
+        assert!(scope.violation(&Target::Ssid("ACME-Lab".to_string())).is_some());
+        assert_eq!(scope.time_violation(), None);
+    }
+
+    #[test]
+    fn time_windows() {
+        let now = Local::now().fixed_offset();
+        let window = |start: Duration, end: Duration| json!({ "start": now + start, "end": now + end });
+
+        let open = scope(json!({ "windows": [window(Duration::hours(-1), Duration::hours(1))] }));
+        assert_eq!(open.time_violation(), None);
+
+        let closed = scope(json!({ "windows": [window(Duration::hours(-2), Duration::hours(-1))] }));
+        assert!(closed.time_violation().is_some());
+    }
+
+    #[test]
+    fn out_of_scope_is_refused_without_override() {
+        let scope = lab();
+        let err = scope.guard("attack", false).authorize("attack", &[host("8.8.8.8")]).unwrap_err();
+        assert!(matches!(err.downcast_ref::<CliError>(), Some(CliError::OutOfScope(_))));
+        assert!(err.to_string().contains(OVERRIDE_FLAG));
+    }
+
+    #[test]
+    fn override_allows_and_logs() {
+        let log = std::env::temp_dir().join(format!("secot-scope-overrides-{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let scope = scope(json!({ "name": "lab", "override_log": log }));
+
+        let command = format!("secot attack deauth {}", OVERRIDE_FLAG);
+        scope.guard(&command, true).authorize("start deauth", &[host("8.8.8.8")]).unwrap();
+        scope.guard(&command, true).refuse("start deauth", "target unknown").unwrap();
+
+        let entries: Vec<serde_json::Value> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["engagement"], "lab");
+        assert_eq!(entries[0]["command"], command);
+        assert_eq!(entries[0]["reasons"][0], "host 8.8.8.8 is not in scope");
+        assert_eq!(entries[1]["reasons"][0], "target unknown");
+        fs::remove_file(log).unwrap();
+    }
+
+    #[test]
+    fn in_scope_override_is_not_logged() {
+        let log = std::env::temp_dir().join(format!("secot-scope-unused-{}.log", std::process::id()));
+        let _ = fs::remove_file(&log);
+        let mut config: ScopeConfig = serde_json::from_value(json!({ "cidrs": ["192.168.56.0/24"] })).unwrap();
+        config.override_log = Some(log.to_string_lossy().into_owned());
+
+        Scope::new(Some(config)).guard("scan", true).authorize("scan", &[host("192.168.56.7")]).unwrap();
+        assert!(!log.exists());
+    }
+}
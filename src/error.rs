@@ -25,6 +25,54 @@ pub enum CliError {
     
     #[error("Unknown error: {0}")]
     Unknown(String),
+
+    /// No device to send the command to.
+    #[error("{0}")]
+    NotConnected(String),
+
+    /// Refused by the engagement scope.
+    #[error("{0}")]
+    OutOfScope(String),
+
+    /// The audit log's hash chain doesn't hold.
+    #[error("{0}")]
+    AuditChainBroken(String),
+
+    /// A command typed wrongly: unknown words or bad arguments.
+    #[error("{0}")]
+    Usage(String),
 }
 
 pub type CliResult<T> = Result<T, CliError>;
+
+/// Process exit codes of a non-interactive run.
+pub mod exit_code {
+    pub const SUCCESS: u8 = 0;
+    /// The command ran and failed, e.g. the device answered with an error.
+    pub const FAILURE: u8 = 1;
+    /// Bad arguments (also what clap uses).
+    pub const USAGE: u8 = 2;
+    pub const NOT_CONNECTED: u8 = 3;
+    pub const OUT_OF_SCOPE: u8 = 4;
+    pub const AUDIT_CHAIN_BROKEN: u8 = 5;
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::NotConnected(_) | CliError::SerialError(_) => exit_code::NOT_CONNECTED,
+            CliError::OutOfScope(_) => exit_code::OUT_OF_SCOPE,
+            CliError::AuditChainBroken(_) => exit_code::AUDIT_CHAIN_BROKEN,
+            CliError::ConfigError(_) | CliError::Usage(_) => exit_code::USAGE,
+            _ => exit_code::FAILURE,
+        }
+    }
+}
+
+/// The exit code for a failed command.
+pub fn exit_code_for(error: &anyhow::Error) -> u8 {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<CliError>())
+        .map_or(exit_code::FAILURE, CliError::exit_code)
+}
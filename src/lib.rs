@@ -1,3 +1,6 @@
+#![allow(non_snake_case)]
+
+pub mod audit;
 pub mod command;
 pub mod config;
 pub mod error;
@@ -5,6 +8,7 @@ pub mod models;
 pub mod mqtt;
 pub mod output;
 pub mod serial;
+pub mod simulator;
 
 // Re-export commonly used types
 pub use config::Config;
@@ -12,4 +16,5 @@ pub use error::{CliError, CliResult};
 pub use models::network::{DeviceInfo, MqttBroker, WiFiNetwork};
 pub use models::port::{PortScanResults, PortStatus};
 pub use output::formatter::{format_output, print_success, print_error, print_info, print_section};
+pub use serial::registry::DeviceRegistry;
 pub use serial::serial_commands::SerialCommands;
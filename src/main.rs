@@ -1,96 +1,412 @@
-mod command;
-mod config;
-mod error;
-mod models;
-mod mqtt;
-mod output;
-mod serial;
-
+use SECoT_CLI_Tool::{audit, command, config, error, mqtt, output, serial};
 use anyhow::Result;
-use command::cmd_handler::handle_command;
+use audit::{default_operator, AuditAction, AuditLog, AuditRecord};
+use command::cli::Cli;
+use command::cmd_handler::{handle_command, handle_invocation, stop_all_attacks};
+use command::completion::ReplCompleter;
+use command::jobs::Jobs;
+use command::line_editor::{History, LineEditor, PromptLine};
+use command::registry::Invocation;
+use command::script::{parse_vars, run_script};
+use config::scope::Scope;
 use config::Config;
+use error::{exit_code, exit_code_for};
 use mqtt::broker::{start_broker, stop_broker};
-use output::formatter::{print_info, print_success, print_error, print_section};
-use serial::serial_connection::SerialConnection;
-use serial::serial_commands::SerialCommands;
-use std::io::{self, Write};
-use std::sync::Arc;
-use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
-
-fn main() -> Result<()> {
+use output::formatter::{print_event, print_info, print_success, print_error, print_section, print_warning, status_to_stderr};
+use serial::events::EventBus;
+use serial::registry::DeviceRegistry;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, ExitCode};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::broadcast::error::RecvError;
+
+const PROMPT: &str = "SECoT> ";
+
+/// Print device events as they arrive, above the prompt if the user is at it.
+fn spawn_event_notifier(runtime: &Runtime, events: EventBus, prompt_line: PromptLine) {
+    let mut rx = events.subscribe();
+
+    runtime.spawn(async move {
+        loop {
+            let message = match rx.recv().await {
+                Ok(event) => event.to_string(),
+                Err(RecvError::Lagged(missed)) => format!("Missed {} device events", missed),
+                Err(RecvError::Closed) => break,
+            };
+
+            // A command is already printing them
+            if events.is_followed() {
+                continue;
+            }
+
+            prompt_line.print_above(|| print_event(&message));
+        }
+    });
+}
+
+/// Stop the broker if it is still running.
+fn shut_down_broker(broker: &Mutex<Option<Child>>) {
+    if let Some(child) = broker.lock().ok().and_then(|mut broker| broker.take()) {
+        stop_broker(child);
+    }
+}
+
+/// On SIGTERM, and on Ctrl-C if `interrupt` is set, stop running attacks
+/// before exiting.
+///
+/// The shell leaves Ctrl-C to cancel the command in the foreground instead.
+fn spawn_signal_handler(runtime: &Runtime, devices: Arc<DeviceRegistry>, broker: Arc<Mutex<Option<Child>>>, interrupt: bool) {
+    runtime.spawn(async move {
+        let interrupted = async {
+            match interrupt {
+                true => tokio::signal::ctrl_c().await,
+                false => std::future::pending().await,
+            }
+        };
+
+        #[cfg(unix)]
+        let (signal, code) = {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut terminate = match signal(SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(e) => {
+                    print_error(&format!("Cannot watch for SIGTERM: {}", e));
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = interrupted => ("Interrupted", 130),
+                _ = terminate.recv() => ("Terminated", 143),
+            }
+        };
+        #[cfg(not(unix))]
+        let (signal, code) = {
+            if interrupted.await.is_err() {
+                return;
+            }
+            ("Interrupted", 130)
+        };
+
+        print_warning(&format!("{}; stopping active attacks...", signal));
+        stop_all_attacks(&devices, false).await;
+        shut_down_broker(&broker);
+        std::process::exit(code);
+    });
+}
+
+/// Stop running attacks when the CLI panics, after the usual panic message.
+fn install_panic_stop(devices: Arc<DeviceRegistry>) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        // The panic may have taken the main runtime's thread, so stop from a fresh one
+        let devices = devices.clone();
+        let stopper = std::thread::spawn(move || {
+            if let Ok(runtime) = Builder::new_current_thread().enable_all().build() {
+                runtime.block_on(stop_all_attacks(&devices, false));
+            }
+        });
+        let _ = stopper.join();
+    }));
+}
+
+/// Read one command line, with the line editor when stdin is a terminal.
+///
+/// Returns `None` at end of input.
+fn read_command(editor: Option<&mut LineEditor>) -> io::Result<Option<String>> {
+    if let Some(editor) = editor {
+        println!();
+        return editor.read_line(PROMPT);
+    }
+
+    print!("\n{}", PROMPT);
+    io::stdout().flush().ok();
+
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input)? {
+        0 => Ok(None),
+        _ => Ok(Some(input)),
+    }
+}
+
+/// Connect the active device to `--port`, or auto-detect one.
+fn connect_device(runtime: &Runtime, devices: &DeviceRegistry, cli: &Cli, config: &Config) -> Result<String> {
+    runtime.block_on(async {
+        let device = devices.active()?;
+        match &cli.port {
+            Some(port) => {
+                let baud_rate = cli.baud.unwrap_or(config.serial.baud_rate);
+                device.connect_to_port(port, baud_rate).await?;
+                Ok(port.clone())
+            }
+            None => device.auto_connect(config.serial.port.as_deref()).await,
+        }
+    })
+}
+
+/// Run one command given on the command line, returning the exit code.
+///
+/// Attacks are left to finish (or hit the watchdog limit) before returning.
+fn run_once(
+    runtime: &Runtime,
+    invocation: Invocation,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope,
+) -> u8 {
+    if let Err(e) = runtime.block_on(handle_invocation(invocation, output_format, devices, jobs, scope)) {
+        print_error(&format!("Error: {}", e));
+        return exit_code_for(&e);
+    }
+
+    wait_for_attacks(runtime, devices);
+    exit_code::SUCCESS
+}
+
+/// Run a command typed at the prompt until it ends or Ctrl-C cancels it.
+///
+/// Background jobs it started keep running.
+fn run_foreground(
+    runtime: &Runtime,
+    line: &str,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope,
+) {
+    let result = runtime.block_on(async {
+        tokio::select! {
+            result = handle_command(line, output_format, devices, jobs, scope) => Some(result),
+            _ = tokio::signal::ctrl_c() => None,
+        }
+    });
+
+    match result {
+        Some(Ok(())) => {},
+        Some(Err(e)) => print_error(&format!("Error: {}", e)),
+        None => {
+            println!();
+            print_warning(&format!("Cancelled '{}'", line));
+            devices
+                .audit()
+                .record(AuditRecord::new(AuditAction::Command, line).target(&devices.active_name()).result("cancelled"));
+        },
+    }
+}
+
+/// Run a script given with `--script`, returning the exit code.
+fn run_script_file(
+    runtime: &Runtime,
+    path: &str,
+    vars: &HashMap<String, String>,
+    output_format: &mut String,
+    devices: &Arc<DeviceRegistry>,
+    jobs: &Arc<Jobs>,
+    scope: &Scope,
+) -> u8 {
+    let summary = match runtime.block_on(run_script(path, vars, output_format, devices, jobs, scope)) {
+        Ok(summary) => summary,
+        Err(e) => {
+            print_error(&format!("Error: {:#}", e));
+            return exit_code::USAGE;
+        }
+    };
+
+    // Show what the script's background jobs printed once each has ended
+    for id in jobs.ids() {
+        print_section(&format!("Job {}", id));
+        if let Err(e) = runtime.block_on(jobs.wait(id)) {
+            print_error(&format!("Error: {}", e));
+        }
+    }
+    wait_for_attacks(runtime, devices);
+    if let Err(e) = summary.print(output_format) {
+        print_error(&format!("Error: {}", e));
+    }
+    summary.exit_code()
+}
+
+/// Block until no device has an attack running that this session started.
+fn wait_for_attacks(runtime: &Runtime, devices: &DeviceRegistry) {
+    let running = || devices.all().iter().any(|(_, device)| !device.active_attacks().is_empty());
+    if running() {
+        print_info("Waiting for the attack to finish (Ctrl-C stops it)...");
+        runtime.block_on(async {
+            while running() {
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        });
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse_args();
+    let one_shot = cli.command.clone();
+    let interactive = one_shot.is_none() && cli.script.is_none();
+    if !interactive {
+        // Keep stdout for the commands' output
+        status_to_stderr(true);
+    }
+    let script_vars = match parse_vars(&cli.vars) {
+        Ok(vars) => vars,
+        Err(e) => {
+            print_error(&format!("{}", e));
+            return ExitCode::from(exit_code::USAGE);
+        }
+    };
+
     // Load configuration
-    let config = match Config::load("config.json") {
+    let config = match Config::load(&cli.config) {
         Ok(config) => config,
-        Err(_) => {
-            print_info("No configuration found. Using default settings.");
+        Err(e) => {
+            // A broken scope section must not go unnoticed
+            print_error(&format!("{:#}", e));
+            if !interactive {
+                return ExitCode::from(exit_code::USAGE);
+            }
+            print_info("Using default settings.");
             Config::default()
         }
     };
 
     // Initialize runtime
-    let runtime = Runtime::new()?;
+    let runtime = match Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            print_error(&format!("Failed to start the async runtime: {}", e));
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
 
-    // Start MQTT broker
-    let broker_process = start_broker();
+    // Start MQTT broker; commands and scripts only get one when asked for
+    let wants_broker = cli.broker || (interactive && !cli.no_broker && config.mqtt.start_broker);
+    let broker = match wants_broker.then(start_broker) {
+        Some(Err(e)) => {
+            print_warning(&format!("{:#}", e));
+            None
+        }
+        started => started.and_then(Result::ok),
+    };
+    let broker = Arc::new(Mutex::new(broker));
 
     // Print welcome message
-    print_section("SECoT CLI Tool");
-    print_info("Secure Command Tool for IoT Security Testing");
-    println!("Type 'help' for available commands\n");
-
-    // Initialize serial connection
-    let (serial_connection, _tx, _rx) = SerialConnection::new();
-    let serial_connection = Arc::new(Mutex::new(serial_connection));
-    let serial_commands = Arc::new(SerialCommands::new(serial_connection.clone()));
-
-    // Try to auto-connect to SECoT device if enabled in config
-    if config.serial.auto_connect {
-        print_info("Attempting to auto-connect to SECoT device...");
-        match runtime.block_on(serial_commands.auto_connect()) {
+    if interactive {
+        print_section("SECoT CLI Tool");
+        print_info("Secure Command Tool for IoT Security Testing");
+        println!("Type 'help' for available commands\n");
+    }
+
+    // Initialize the device registry with the default serial connection
+    let events = EventBus::new();
+    let prompt_line = PromptLine::default();
+    spawn_event_notifier(&runtime, events.clone(), prompt_line.clone());
+
+    // Record every operator action for the client report
+    let operator = config.audit.operator.clone().unwrap_or_else(default_operator);
+    let audit = match AuditLog::open(&config.audit.path, &operator) {
+        Ok(audit) => {
+            if interactive {
+                print_info(&format!("Recording actions by '{}' in {}", operator, config.audit.path));
+            }
+            audit
+        },
+        Err(e) => {
+            print_error(&format!("{:#}", e));
+            print_warning("Audit logging is off for this session");
+            AuditLog::disabled()
+        },
+    };
+
+    let devices = Arc::new(DeviceRegistry::new(events, audit));
+    devices.set_attack_limit(Duration::from_secs(config.safety.max_attack_secs.max(1)));
+    let jobs = Arc::new(Jobs::new(prompt_line.clone()));
+    spawn_signal_handler(&runtime, devices.clone(), broker.clone(), !interactive);
+    install_panic_stop(devices.clone());
+
+    // Connect to the SECoT device if the command needs one, or for the
+    // shell and scripts with --port or auto-connect on
+    let wants_device = match &cli.command {
+        Some(invocation) => invocation.spec.needs_device(),
+        None => cli.port.is_some() || config.serial.auto_connect,
+    };
+    if wants_device && !cli.no_connect {
+        if interactive && cli.port.is_none() {
+            print_info("Attempting to auto-connect to SECoT device...");
+        }
+        match connect_device(&runtime, &devices, &cli, &config) {
             Ok(port) => print_success(&format!("Connected to SECoT device on port {}", port)),
+            Err(e) if one_shot.is_some() => {
+                print_error(&format!("Failed to connect: {:#}", e));
+                shut_down_broker(&broker);
+                return ExitCode::from(exit_code::NOT_CONNECTED);
+            },
+            Err(e) if cli.port.is_some() => print_error(&format!("Failed to connect: {:#}", e)),
             Err(_) => print_info("No SECoT device found. Use 'serial connect <port>' to connect manually."),
         }
     }
 
-    // Set initial output format from config
-    let mut output_format = config.output.default_format.clone();
+    // Rules of engagement for active operations
+    let scope = Scope::new(config.scope.clone());
+    if scope.is_configured() {
+        print_info(&scope.summary());
+    } else if interactive {
+        print_warning(&scope.summary());
+    }
+
+    // Set initial output format from the command line or config
+    let mut output_format = cli.format.clone().unwrap_or_else(|| config.output.default_format.clone());
+
+    if let Some(invocation) = one_shot {
+        let code = run_once(&runtime, invocation, &mut output_format, &devices, &jobs, &scope);
+        runtime.block_on(stop_all_attacks(&devices, false));
+        shut_down_broker(&broker);
+        return ExitCode::from(code);
+    }
+
+    if let Some(path) = &cli.script {
+        let code = run_script_file(&runtime, path, &script_vars, &mut output_format, &devices, &jobs, &scope);
+        runtime.block_on(stop_all_attacks(&devices, false));
+        shut_down_broker(&broker);
+        return ExitCode::from(code);
+    }
+
     print_info(&format!("Output format set to '{}'", output_format));
+    let mut editor = io::stdin().is_terminal().then(|| {
+        let history = History::load(History::user_path());
+        LineEditor::new(ReplCompleter::new(devices.clone(), jobs.clone()), history).with_prompt_line(prompt_line)
+    });
 
     // Main command loop
     loop {
-        print!("\nSECoT> ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+        let input = match read_command(editor.as_mut()) {
+            Ok(Some(input)) => input,
+            Ok(None) => break, // EOF
+            Err(e) => {
+                print_error(&format!("Failed to read input: {}", e));
+                break;
+            }
+        };
         let trimmed = input.trim();
 
         if trimmed == "exit" {
             print_info("Exiting SECoT CLI Tool...");
             break;
-        } else if trimmed.starts_with("set output ") {
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if let Some(fmt) = parts.get(2) {
-                if fmt == &"table" || fmt == &"json" {
-                    output_format = fmt.to_string();
-                    print_success(&format!("Output format set to '{}'", output_format));
-                } else {
-                    print_error("Invalid format. Use 'set output <table|json>'");
-                }
-            } else {
-                print_error("Invalid format. Use 'set output <table|json>'");
-            }
-        } else {
-            match runtime.block_on(handle_command(trimmed, &output_format, &serial_commands)) {
-                Ok(_) => {},
-                Err(e) => print_error(&format!("Error: {}", e)),
-            }
         }
+        run_foreground(&runtime, trimmed, &mut output_format, &devices, &jobs, &scope);
     }
 
     // Clean up
-    stop_broker(broker_process);
+    let cancelled = jobs.cancel_all();
+    if cancelled > 0 {
+        print_warning(&format!("Cancelled {} running background job(s)", cancelled));
+    }
+    runtime.block_on(stop_all_attacks(&devices, false));
+    shut_down_broker(&broker);
     print_success("Goodbye!");
-    Ok(())
+    ExitCode::SUCCESS
 }
\ No newline at end of file
@@ -1,8 +1,76 @@
 pub mod port {
+    use anyhow::{anyhow, Result};
     use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, BTreeSet};
     use std::net::IpAddr;
     use std::fmt;
 
+    /// The ports `scan ports` checks when no spec is given.
+    pub const COMMON_PORTS: &[u16] = &[
+        21, 22, 23, 25, 53, 80, 110, 143, 443, 465, 587, 993, 995, 1883, 3306, 5432, 8080, 8883,
+    ];
+
+    /// TCP ports by how often they are found open, most common first.
+    pub const TOP_PORTS: &[u16] = &[
+        80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, 143, 53, 135, 3306, 8080, 1723, 111, 995, 993, 5900,
+        1025, 587, 8888, 199, 1720, 465, 548, 113, 81, 6001, 10000, 514, 5060, 179, 1026, 2000, 8443, 8000,
+        32768, 554, 26, 1433, 49152, 2001, 515, 8008, 49154, 1027, 5666, 646, 5000, 5631, 631, 49153, 8081,
+        2049, 88, 79, 5800, 106, 2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156, 543, 544, 5101, 144,
+        7, 389, 8009, 3128, 444, 9999, 5009, 7070, 5190, 3000, 5432, 1900, 3986, 13, 1029, 9, 5051, 6646,
+        49157, 1028, 873, 1755, 2717, 4899, 9100, 119, 37,
+    ];
+
+    /// Statuses listed port by port however many ports have them.
+    const ALWAYS_LISTED: &[&str] = &["open"];
+
+    /// Above this many ports with the same other status, they are counted instead of listed.
+    const LIST_LIMIT: usize = 10;
+
+    /// Parse a port spec into distinct ports in ascending order.
+    ///
+    /// A spec is a comma-separated list of single ports (`1883`), ranges
+    /// (`8000-8100`), `topN` for the N most common TCP ports, `common` for
+    /// the default list and `all`.
+    pub fn parse_port_spec(spec: &str) -> Result<Vec<u16>> {
+        let mut ports = BTreeSet::new();
+        for item in spec.split(',').map(str::trim) {
+            match item {
+                "" => return Err(anyhow!("Empty item in port spec '{}'", spec)),
+                "common" => ports.extend(COMMON_PORTS),
+                "all" => ports.extend(1..=u16::MAX),
+                _ if item.starts_with("top") => {
+                    let count: usize = item[3..]
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid '{}'; use e.g. top20", item))?;
+                    if count == 0 || count > TOP_PORTS.len() {
+                        return Err(anyhow!("'{}' is out of range; use top1 to top{}", item, TOP_PORTS.len()));
+                    }
+                    ports.extend(&TOP_PORTS[..count]);
+                }
+                _ => match item.split_once('-') {
+                    Some((first, last)) => {
+                        let (first, last) = (parse_port(first)?, parse_port(last)?);
+                        if first > last {
+                            return Err(anyhow!("Port range '{}' runs backwards", item));
+                        }
+                        ports.extend(first..=last);
+                    }
+                    None => {
+                        ports.insert(parse_port(item)?);
+                    }
+                },
+            }
+        }
+        Ok(ports.into_iter().collect())
+    }
+
+    fn parse_port(text: &str) -> Result<u16> {
+        match text.trim().parse::<u16>() {
+            Ok(port) if port > 0 => Ok(port),
+            _ => Err(anyhow!("'{}' is not a port between 1 and 65535", text)),
+        }
+    }
+
     #[derive(Serialize, Deserialize, Debug)]
     pub struct IpAddress(pub IpAddr);
 
@@ -17,6 +85,43 @@ pub mod port {
         pub port: u16,
         pub status: String,
         pub service: Option<String>,
+        /// What decided the status, e.g. the reply a UDP probe got
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub evidence: Option<String>,
+        /// The software behind the port, when a signature names it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub product: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub version: Option<String>,
+        /// The first readable line the service sent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub banner: Option<String>,
+        /// How sure the service, product and version are
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub confidence: Option<Confidence>,
+    }
+
+    /// How much a service identification can be trusted.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Confidence {
+        /// A loose hint, such as a login prompt
+        Low,
+        /// The protocol answered as expected
+        Medium,
+        /// The service named its product
+        High,
+    }
+
+    impl fmt::Display for Confidence {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let confidence = match self {
+                Confidence::Low => "low",
+                Confidence::Medium => "medium",
+                Confidence::High => "high",
+            };
+            f.write_str(confidence)
+        }
     }
 
     impl fmt::Display for PortStatus {
@@ -27,25 +132,120 @@ pub mod port {
                 self.port,
                 self.status,
                 self.service.as_deref().unwrap_or("unknown")
-            )
+            )?;
+            if let Some(product) = &self.product {
+                write!(f, ", Product: {}", product)?;
+                if let Some(version) = &self.version {
+                    write!(f, " {}", version)?;
+                }
+            }
+            if let Some(confidence) = self.confidence {
+                write!(f, " ({} confidence)", confidence)?;
+            }
+            if let (None, Some(banner)) = (&self.product, &self.banner) {
+                write!(f, ", Banner: {}", banner)?;
+            }
+            if let Some(evidence) = &self.evidence {
+                write!(f, ", Evidence: {}", evidence)?;
+            }
+            Ok(())
         }
     }
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct PortScanResults {
         pub ip: IpAddress,
+        /// `tcp` or `udp`
+        #[serde(default = "default_protocol")]
+        pub protocol: String,
         pub results: Vec<PortStatus>,
     }
 
+    fn default_protocol() -> String {
+        "tcp".to_string()
+    }
+
     impl fmt::Display for PortScanResults {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            writeln!(f, "Port Scan Results for {}:", self.ip)?;
+            writeln!(f, "{} Port Scan Results for {}:", self.protocol.to_uppercase(), self.ip)?;
+
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
             for port in &self.results {
+                *counts.entry(port.status.as_str()).or_default() += 1;
+            }
+            let listed = |status: &str| ALWAYS_LISTED.contains(&status) || counts[status] <= LIST_LIMIT;
+
+            for port in self.results.iter().filter(|port| listed(&port.status)) {
                 writeln!(f, "  {}", port)?;
             }
+            for (status, count) in counts.iter().filter(|(status, _)| !listed(status)) {
+                writeln!(f, "  Not shown: {} {} ports", count, status)?;
+            }
             Ok(())
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn ports(spec: &str) -> Vec<u16> {
+            parse_port_spec(spec).unwrap()
+        }
+
+        #[test]
+        fn single_ports_and_lists() {
+            assert_eq!(ports("1883"), vec![1883]);
+            assert_eq!(ports(" 443 , 22,80 "), vec![22, 80, 443]);
+            assert_eq!(ports("80,80"), vec![80]);
+        }
+
+        #[test]
+        fn named_sets() {
+            let mut common = COMMON_PORTS.to_vec();
+            common.sort();
+            assert_eq!(ports("common"), common);
+
+            let all = ports("all");
+            assert_eq!(all.len(), 65535);
+            assert_eq!((all[0], all[65534]), (1, 65535));
+        }
+
+        #[test]
+        fn top_ports() {
+            assert_eq!(ports("top5"), vec![21, 22, 23, 80, 443]);
+            assert_eq!(ports(&format!("top{}", TOP_PORTS.len())).len(), TOP_PORTS.len());
+            assert!(parse_port_spec("top0").is_err());
+            assert!(parse_port_spec(&format!("top{}", TOP_PORTS.len() + 1)).is_err());
+            assert!(parse_port_spec("topx").is_err());
+        }
+
+        #[test]
+        fn ranges() {
+            assert_eq!(ports("8000-8002"), vec![8000, 8001, 8002]);
+            assert_eq!(ports("22-22"), vec![22]);
+            assert_eq!(ports("20-25,22-30"), (20..=30).collect::<Vec<u16>>());
+            assert_eq!(ports("top3,79-81"), vec![23, 79, 80, 81, 443]);
+            assert!(parse_port_spec("10-5").is_err());
+            assert!(parse_port_spec("80-").is_err());
+            assert!(parse_port_spec("65530-65536").is_err());
+        }
+
+        #[test]
+        fn port_bounds() {
+            assert_eq!(ports("1,65535"), vec![1, 65535]);
+            assert!(parse_port_spec("0").is_err());
+            assert!(parse_port_spec("65536").is_err());
+            assert!(parse_port_spec("-1").is_err());
+        }
+
+        #[test]
+        fn malformed_specs() {
+            assert!(parse_port_spec("").is_err());
+            assert!(parse_port_spec("80,,81").is_err());
+            assert!(parse_port_spec("http").is_err());
+        }
+    }
 }
 
 pub mod network {
@@ -57,30 +257,53 @@ pub mod network {
     pub struct DeviceInfo {
         pub ip: IpAddr,
         pub hostname: String,
+        /// How the host showed it was up, e.g. an ICMP echo reply
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub evidence: Option<String>,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct MqttBroker {
         pub ip: IpAddr,
         pub port: u16,
+        #[serde(default, alias = "auth")]
         pub requires_auth: bool,
+        #[serde(default, alias = "tls")]
         pub supports_tls: bool,
+        #[serde(default, alias = "open")]
         pub is_accessible: bool,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    /// A broker as the device saw it, next to whether this host could connect to it.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct CheckedMqttBroker {
+        #[serde(flatten)]
+        pub broker: MqttBroker,
+        /// `None` when the host-side check was not run.
+        pub host_accessible: Option<bool>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct WiFiNetwork {
+        #[serde(default)]
         pub ssid: String,
         pub bssid: String,
         pub channel: u8,
+        #[serde(alias = "signal")]
         pub rssi: i32,
+        #[serde(alias = "auth")]
         pub encryption: String,
+        #[serde(default)]
         pub hidden: bool,
     }
 
     impl fmt::Display for DeviceInfo {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "IP: {}, Hostname: {}", self.ip, self.hostname)
+            write!(f, "IP: {}, Hostname: {}", self.ip, self.hostname)?;
+            if let Some(evidence) = &self.evidence {
+                write!(f, ", Found by: {}", evidence)?;
+            }
+            Ok(())
         }
     }
 
@@ -98,6 +321,16 @@ pub mod network {
         }
     }
 
+    impl fmt::Display for CheckedMqttBroker {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.broker)?;
+            if let Some(host_accessible) = self.host_accessible {
+                write!(f, ", Accessible from host: {}", host_accessible)?;
+            }
+            Ok(())
+        }
+    }
+
     impl fmt::Display for WiFiNetwork {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(
@@ -112,4 +345,241 @@ pub mod network {
             )
         }
     }
-}
\ No newline at end of file
+}
+pub mod attack {
+    use anyhow::{anyhow, Result};
+    use crate::error::CliError;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::time::Duration;
+
+    /// An attack the SECoT card can run.
+    ///
+    /// `Firmware` carries attacks this tool doesn't know but the connected
+    /// firmware advertises; they are only produced by [`AttackKind::parse`]
+    /// when the firmware lists the name.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum AttackKind {
+        Deauth,
+        BeaconFlood,
+        ProbeSpam,
+        ArpSpoof,
+        MqttSpoof,
+        EvilTwin,
+        PassiveSniff,
+        BluetoothScan,
+        Firmware(String),
+    }
+
+    impl AttackKind {
+        /// Every attack of the stock firmware.
+        pub const ALL: [AttackKind; 8] = [
+            AttackKind::Deauth,
+            AttackKind::BeaconFlood,
+            AttackKind::ProbeSpam,
+            AttackKind::ArpSpoof,
+            AttackKind::MqttSpoof,
+            AttackKind::EvilTwin,
+            AttackKind::PassiveSniff,
+            AttackKind::BluetoothScan,
+        ];
+
+        /// The name the firmware uses.
+        pub fn name(&self) -> &str {
+            match self {
+                AttackKind::Deauth => "deauth",
+                AttackKind::BeaconFlood => "beaconFlood",
+                AttackKind::ProbeSpam => "probeSpam",
+                AttackKind::ArpSpoof => "arpSpoof",
+                AttackKind::MqttSpoof => "mqttSpoof",
+                AttackKind::EvilTwin => "evilTwin",
+                AttackKind::PassiveSniff => "passiveSniff",
+                AttackKind::BluetoothScan => "bluetoothScan",
+                AttackKind::Firmware(name) => name,
+            }
+        }
+
+        /// A stock attack by name, ignoring case.
+        pub fn known(name: &str) -> Option<Self> {
+            Self::ALL
+                .into_iter()
+                .find(|kind| kind.name().eq_ignore_ascii_case(name))
+        }
+
+        /// Parse a user-supplied attack name.
+        ///
+        /// Names the firmware `advertised` pass through as [`AttackKind::Firmware`];
+        /// anything else is rejected with the closest known names as suggestions.
+        pub fn parse(name: &str, advertised: &[String]) -> Result<Self> {
+            if let Some(kind) = Self::known(name) {
+                return Ok(kind);
+            }
+            if let Some(name) = advertised.iter().find(|a| a.as_str() == name) {
+                return Ok(AttackKind::Firmware(name.clone()));
+            }
+
+            let mut candidates: Vec<&str> = Self::ALL.iter().map(|kind| kind.name()).collect();
+            candidates.extend(advertised.iter().map(String::as_str).filter(|a| Self::known(a).is_none()));
+
+            let suggestions: Vec<&str> = candidates
+                .iter()
+                .copied()
+                .filter(|candidate| {
+                    let candidate = candidate.to_lowercase();
+                    let name = name.to_lowercase();
+                    edit_distance(&candidate, &name) <= 2 || (name.len() >= 3 && candidate.contains(&name))
+                })
+                .collect();
+
+            let message = match suggestions.as_slice() {
+                [] => format!("Unknown attack '{}'. Known attacks: {}", name, candidates.join(", ")),
+                _ => format!("Unknown attack '{}'. Did you mean {}?", name, suggestions.join(" or ")),
+            };
+            Err(CliError::Usage(message).into())
+        }
+
+        /// Parameters that must be set before the attack can start.
+        pub fn required_params(&self) -> &'static [&'static str] {
+            match self {
+                AttackKind::ArpSpoof => &["target", "gateway"],
+                AttackKind::MqttSpoof => &["broker"],
+                AttackKind::EvilTwin => &["ssid"],
+                _ => &[],
+            }
+        }
+
+        /// Parameters that name who or what the attack is aimed at.
+        pub fn target_params(&self) -> &'static [(&'static str, TargetKind)] {
+            match self {
+                AttackKind::Deauth => &[("target", TargetKind::Bssid)],
+                AttackKind::ArpSpoof => &[("target", TargetKind::Host), ("gateway", TargetKind::Host)],
+                AttackKind::MqttSpoof => &[("broker", TargetKind::MqttHost)],
+                AttackKind::EvilTwin => &[("ssid", TargetKind::Ssid)],
+                _ => &[],
+            }
+        }
+
+        /// Parameters with a usable default on the device.
+        pub fn optional_params(&self) -> &'static [&'static str] {
+            match self {
+                AttackKind::Deauth => &["channel", "target", "reason"],
+                AttackKind::BeaconFlood => &["ssid_count", "channel"],
+                AttackKind::ProbeSpam => &["rate"],
+                AttackKind::MqttSpoof => &["topic"],
+                AttackKind::EvilTwin | AttackKind::PassiveSniff => &["channel"],
+                AttackKind::BluetoothScan => &["duration"],
+                AttackKind::ArpSpoof | AttackKind::Firmware(_) => &[],
+            }
+        }
+    }
+
+    /// What kind of target an attack parameter holds.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TargetKind {
+        /// An IP address on the network.
+        Host,
+        /// The IP address of an MQTT broker.
+        MqttHost,
+        /// A station or access point MAC address.
+        Bssid,
+        Ssid,
+    }
+
+    impl fmt::Display for AttackKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.name())
+        }
+    }
+
+    impl From<String> for AttackKind {
+        fn from(name: String) -> Self {
+            Self::known(&name).unwrap_or(AttackKind::Firmware(name))
+        }
+    }
+
+    impl From<AttackKind> for String {
+        fn from(kind: AttackKind) -> Self {
+            kind.name().to_string()
+        }
+    }
+
+    /// Parse an attack duration: plain seconds, or a number with an `s`, `m` or `h` suffix.
+    pub fn parse_duration(text: &str) -> Result<Duration> {
+        let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+            Some(index) => text.split_at(index),
+            None => (text, "s"),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| anyhow!("Invalid duration '{}'; use e.g. 90, 30s, 5m or 1h", text))?;
+
+        let scale = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            _ => return Err(anyhow!("Invalid duration unit in '{}'; use s, m or h", text)),
+        };
+        let seconds = value
+            .checked_mul(scale)
+            .ok_or_else(|| anyhow!("Duration '{}' is too long", text))?;
+        if seconds == 0 {
+            return Err(anyhow!("Duration must be at least one second"));
+        }
+        Ok(Duration::from_secs(seconds))
+    }
+
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+        for (i, ca) in a.chars().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, cb) in b.iter().enumerate() {
+                let cost = if ca == *cb { 0 } else { 1 };
+                current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+
+        previous[b.len()]
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn secs(text: &str) -> u64 {
+            parse_duration(text).unwrap().as_secs()
+        }
+
+        #[test]
+        fn duration_units() {
+            assert_eq!(secs("90"), 90);
+            assert_eq!(secs("30s"), 30);
+            assert_eq!(secs("5m"), 300);
+            assert_eq!(secs("1h"), 3600);
+        }
+
+        #[test]
+        fn zero_and_malformed_durations() {
+            for text in ["0", "0s", "0h", "", "s", "-5", "1.5m", "5x", "5 m", "5ms"] {
+                assert!(parse_duration(text).is_err(), "{}", text);
+            }
+        }
+
+        #[test]
+        fn duration_overflow() {
+            let most_hours = u64::MAX / 3600;
+            assert_eq!(secs(&format!("{}h", most_hours)), most_hours * 3600);
+            assert!(parse_duration(&format!("{}h", most_hours + 1)).is_err());
+            assert!(parse_duration("99999999999999999999").is_err());
+        }
+
+        #[test]
+        fn unknown_attack_is_a_usage_error() {
+            let err = AttackKind::parse("deuth", &[]).unwrap_err();
+            assert!(matches!(err.downcast_ref::<CliError>(), Some(CliError::Usage(_))));
+        }
+    }
+}
@@ -1,22 +1,25 @@
+use anyhow::{Context, Result};
+use crate::output::formatter::{print_error, print_info, print_success};
 use std::process::{Child, Command};
 use std::thread::sleep;
 use std::time::Duration;
 
-pub fn start_broker() -> Child {
+pub fn start_broker() -> Result<Child> {
     let child = Command::new("mosquitto")
         .arg("-v")
         .spawn()
-        .expect("Failed to start MQTT broker");
-    println!("MQTT Broker Started (PID: {})", child.id());
+        .context("Failed to start MQTT broker (is mosquitto installed?)")?;
+    print_success(&format!("MQTT Broker Started (PID: {})", child.id()));
     sleep(Duration::from_secs(1));
-    child
+    Ok(child)
 }
 
 pub fn stop_broker(mut child: Child){
     if let Err(e) = child.kill(){
-        eprintln!("Err Stopping MQTT Broker: {}", e);
+        print_error(&format!("Err Stopping MQTT Broker: {}", e));
     }
     else{
-        println!("Disconnected from MQTT Broker");
+        let _ = child.wait();
+        print_info("Disconnected from MQTT Broker");
     }
 }
\ No newline at end of file
@@ -2,6 +2,124 @@ use anyhow::Result;
 use console::style;
 use serde::Serialize;
 use std::fmt::Display;
+use std::future::Future;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Whether status messages go to stderr, leaving stdout to command output.
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// Where output goes instead of the terminal, for a background job.
+    static CAPTURE: CapturedOutput;
+}
+
+/// Output kept while a command runs in the background, to be shown later.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    text: Arc<Mutex<String>>,
+}
+
+impl CapturedOutput {
+    /// Everything captured from byte `from` on.
+    pub fn since(&self, from: usize) -> String {
+        self.text
+            .lock()
+            .map(|text| text.get(from..).unwrap_or_default().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.text.lock().map(|text| text.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run `future`, capturing everything it prints instead of showing it.
+    ///
+    /// Only output printed from the task running `future` is captured.
+    pub async fn capture<F: Future>(self, future: F) -> F::Output {
+        CAPTURE.scope(self, future).await
+    }
+
+    fn push_line(&self, line: &str) {
+        if let Ok(mut text) = self.text.lock() {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+}
+
+/// Keep `line` if output is being captured; returns whether it was.
+fn captured(line: &str) -> bool {
+    CAPTURE.try_with(|capture| capture.push_line(line)).is_ok()
+}
+
+/// Print a line of command output to stdout, or capture it.
+///
+/// Used through [`outln!`](crate::outln) rather than `println!`, so
+/// background jobs keep their output.
+pub fn print_output(line: &str) {
+    if !captured(line) {
+        println!("{}", line);
+    }
+}
+
+/// Print a diagnostic line to stderr, or capture it.
+pub fn print_diagnostic(line: &str) {
+    if !captured(line) {
+        eprintln!("{}", line);
+    }
+}
+
+/// Print a progress mark such as a dot to stderr, without a newline.
+///
+/// Captured output leaves progress out; it means nothing afterwards.
+pub fn print_progress(mark: &str) {
+    if CAPTURE.try_with(|_| ()).is_err() {
+        eprint!("{}", mark);
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// `println!` for command output, which background jobs capture.
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::formatter::print_output("")
+    };
+    ($($arg:tt)*) => {
+        $crate::output::formatter::print_output(&format!($($arg)*))
+    };
+}
+
+/// `eprintln!` for command diagnostics, which background jobs capture.
+#[macro_export]
+macro_rules! errln {
+    ($($arg:tt)*) => {
+        $crate::output::formatter::print_diagnostic(&format!($($arg)*))
+    };
+}
+
+/// Send status messages (success, warning, info, events, sections) to
+/// stderr so stdout carries only command output, e.g. JSON for a script.
+pub fn status_to_stderr(enabled: bool) {
+    STATUS_TO_STDERR.store(enabled, Ordering::SeqCst);
+}
+
+fn print_status(line: String) {
+    if captured(&line) {
+        return;
+    }
+    if STATUS_TO_STDERR.load(Ordering::SeqCst) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
 
 /// Format output based on the specified format
 pub fn format_output<T: Serialize + Display>(data: &T, format: &str) -> Result<String> {
@@ -23,27 +141,32 @@ pub fn format_output<T: Serialize + Display>(data: &T, format: &str) -> Result<S
 
 /// Print a success message
 pub fn print_success(message: &str) {
-    println!("{} {}", style("✓").green().bold(), message);
+    print_status(format!("{} {}", style("✓").green().bold(), message));
 }
 
 /// Print an error message
 pub fn print_error(message: &str) {
-    eprintln!("{} {}", style("✗").red().bold(), message);
+    print_diagnostic(&format!("{} {}", style("✗").red().bold(), message));
 }
 
 /// Print a warning message
 pub fn print_warning(message: &str) {
-    println!("{} {}", style("!").yellow().bold(), message);
+    print_status(format!("{} {}", style("!").yellow().bold(), message));
 }
 
 /// Print an info message
 pub fn print_info(message: &str) {
-    println!("{} {}", style("i").blue().bold(), message);
+    print_status(format!("{} {}", style("i").blue().bold(), message));
+}
+
+/// Print an asynchronous device event
+pub fn print_event(message: &str) {
+    print_status(format!("{} {}", style("»").magenta().bold(), message));
 }
 
 /// Print a section header
 pub fn print_section(title: &str) {
-    println!("\n{}", style(title).bold().underlined());
+    print_status(format!("\n{}", style(title).bold().underlined()));
 }
 
 /// Print a command result
@@ -54,5 +177,5 @@ pub fn print_command_result(command: &str, result: &str, success: bool) {
         style("FAILED").red().bold()
     };
     
-    println!("{}: {} - {}", command, status, result);
+    print_output(&format!("{}: {} - {}", command, status, result));
 }
@@ -18,8 +18,11 @@ pub fn create_table<T: Serialize>(items: &[T], headers: &[&str]) -> Result<Table
             let cells: Vec<Cell> = headers
                 .iter()
                 .map(|&header| {
-                    let value = map.get(header).cloned().unwrap_or(serde_json::Value::Null);
-                    Cell::new(&value.to_string())
+                    match map.get(header) {
+                        Some(serde_json::Value::String(text)) => Cell::new(text),
+                        Some(serde_json::Value::Null) | None => Cell::new(""),
+                        Some(value) => Cell::new(&value.to_string()),
+                    }
                 })
                 .collect();
             
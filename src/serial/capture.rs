@@ -0,0 +1,316 @@
+//! Recording and replay of raw serial traffic.
+//!
+//! A capture is a JSON Lines file. The first line is a header, every other
+//! line is one chunk of bytes as it was written to or read from the port:
+//!
+//! ```text
+//! {"format":"secot-capture","version":1,"started":"2026-05-02T10:14:03+02:00","port":"/dev/ttyUSB0","baud_rate":115200,"wire_mode":"text","next_request_id":7}
+//! {"t_ms":0,"dir":"tx","hex":"2337207374617475730a","text":"#7 status\n"}
+//! {"t_ms":12,"dir":"rx","hex":"23372073746174652069646c650a","text":"#7 state idle\n"}
+//! ```
+//!
+//! * `t_ms` — milliseconds since the recording started.
+//! * `dir` — `tx` (host to device) or `rx` (device to host).
+//! * `hex` — the exact bytes, hex encoded. This is what replay uses.
+//! * `text` — the same bytes decoded lossily as UTF-8, for humans only.
+//!
+//! `next_request_id` is the sequence ID the next command got when recording
+//! started; replay resumes numbering from there so recorded responses match.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const CAPTURE_FORMAT: &str = "secot-capture";
+pub const CAPTURE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub format: String,
+    pub version: u32,
+    pub started: DateTime<Local>,
+    pub port: String,
+    pub baud_rate: u32,
+    pub wire_mode: String,
+    pub next_request_id: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub t_ms: u64,
+    pub dir: Direction,
+    pub hex: String,
+    #[serde(default, skip_deserializing)]
+    pub text: String,
+}
+
+impl CaptureRecord {
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        hex_decode(&self.hex).ok_or_else(|| anyhow!("Invalid hex data in capture: {}", self.hex))
+    }
+}
+
+/// An open recording.
+pub struct Capture {
+    path: String,
+    writer: BufWriter<File>,
+    started: Instant,
+    chunks: u64,
+}
+
+/// Shared slot for the active recording, if any.
+pub type CaptureHandle = Arc<Mutex<Option<Capture>>>;
+
+impl Capture {
+    pub fn create(path: &str, header: &CaptureHeader) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create capture file: {}", path))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", serde_json::to_string(header)?)?;
+        writer.flush()?;
+
+        Ok(Self {
+            path: path.to_string(),
+            writer,
+            started: Instant::now(),
+            chunks: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn chunks(&self) -> u64 {
+        self.chunks
+    }
+
+    pub fn record(&mut self, dir: Direction, bytes: &[u8]) {
+        let record = CaptureRecord {
+            t_ms: self.started.elapsed().as_millis() as u64,
+            dir,
+            hex: hex_encode(bytes),
+            text: String::from_utf8_lossy(bytes).into_owned(),
+        };
+
+        // Flush every chunk so a crash still leaves a usable transcript
+        let written = serde_json::to_string(&record)
+            .map_err(std::io::Error::other)
+            .and_then(|line| writeln!(self.writer, "{}", line))
+            .and_then(|_| self.writer.flush());
+        match written {
+            Ok(()) => self.chunks += 1,
+            Err(e) => log::warn!("Failed to write capture record to {}: {}", self.path, e),
+        }
+    }
+}
+
+/// Append `bytes` to the active recording, if there is one.
+pub fn record(capture: &CaptureHandle, dir: Direction, bytes: &[u8]) {
+    if let Ok(mut capture) = capture.lock() {
+        if let Some(capture) = capture.as_mut() {
+            capture.record(dir, bytes);
+        }
+    }
+}
+
+/// A capture file loaded for replay.
+pub struct Recording {
+    pub header: CaptureHeader,
+    pub records: Vec<CaptureRecord>,
+}
+
+impl Recording {
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open capture file: {}", path))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines
+            .next()
+            .ok_or_else(|| anyhow!("Capture file is empty: {}", path))??;
+        let header: CaptureHeader = serde_json::from_str(&header_line)
+            .with_context(|| format!("Invalid capture header in {}", path))?;
+        if header.format != CAPTURE_FORMAT || header.version != CAPTURE_VERSION {
+            return Err(anyhow!(
+                "Unsupported capture format {} v{}",
+                header.format,
+                header.version
+            ));
+        }
+
+        let mut records = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: CaptureRecord = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid capture record on line {}", number + 2))?;
+            records.push(record);
+        }
+
+        Ok(Self { header, records })
+    }
+
+    /// Split the recording into a device stand-in the serial threads can use
+    /// in place of a port.
+    ///
+    /// Device output recorded before the first command is played immediately.
+    /// After that, each write from the host releases the output recorded
+    /// between the matching command and the next one. With `realtime`, the
+    /// recorded gaps between chunks are reproduced.
+    pub fn into_ports(self, realtime: bool) -> Result<(ReplayReader, ReplayWriter)> {
+        let mut preamble = Vec::new();
+        let mut exchanges: Vec<Exchange> = Vec::new();
+
+        for record in &self.records {
+            let bytes = record.bytes()?;
+            match record.dir {
+                Direction::Tx => exchanges.push(Exchange {
+                    sent: bytes,
+                    sent_at: record.t_ms,
+                    replies: Vec::new(),
+                }),
+                Direction::Rx => match exchanges.last_mut() {
+                    Some(exchange) => {
+                        let delay = record.t_ms.saturating_sub(exchange.sent_at);
+                        exchange.replies.push((delay, bytes));
+                    }
+                    None => preamble.push(bytes),
+                },
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        for chunk in preamble {
+            let _ = tx.send(chunk);
+        }
+
+        Ok((
+            ReplayReader {
+                rx,
+                pending: Vec::new(),
+            },
+            ReplayWriter {
+                tx,
+                exchanges: exchanges.into_iter(),
+                realtime,
+                index: 0,
+            },
+        ))
+    }
+}
+
+/// One recorded command and the device output that followed it.
+struct Exchange {
+    sent: Vec<u8>,
+    sent_at: u64,
+    /// Output chunks with their delay after the command, in milliseconds.
+    replies: Vec<(u64, Vec<u8>)>,
+}
+
+/// Device-to-host half of a replay; read by the serial reader thread.
+pub struct ReplayReader {
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl Read for ReplayReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            // Behave like a port with a read timeout
+            match self.rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(chunk) => self.pending = chunk,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "replay idle"))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+/// Host-to-device half of a replay; written by the serial writer thread.
+pub struct ReplayWriter {
+    tx: Sender<Vec<u8>>,
+    exchanges: std::vec::IntoIter<Exchange>,
+    realtime: bool,
+    index: usize,
+}
+
+impl Write for ReplayWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.index += 1;
+
+        let Some(exchange) = self.exchanges.next() else {
+            log::warn!("Replay exhausted; write #{} gets no response", self.index);
+            return Ok(buf.len());
+        };
+
+        if exchange.sent != buf {
+            log::warn!(
+                "Replay diverged at write #{}: recorded {:?}, got {:?}",
+                self.index,
+                String::from_utf8_lossy(&exchange.sent),
+                String::from_utf8_lossy(buf)
+            );
+        }
+
+        if !self.realtime {
+            for (_, chunk) in exchange.replies {
+                let _ = self.tx.send(chunk);
+            }
+            return Ok(buf.len());
+        }
+
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let mut elapsed = 0;
+            for (delay, chunk) in exchange.replies {
+                if delay > elapsed {
+                    std::thread::sleep(Duration::from_millis(delay - elapsed));
+                    elapsed = delay;
+                }
+                if tx.send(chunk).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
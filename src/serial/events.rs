@@ -0,0 +1,213 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Prefix of an asynchronous notification line, e.g. `EVENT attack_started deauth`.
+const EVENT_PREFIX: &str = "EVENT";
+
+/// How many past events are kept for `secot events`.
+const HISTORY_LEN: usize = 200;
+
+/// What happened on the device.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    AttackStarted { attack: String },
+    AttackFinished { attack: String, reason: Option<String> },
+    ScanProgress { scan: String, percent: Option<u8>, message: Option<String> },
+    DeviceError { code: String, message: String },
+    RebootDetected { reason: String },
+    /// The serial link dropped (raised by the host, not the device).
+    LinkLost { reason: String },
+    /// The serial link came back after being lost.
+    LinkRestored { port: String },
+    /// The host stopped an attack on its own (raised by the host, not the device).
+    SafetyStop { attack: String, reason: String },
+    /// A line the device sent on its own that we don't know how to interpret.
+    Message { text: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceEvent {
+    pub timestamp: DateTime<Local>,
+    /// Port (or device name) the event came from.
+    pub source: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl DeviceEvent {
+    pub fn new(source: &str, kind: EventKind) -> Self {
+        Self {
+            timestamp: Local::now(),
+            source: source.to_string(),
+            kind,
+        }
+    }
+
+    /// Interpret an unsolicited device line.
+    pub fn from_line(source: &str, line: &str) -> Self {
+        Self::new(source, EventKind::parse(line))
+    }
+}
+
+impl EventKind {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+
+        // The ESP32 ROM prints these before the firmware starts
+        if line.starts_with("rst:") || line.starts_with("ets ") {
+            return EventKind::RebootDetected { reason: line.to_string() };
+        }
+
+        let Some(rest) = line.strip_prefix(EVENT_PREFIX).map(str::trim_start) else {
+            return EventKind::Message { text: line.to_string() };
+        };
+
+        let mut words = rest.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let joined = |from: usize| -> Option<String> {
+            let text = args.get(from..).unwrap_or_default().join(" ");
+            (!text.is_empty()).then_some(text)
+        };
+
+        match (name, args.as_slice()) {
+            ("attack_started", [attack, ..]) => EventKind::AttackStarted {
+                attack: attack.to_string(),
+            },
+            ("attack_finished", [attack, ..]) => EventKind::AttackFinished {
+                attack: attack.to_string(),
+                reason: joined(1),
+            },
+            ("scan_progress", [scan, rest @ ..]) => {
+                let percent = rest.first().and_then(|p| p.trim_end_matches('%').parse().ok());
+                EventKind::ScanProgress {
+                    scan: scan.to_string(),
+                    percent,
+                    message: joined(if percent.is_some() { 2 } else { 1 }),
+                }
+            }
+            ("error", [code, ..]) => EventKind::DeviceError {
+                code: code.to_string(),
+                message: joined(1).unwrap_or_default(),
+            },
+            ("reboot", _) => EventKind::RebootDetected {
+                reason: joined(0).unwrap_or_else(|| "device reported reboot".to_string()),
+            },
+            _ => EventKind::Message { text: line.to_string() },
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventKind::AttackStarted { attack } => write!(f, "attack started: {}", attack),
+            EventKind::AttackFinished { attack, reason } => match reason {
+                Some(reason) => write!(f, "attack finished: {} ({})", attack, reason),
+                None => write!(f, "attack finished: {}", attack),
+            },
+            EventKind::ScanProgress { scan, percent, message } => {
+                write!(f, "{} scan", scan)?;
+                if let Some(percent) = percent {
+                    write!(f, " {}%", percent)?;
+                }
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            EventKind::DeviceError { code, message } => write!(f, "device error {}: {}", code, message),
+            EventKind::RebootDetected { reason } => write!(f, "reboot detected: {}", reason),
+            EventKind::LinkLost { reason } => write!(f, "serial link lost: {}", reason),
+            EventKind::LinkRestored { port } => write!(f, "serial link restored on {}", port),
+            EventKind::SafetyStop { attack, reason } => write!(f, "safety stop of {}: {}", attack, reason),
+            EventKind::Message { text } => write!(f, "{}", text),
+        }
+    }
+}
+
+impl fmt::Display for DeviceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} {}", self.timestamp.format("%H:%M:%S"), self.source, self.kind)
+    }
+}
+
+/// Fan-out channel for device events, with a short history for late subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<DeviceEvent>,
+    history: Arc<Mutex<VecDeque<DeviceEvent>>>,
+    followers: Arc<AtomicUsize>,
+}
+
+/// Marks the bus as being tailed by a command for as long as it lives.
+pub struct FollowGuard {
+    followers: Arc<AtomicUsize>,
+}
+
+impl Drop for FollowGuard {
+    fn drop(&mut self) {
+        self.followers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(100);
+        Self {
+            tx,
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_LEN))),
+            followers: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn publish(&self, event: DeviceEvent) {
+        if let Ok(mut history) = self.history.lock() {
+            if history.len() == HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(event.clone());
+        }
+
+        // No subscribers is fine; the event is still in the history
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Subscribe on behalf of a command that prints events itself, so the
+    /// REPL notifier can stay quiet until the guard is dropped.
+    pub fn follow(&self) -> (broadcast::Receiver<DeviceEvent>, FollowGuard) {
+        self.followers.fetch_add(1, Ordering::SeqCst);
+        let guard = FollowGuard {
+            followers: self.followers.clone(),
+        };
+        (self.subscribe(), guard)
+    }
+
+    pub fn is_followed(&self) -> bool {
+        self.followers.load(Ordering::SeqCst) > 0
+    }
+
+    /// The last `count` events, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<DeviceEvent> {
+        match self.history.lock() {
+            Ok(history) => history.iter().skip(history.len().saturating_sub(count)).cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Firmware identification, as reported by the `info` command.
+//!
+//! The device answers `info` with one `key value` line per field, lists
+//! separated by commas, before the closing `OK`:
+//!
+//! ```text
+//! firmware 1.2.0
+//! build 3f2a9c1
+//! chip ESP32-D0WD-V3
+//! mac 24:6F:28:AA:BB:CC
+//! protocol 1
+//! attacks deauth,beaconFlood,probeSpam
+//! params deauth.channel,deauth.target,beaconFlood.ssid_count
+//! ```
+//!
+//! A single JSON object with the same field names is accepted as well.
+//! Firmware that predates `info` answers `ERR`; such devices are treated as
+//! supporting everything.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Command the capabilities are queried with.
+pub const INFO_COMMAND: &str = "info";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FirmwareInfo {
+    pub firmware: String,
+    pub build: Option<String>,
+    pub chip: Option<String>,
+    pub mac: Option<String>,
+    pub protocol: Option<u8>,
+    pub attacks: Vec<String>,
+    /// Supported parameters as `<attack>.<param>`.
+    pub params: Vec<String>,
+}
+
+impl FirmwareInfo {
+    /// Parse the response to `info`.
+    pub fn parse(response: &str) -> Result<Self> {
+        let response = response.trim();
+        if response.starts_with('{') {
+            return Ok(serde_json::from_str(response)?);
+        }
+
+        let mut info = Self::default();
+        for line in response.lines() {
+            let (key, value) = match line.trim().split_once(char::is_whitespace) {
+                Some((key, value)) => (key, value.trim()),
+                None => continue,
+            };
+
+            match key {
+                "firmware" => info.firmware = value.to_string(),
+                "build" => info.build = Some(value.to_string()),
+                "chip" => info.chip = Some(value.to_string()),
+                "mac" => info.mac = Some(value.to_string()),
+                "protocol" => info.protocol = value.parse().ok(),
+                "attacks" => info.attacks = split_list(value),
+                "params" => info.params = split_list(value),
+                // Newer firmware may report more than we know about
+                _ => log::debug!("Ignoring unknown info field '{}'", key),
+            }
+        }
+
+        if info.firmware.is_empty() {
+            return Err(anyhow!("Device info does not include a firmware version"));
+        }
+        Ok(info)
+    }
+
+    /// Whether the firmware can run `attack`. Firmware that lists no attacks is trusted.
+    pub fn supports_attack(&self, attack: &str) -> bool {
+        self.attacks.is_empty() || self.attacks.iter().any(|a| a == attack)
+    }
+
+    /// Whether `attack` has a parameter called `param`. Firmware that lists no parameters is trusted.
+    pub fn supports_parameter(&self, attack: &str, param: &str) -> bool {
+        self.params.is_empty() || self.params.iter().any(|p| *p == format!("{}.{}", attack, param))
+    }
+
+    /// Refuse a device command the firmware has said it can't run.
+    pub fn check_command(&self, command: &str) -> Result<()> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+
+        match parts.as_slice() {
+            ["attack", attack, ..] | ["stop", attack] if !self.supports_attack(attack) => Err(anyhow!(
+                "Firmware {} does not support the {} attack (supported: {})",
+                self.firmware,
+                attack,
+                self.attacks.join(", ")
+            )),
+            ["set", attack, param, ..] | ["get", attack, param] if !self.supports_parameter(attack, param) => {
+                Err(anyhow!(
+                    "Firmware {} has no parameter {}.{}",
+                    self.firmware,
+                    attack,
+                    param
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.firmware)?;
+        if let Some(build) = &self.build {
+            write!(f, " ({})", build)?;
+        }
+        if let Some(chip) = &self.chip {
+            write!(f, " on {}", chip)?;
+        }
+        Ok(())
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
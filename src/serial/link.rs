@@ -0,0 +1,148 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serialport::SerialPortType;
+use std::fmt;
+use std::time::Duration;
+
+/// How often the supervisor polls the USB bus for the device.
+pub const HOTPLUG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First and longest wait between two failed reconnect attempts.
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkState {
+    /// No link, and none wanted.
+    Disconnected,
+    Connected,
+    /// The link dropped without being asked to; waiting for the device to come back.
+    Lost,
+    /// The device is back on the bus and a reconnect is in progress.
+    Reconnecting,
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LinkState::Disconnected => "disconnected",
+            LinkState::Connected => "connected",
+            LinkState::Lost => "lost",
+            LinkState::Reconnecting => "reconnecting",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What identifies a USB serial adapter across re-enumeration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsbIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+impl fmt::Display for UsbIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)?;
+        if let Some(serial) = &self.serial_number {
+            write!(f, " ({})", serial)?;
+        }
+        Ok(())
+    }
+}
+
+impl UsbIdentity {
+    /// Look up the USB identity of `port_name`, if it is a USB port.
+    pub fn of_port(port_name: &str) -> Option<Self> {
+        serialport::available_ports()
+            .ok()?
+            .into_iter()
+            .find(|port| port.port_name == port_name)
+            .and_then(|port| match port.port_type {
+                SerialPortType::UsbPort(info) => Some(Self {
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number,
+                }),
+                _ => None,
+            })
+    }
+
+    /// Find the port this device is currently enumerated as.
+    pub fn find_port(&self) -> Option<String> {
+        serialport::available_ports()
+            .ok()?
+            .into_iter()
+            .find(|port| match &port.port_type {
+                SerialPortType::UsbPort(info) => {
+                    info.vid == self.vid
+                        && info.pid == self.pid
+                        && info.serial_number == self.serial_number
+                }
+                _ => false,
+            })
+            .map(|port| port.port_name)
+    }
+}
+
+/// Health of the serial link, shared between the connection, its reader
+/// thread and the reconnect supervisor.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkHealth {
+    pub state: LinkState,
+    pub port_name: String,
+    pub usb: Option<UsbIdentity>,
+    pub connected_since: Option<DateTime<Local>>,
+    pub last_rx: Option<DateTime<Local>>,
+    pub last_error: Option<String>,
+    /// Failed attempts since the link was lost.
+    pub reconnect_attempts: u32,
+    /// Successful reconnects since the user connected.
+    pub reconnects: u32,
+}
+
+impl Default for LinkHealth {
+    fn default() -> Self {
+        Self {
+            state: LinkState::Disconnected,
+            port_name: String::new(),
+            usb: None,
+            connected_since: None,
+            last_rx: None,
+            last_error: None,
+            reconnect_attempts: 0,
+            reconnects: 0,
+        }
+    }
+}
+
+impl LinkHealth {
+    /// Where to look for the device when reconnecting.
+    pub fn locate(&self) -> Option<String> {
+        match &self.usb {
+            Some(identity) => identity.find_port(),
+            // Not a USB device: wait for the same path to reappear
+            None => serialport::available_ports()
+                .ok()?
+                .into_iter()
+                .find(|port| port.port_name == self.port_name)
+                .map(|port| port.port_name),
+        }
+    }
+
+    /// Whether the device is still enumerated on the bus.
+    pub fn is_present(&self) -> bool {
+        match &self.usb {
+            Some(identity) => identity.find_port().is_some(),
+            // Ports without a USB identity can't be watched; rely on read errors
+            None => true,
+        }
+    }
+}
+
+/// Next wait after a failed reconnect attempt.
+pub fn next_backoff(current: Duration) -> Duration {
+    std::cmp::min(current * 2, MAX_BACKOFF)
+}
@@ -1,2 +1,12 @@
+pub mod capture;
+pub mod events;
+pub mod firmware;
+pub mod link;
+pub mod params;
+pub mod protocol;
+pub mod registry;
+pub mod response;
+pub mod scan_results;
 pub mod serial_connection;
 pub mod serial_commands;
+pub mod watchdog;
@@ -0,0 +1,288 @@
+//! Attack parameter schemas and client-side validation.
+//!
+//! Firmware that supports it answers `params <attack>` with one line per
+//! parameter, either a JSON object or `|`-separated fields:
+//!
+//! ```text
+//! channel|int|1..14|1|Channel to send deauth frames on
+//! target|mac||FF:FF:FF:FF:FF:FF|Client to disconnect, broadcast for all
+//! mode|choice|once/loop|once|Whether to stop after one round
+//! ```
+//!
+//! The third field is the range: `min..max` for integers, the maximum length
+//! as `..max` for strings, and the allowed values separated by `/` for
+//! choices. When the firmware has no `params` command the built-in table
+//! below is used instead.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    Int,
+    Bool,
+    String,
+    Mac,
+    Ip,
+    Choice,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ParamType::Int => "int",
+            ParamType::Bool => "bool",
+            ParamType::String => "string",
+            ParamType::Mac => "mac",
+            ParamType::Ip => "ip",
+            ParamType::Choice => "choice",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for ParamType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.trim().to_lowercase()))
+            .map_err(|_| anyhow!("Unknown parameter type '{}'", s))
+    }
+}
+
+/// One attack parameter as described by the firmware or the built-in table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParamType,
+    /// Smallest value for `int`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    /// Largest value for `int`, longest value for `string`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    /// Allowed values for `choice`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl ParamSpec {
+    /// Parse one `name|type|range|default|description` line.
+    pub fn parse_line(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.splitn(5, '|').map(str::trim).collect();
+        let [name, kind, range, default, description] = fields.as_slice() else {
+            return Err(anyhow!("Expected name|type|range|default|description"));
+        };
+
+        let mut spec = Self {
+            name: name.to_string(),
+            kind: kind.parse()?,
+            min: None,
+            max: None,
+            choices: Vec::new(),
+            default: (!default.is_empty()).then(|| default.to_string()),
+            description: description.to_string(),
+        };
+
+        if spec.kind == ParamType::Choice {
+            spec.choices = range.split('/').map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect();
+        } else if let Some((min, max)) = range.split_once("..") {
+            spec.min = (!min.is_empty()).then(|| min.parse()).transpose().context("Invalid minimum")?;
+            spec.max = (!max.is_empty()).then(|| max.parse()).transpose().context("Invalid maximum")?;
+        }
+
+        Ok(spec)
+    }
+
+    /// Human-readable range, e.g. `1..14`, `≤32 chars` or `once/loop`.
+    pub fn range(&self) -> String {
+        match self.kind {
+            ParamType::Choice => self.choices.join("/"),
+            ParamType::String => self.max.map(|max| format!("≤{} chars", max)).unwrap_or_default(),
+            _ => match (self.min, self.max) {
+                (None, None) => String::new(),
+                (min, max) => format!(
+                    "{}..{}",
+                    min.map(|m| m.to_string()).unwrap_or_default(),
+                    max.map(|m| m.to_string()).unwrap_or_default()
+                ),
+            },
+        }
+    }
+
+    /// Check `value` against the spec and return it in the form the firmware expects.
+    pub fn validate(&self, value: &str) -> Result<String> {
+        let invalid = |reason: String| anyhow!("Invalid value '{}' for {}: {}", value, self.name, reason);
+
+        match self.kind {
+            ParamType::Int => {
+                let number: i64 = value.parse().map_err(|_| invalid("expected an integer".to_string()))?;
+                if self.min.is_some_and(|min| number < min) || self.max.is_some_and(|max| number > max) {
+                    return Err(invalid(format!("must be in {}", self.range())));
+                }
+                Ok(number.to_string())
+            }
+            ParamType::Bool => match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok("true".to_string()),
+                "false" | "no" | "off" | "0" => Ok("false".to_string()),
+                _ => Err(invalid("expected true or false".to_string())),
+            },
+            ParamType::String => {
+                if self.max.is_some_and(|max| value.chars().count() as i64 > max) {
+                    return Err(invalid(format!("longer than {} characters", self.max.unwrap_or_default())));
+                }
+                Ok(value.to_string())
+            }
+            ParamType::Mac => {
+                let octets: Vec<&str> = value.split([':', '-']).collect();
+                let valid = octets.len() == 6
+                    && octets.iter().all(|o| o.len() == 2 && u8::from_str_radix(o, 16).is_ok());
+                if !valid {
+                    return Err(invalid("expected a MAC address like AA:BB:CC:DD:EE:FF".to_string()));
+                }
+                Ok(octets.join(":").to_uppercase())
+            }
+            ParamType::Ip => value
+                .parse::<IpAddr>()
+                .map(|ip| ip.to_string())
+                .map_err(|_| invalid("expected an IP address".to_string())),
+            ParamType::Choice => self
+                .choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(value))
+                .cloned()
+                .ok_or_else(|| invalid(format!("expected one of {}", self.choices.join(", ")))),
+        }
+    }
+
+    /// The `|`-separated line form, as sent by the firmware.
+    pub fn to_line(&self) -> String {
+        let range = match self.kind {
+            ParamType::Choice => self.choices.join("/"),
+            _ if self.min.is_none() && self.max.is_none() => String::new(),
+            _ => format!(
+                "{}..{}",
+                self.min.map(|m| m.to_string()).unwrap_or_default(),
+                self.max.map(|m| m.to_string()).unwrap_or_default()
+            ),
+        };
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.name,
+            self.kind,
+            range,
+            self.default.as_deref().unwrap_or(""),
+            self.description
+        )
+    }
+}
+
+/// Where a schema came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaSource {
+    Firmware,
+    BuiltIn,
+}
+
+impl fmt::Display for SchemaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaSource::Firmware => write!(f, "from firmware"),
+            SchemaSource::BuiltIn => write!(f, "built-in"),
+        }
+    }
+}
+
+/// Every parameter of one attack.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSchema {
+    pub attack: String,
+    pub source: SchemaSource,
+    pub params: Vec<ParamSpec>,
+}
+
+impl ParamSchema {
+    pub fn get(&self, name: &str) -> Option<&ParamSpec> {
+        self.params.iter().find(|spec| spec.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.params.iter().map(|spec| spec.name.clone()).collect()
+    }
+
+    /// Validate `value` for `param`, rejecting parameters the attack doesn't have.
+    pub fn validate(&self, param: &str, value: &str) -> Result<String> {
+        match self.get(param) {
+            Some(spec) => spec.validate(value),
+            None => Err(anyhow!(
+                "{} has no parameter '{}' (known: {})",
+                self.attack,
+                param,
+                self.names().join(", ")
+            )),
+        }
+    }
+}
+
+fn spec(name: &str, kind: ParamType, range: (Option<i64>, Option<i64>), default: &str, description: &str) -> ParamSpec {
+    ParamSpec {
+        name: name.to_string(),
+        kind,
+        min: range.0,
+        max: range.1,
+        choices: Vec::new(),
+        default: (!default.is_empty()).then(|| default.to_string()),
+        description: description.to_string(),
+    }
+}
+
+const CHANNEL: (Option<i64>, Option<i64>) = (Some(1), Some(14));
+const UNBOUNDED: (Option<i64>, Option<i64>) = (None, None);
+
+/// Parameters of the stock firmware, used when the device can't describe them.
+pub fn builtin_schema(attack: &str) -> Option<ParamSchema> {
+    use ParamType::*;
+
+    let params = match attack {
+        "deauth" => vec![
+            spec("channel", Int, CHANNEL, "1", "WiFi channel to send deauth frames on"),
+            spec("target", Mac, UNBOUNDED, "FF:FF:FF:FF:FF:FF", "Client to disconnect, broadcast for all"),
+            spec("reason", Int, (Some(1), Some(24)), "7", "802.11 reason code in the frames"),
+        ],
+        "beaconFlood" => vec![
+            spec("ssid_count", Int, (Some(1), Some(100)), "20", "Number of fake SSIDs to advertise"),
+            spec("channel", Int, CHANNEL, "1", "WiFi channel to beacon on"),
+        ],
+        "probeSpam" => vec![spec("rate", Int, (Some(1), Some(100)), "10", "Probe requests per second")],
+        "arpSpoof" => vec![
+            spec("target", Ip, UNBOUNDED, "", "Host whose ARP cache is poisoned"),
+            spec("gateway", Ip, UNBOUNDED, "", "Gateway to impersonate"),
+        ],
+        "mqttSpoof" => vec![
+            spec("broker", Ip, UNBOUNDED, "", "Broker to publish spoofed messages to"),
+            spec("topic", String, (None, Some(128)), "#", "Topic to publish on"),
+        ],
+        "evilTwin" => vec![
+            spec("ssid", String, (None, Some(32)), "", "SSID of the network to clone"),
+            spec("channel", Int, CHANNEL, "6", "WiFi channel for the fake access point"),
+        ],
+        "passiveSniff" => vec![spec("channel", Int, CHANNEL, "1", "WiFi channel to listen on")],
+        "bluetoothScan" => vec![spec("duration", Int, (Some(1), Some(60)), "10", "Scan time in seconds")],
+        _ => return None,
+    };
+
+    Some(ParamSchema {
+        attack: attack.to_string(),
+        source: SchemaSource::BuiltIn,
+        params,
+    })
+}
@@ -0,0 +1,352 @@
+//! Wire protocol for the SECoT serial link.
+//!
+//! Two modes are supported:
+//!
+//! * **Text** — one request per line, tagged `#<id> <command>\n`. The device
+//!   answers with lines tagged the same way. Every firmware speaks this.
+//! * **Framed** — COBS-encoded packets terminated by a `0x00` byte. Newer
+//!   firmware switches to it after acknowledging `proto framed <version>`;
+//!   it keeps answering in text until it receives its first framed packet.
+//!
+//! A decoded frame is laid out as:
+//!
+//! ```text
+//! +---------+------+------------+---------+-----------+
+//! | version | type | request id | payload | CRC-16    |
+//! | u8      | u8   | u32 LE     | n bytes | u16 LE    |
+//! +---------+------+------------+---------+-----------+
+//! ```
+//!
+//! The CRC is CRC-16/CCITT-FALSE over everything before it.
+//!
+//! In both modes a response is a sequence of payloads for the same request
+//! ID, closed by `OK` or `ERR <code> [message]` (see `serial::response`).
+
+use thiserror::Error;
+
+/// Version of the framed protocol spoken by this client.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Frames larger than this are discarded instead of buffered forever.
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+const HEADER_LEN: usize = 6;
+const CRC_LEN: usize = 2;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    #[error("invalid COBS encoding")]
+    InvalidEncoding,
+
+    #[error("frame too short ({0} bytes)")]
+    TooShort(usize),
+
+    #[error("frame exceeds {MAX_FRAME_LEN} bytes")]
+    TooLong,
+
+    #[error("CRC mismatch (expected {expected:#06x}, got {actual:#06x})")]
+    CrcMismatch { expected: u16, actual: u16 },
+
+    #[error("unsupported protocol version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("unknown message type {0:#04x}")]
+    UnknownMessageType(u8),
+}
+
+/// How requests and responses are put on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireMode {
+    /// Newline-terminated lines without request tags, one request at a time,
+    /// for the oldest firmware.
+    Untagged,
+    Text,
+    Framed,
+}
+
+impl std::fmt::Display for WireMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireMode::Untagged => write!(f, "untagged text"),
+            WireMode::Text => write!(f, "text"),
+            WireMode::Framed => write!(f, "framed v{}", PROTOCOL_VERSION),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    /// Host to device command.
+    Command = 0x01,
+    /// Device answer to a command, matched by request ID.
+    Response = 0x02,
+    /// Device notification that answers no request (request ID 0).
+    Event = 0x03,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = CodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(MessageType::Command),
+            0x02 => Ok(MessageType::Response),
+            0x03 => Ok(MessageType::Event),
+            other => Err(CodecError::UnknownMessageType(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub version: u8,
+    pub msg_type: MessageType,
+    pub request_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(msg_type: MessageType, request_id: u32, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            msg_type,
+            request_id,
+            payload: payload.into(),
+        }
+    }
+
+    /// Serialize, checksum and COBS-encode the frame, including the trailing delimiter.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(HEADER_LEN + self.payload.len() + CRC_LEN);
+        raw.push(self.version);
+        raw.push(self.msg_type as u8);
+        raw.extend_from_slice(&self.request_id.to_le_bytes());
+        raw.extend_from_slice(&self.payload);
+        raw.extend_from_slice(&crc16(&raw).to_le_bytes());
+
+        let mut encoded = cobs_encode(&raw);
+        encoded.push(0);
+        encoded
+    }
+
+    /// Decode one COBS packet (without its `0x00` delimiter).
+    pub fn decode(packet: &[u8]) -> Result<Self, CodecError> {
+        let raw = cobs_decode(packet).ok_or(CodecError::InvalidEncoding)?;
+        if raw.len() < HEADER_LEN + CRC_LEN {
+            return Err(CodecError::TooShort(raw.len()));
+        }
+
+        let (body, crc_bytes) = raw.split_at(raw.len() - CRC_LEN);
+        let expected = u16::from_le_bytes([crc_bytes[0], crc_bytes[1]]);
+        let actual = crc16(body);
+        if expected != actual {
+            return Err(CodecError::CrcMismatch { expected, actual });
+        }
+
+        let version = body[0];
+        if version != PROTOCOL_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            version,
+            msg_type: MessageType::try_from(body[1])?,
+            request_id: u32::from_le_bytes([body[2], body[3], body[4], body[5]]),
+            payload: body[HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Reassembles frames from arbitrarily split serial reads.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    overflowed: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed received bytes and return every frame they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<Frame, CodecError>> {
+        let mut frames = Vec::new();
+
+        for &byte in bytes {
+            if byte != 0 {
+                if self.buffer.len() < MAX_FRAME_LEN {
+                    self.buffer.push(byte);
+                } else {
+                    self.overflowed = true;
+                }
+                continue;
+            }
+
+            // Delimiter: whatever came before is one packet
+            if self.overflowed {
+                frames.push(Err(CodecError::TooLong));
+            } else if !self.buffer.is_empty() {
+                frames.push(Frame::decode(&self.buffer));
+            }
+            self.buffer.clear();
+            self.overflowed = false;
+        }
+
+        frames
+    }
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF).
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Consistent Overhead Byte Stuffing: removes every `0x00` from `data`.
+pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_index = 0;
+    let mut code: u8 = 1;
+    out.push(0);
+
+    for &byte in data {
+        if byte == 0 {
+            out[code_index] = code;
+            code_index = out.len();
+            out.push(0);
+            code = 1;
+        } else {
+            out.push(byte);
+            code += 1;
+            if code == 0xFF {
+                out[code_index] = code;
+                code_index = out.len();
+                out.push(0);
+                code = 1;
+            }
+        }
+    }
+
+    out[code_index] = code;
+    out
+}
+
+/// Reverse of [`cobs_encode`]. Returns `None` on malformed input.
+pub fn cobs_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        let code = data[i];
+        if code == 0 {
+            return None;
+        }
+
+        let end = i + code as usize;
+        if end > data.len() {
+            return None;
+        }
+
+        out.extend_from_slice(&data[i + 1..end]);
+        i = end;
+
+        if code != 0xFF && i < data.len() {
+            out.push(0);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strip the trailing delimiter `Frame::encode` adds.
+    fn packet(frame: &Frame) -> Vec<u8> {
+        let mut encoded = frame.encode();
+        assert_eq!(encoded.pop(), Some(0));
+        encoded
+    }
+
+    #[test]
+    fn crc16_matches_the_ccitt_false_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn frame_round_trips() {
+        let frame = Frame::new(MessageType::Response, 42, "network HomeWiFi 6 -65");
+        assert_eq!(Frame::decode(&packet(&frame)), Ok(frame));
+    }
+
+    #[test]
+    fn zero_bytes_in_the_payload_survive_encoding() {
+        let frame = Frame::new(MessageType::Command, 0x0100, vec![0, 1, 0, 0, 2, 0]);
+        let encoded = frame.encode();
+        assert_eq!(encoded.iter().filter(|&&byte| byte == 0).count(), 1);
+        assert_eq!(Frame::decode(&encoded[..encoded.len() - 1]), Ok(frame));
+    }
+
+    #[test]
+    fn long_runs_without_zeros_round_trip() {
+        for len in [253, 254, 255, 600] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 255 + 1) as u8).collect();
+            assert_eq!(cobs_decode(&cobs_encode(&data)), Some(data));
+        }
+    }
+
+    #[test]
+    fn decoder_reassembles_a_frame_split_across_reads() {
+        let first = Frame::new(MessageType::Response, 7, "OK");
+        let second = Frame::new(MessageType::Event, 0, "EVENT attack_finished deauth");
+        let bytes = [first.encode(), second.encode()].concat();
+
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(3) {
+            frames.extend(decoder.push(chunk));
+        }
+        assert_eq!(frames, vec![Ok(first), Ok(second)]);
+    }
+
+    #[test]
+    fn corrupted_frame_fails_the_crc() {
+        let frame = Frame::new(MessageType::Response, 1, "state idle");
+        let mut raw = cobs_decode(&packet(&frame)).unwrap();
+        raw[HEADER_LEN] ^= 0x20;
+
+        assert!(matches!(
+            Frame::decode(&cobs_encode(&raw)),
+            Err(CodecError::CrcMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_frame_is_dropped_and_the_decoder_recovers() {
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.push(&vec![1; MAX_FRAME_LEN + 10]).is_empty());
+        assert_eq!(decoder.push(&[0]), vec![Err(CodecError::TooLong)]);
+
+        let frame = Frame::new(MessageType::Response, 2, "OK");
+        assert_eq!(decoder.push(&frame.encode()), vec![Ok(frame)]);
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        assert_eq!(Frame::decode(&[5, 1, 2]), Err(CodecError::InvalidEncoding));
+        assert_eq!(Frame::decode(&cobs_encode(&[1, 2, 3])), Err(CodecError::TooShort(3)));
+    }
+}
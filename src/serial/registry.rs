@@ -0,0 +1,314 @@
+use anyhow::{anyhow, Result};
+use crate::outln;
+use crate::audit::AuditLog;
+use crate::output::table::FormattedTable;
+use crate::serial::events::EventBus;
+use crate::serial::link::LinkState;
+use crate::serial::serial_commands::SerialCommands;
+use crate::serial::watchdog::{DEFAULT_MAX_ATTACK_DURATION, EMERGENCY_STOP_TIMEOUT};
+use futures::future::join_all;
+use prettytable::{Cell, Row, Table};
+use serde_json::{json, Map};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Name of the device created at startup.
+pub const DEFAULT_DEVICE: &str = "default";
+
+/// Target that addresses every registered device, as in `@all secot status`.
+pub const ALL_DEVICES: &str = "all";
+
+struct Registry {
+    devices: BTreeMap<String, Arc<SerialCommands>>,
+    active: String,
+    attack_limit: Duration,
+}
+
+/// Named SECoT devices, each with its own serial link.
+///
+/// Commands go to the active device unless they name another one. All
+/// devices publish on the same event bus; events carry their port as source.
+pub struct DeviceRegistry {
+    events: EventBus,
+    audit: AuditLog,
+    inner: Mutex<Registry>,
+    /// Hosts found by scans this session, for completion.
+    hosts: Mutex<BTreeSet<IpAddr>>,
+}
+
+impl DeviceRegistry {
+    /// Create a registry holding a single, disconnected `default` device.
+    ///
+    /// Every device records the requests it sends in `audit`.
+    pub fn new(events: EventBus, audit: AuditLog) -> Self {
+        let default = Arc::new(SerialCommands::for_bus(events.clone(), audit.clone()));
+
+        Self {
+            events,
+            audit,
+            inner: Mutex::new(Registry {
+                devices: BTreeMap::from([(DEFAULT_DEVICE.to_string(), default)]),
+                active: DEFAULT_DEVICE.to_string(),
+                attack_limit: DEFAULT_MAX_ATTACK_DURATION,
+            }),
+            hosts: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Registry>> {
+        self.inner.lock().map_err(|_| anyhow!("Device registry is poisoned"))
+    }
+
+    /// Register a new, disconnected device under `name`.
+    pub fn add(&self, name: &str) -> Result<Arc<SerialCommands>> {
+        validate_name(name)?;
+
+        let mut registry = self.lock()?;
+        if registry.devices.contains_key(name) {
+            return Err(anyhow!("Device '{}' already exists", name));
+        }
+
+        let device = Arc::new(SerialCommands::for_bus(self.events.clone(), self.audit.clone()));
+        device.set_attack_limit(registry.attack_limit);
+        registry.devices.insert(name.to_string(), device.clone());
+        Ok(device)
+    }
+
+    /// Forget `name`. The caller is responsible for disconnecting it.
+    ///
+    /// Removing the active device makes the first remaining one active.
+    pub fn remove(&self, name: &str) -> Result<Arc<SerialCommands>> {
+        let mut registry = self.lock()?;
+        if registry.devices.len() == 1 && registry.devices.contains_key(name) {
+            return Err(anyhow!("Cannot remove the only device"));
+        }
+
+        let device = registry
+            .devices
+            .remove(name)
+            .ok_or_else(|| anyhow!("No device named '{}'", name))?;
+
+        if registry.active == name {
+            if let Some(first) = registry.devices.keys().next().cloned() {
+                registry.active = first;
+            }
+        }
+
+        Ok(device)
+    }
+
+    /// Make `name` the target of commands without an `@name` prefix.
+    pub fn use_device(&self, name: &str) -> Result<()> {
+        let mut registry = self.lock()?;
+        if !registry.devices.contains_key(name) {
+            return Err(anyhow!("No device named '{}'", name));
+        }
+        registry.active = name.to_string();
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<SerialCommands>> {
+        self.lock()?
+            .devices
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("No device named '{}'", name))
+    }
+
+    pub fn active_name(&self) -> String {
+        self.lock().map(|registry| registry.active.clone()).unwrap_or_default()
+    }
+
+    /// The device commands go to by default.
+    pub fn active(&self) -> Result<Arc<SerialCommands>> {
+        let registry = self.lock()?;
+        registry
+            .devices
+            .get(&registry.active)
+            .cloned()
+            .ok_or_else(|| anyhow!("No active device"))
+    }
+
+    /// Every device, ordered by name.
+    pub fn all(&self) -> Vec<(String, Arc<SerialCommands>)> {
+        self.lock()
+            .map(|registry| {
+                registry
+                    .devices
+                    .iter()
+                    .map(|(name, device)| (name.clone(), device.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Remember hosts a scan found.
+    pub fn remember_hosts(&self, hosts: impl IntoIterator<Item = IpAddr>) {
+        if let Ok(mut known) = self.hosts.lock() {
+            known.extend(hosts);
+        }
+    }
+
+    /// Hosts found by scans so far, in address order.
+    pub fn known_hosts(&self) -> Vec<IpAddr> {
+        self.hosts
+            .lock()
+            .map(|hosts| hosts.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Limit how long attacks may run on every device, present and future.
+    pub fn set_attack_limit(&self, limit: Duration) {
+        if let Ok(mut registry) = self.lock() {
+            registry.attack_limit = limit;
+            for device in registry.devices.values() {
+                device.set_attack_limit(limit);
+            }
+        }
+    }
+
+    /// Stop attacks on every device at once.
+    ///
+    /// With `force`, `stop` goes to every connected device; otherwise only to
+    /// devices this session started attacks on. Returns, per device, whether
+    /// `stop` was sent.
+    pub async fn stop_all(&self, force: bool) -> Vec<(String, Result<bool>)> {
+        let devices = self.all();
+        let stops = devices.iter().map(|(_, device)| async move {
+            // The device may be wedged; don't hang the exit on it
+            tokio::time::timeout(EMERGENCY_STOP_TIMEOUT * 2, device.emergency_stop(force))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("no answer to stop")))
+        });
+        let results = join_all(stops).await;
+
+        devices.into_iter().map(|(name, _)| name).zip(results).collect()
+    }
+
+    /// Show every device with its port and link state.
+    pub async fn list(&self, output_format: &str) -> Result<()> {
+        let active = self.active_name();
+        let mut rows = Vec::new();
+        for (name, device) in self.all() {
+            rows.push((name, device.health().await, device.firmware().await));
+        }
+
+        if output_format == "json" {
+            let devices: Vec<_> = rows
+                .iter()
+                .map(|(name, health, firmware)| {
+                    json!({
+                        "name": name,
+                        "active": *name == active,
+                        "port": health.port_name,
+                        "state": health.state,
+                        "firmware": firmware.as_ref().map(|info| &info.firmware),
+                    })
+                })
+                .collect();
+            outln!("{}", serde_json::to_string_pretty(&devices)?);
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.add_row(Row::new(vec![
+            Cell::new(""),
+            Cell::new("name"),
+            Cell::new("port"),
+            Cell::new("state"),
+            Cell::new("firmware"),
+        ]));
+        for (name, health, firmware) in &rows {
+            let port = match health.state {
+                LinkState::Disconnected => "-",
+                _ => health.port_name.as_str(),
+            };
+            table.add_row(Row::new(vec![
+                Cell::new(if *name == active { "*" } else { "" }),
+                Cell::new(name),
+                Cell::new(port),
+                Cell::new(&health.state.to_string()),
+                Cell::new(firmware.as_ref().map(|info| info.firmware.as_str()).unwrap_or("-")),
+            ]));
+        }
+        outln!("{}", FormattedTable::new("Devices", table));
+
+        Ok(())
+    }
+
+    /// Send `command` to every device at once and show the responses side by side.
+    pub async fn fan_out(&self, command: &str, idle_timeout: Duration, output_format: &str) -> Result<()> {
+        let devices = self.all();
+        let responses = join_all(devices.iter().map(|(_, device)| async move {
+            device.stream_command(command, idle_timeout).await?.collect().await
+        }))
+        .await;
+        let results: Vec<(String, Result<Vec<String>>)> = devices
+            .into_iter()
+            .map(|(name, _)| name)
+            .zip(responses)
+            .collect();
+
+        if output_format == "json" {
+            let mut by_device = Map::new();
+            for (name, result) in &results {
+                let value = match result {
+                    Ok(lines) => json!({ "lines": lines }),
+                    Err(e) => json!({ "error": e.to_string() }),
+                };
+                by_device.insert(name.clone(), value);
+            }
+            outln!("{}", serde_json::to_string_pretty(&by_device)?);
+            return Ok(());
+        }
+
+        // One column per device, one row per response line
+        let columns: Vec<Vec<String>> = results
+            .iter()
+            .map(|(_, result)| match result {
+                Ok(lines) if lines.is_empty() => vec!["OK".to_string()],
+                Ok(lines) => lines.clone(),
+                Err(e) => vec![format!("ERROR: {}", e)],
+            })
+            .collect();
+        let height = columns.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut table = Table::new();
+        table.add_row(Row::new(results.iter().map(|(name, _)| Cell::new(name)).collect()));
+        for row in 0..height {
+            table.add_row(Row::new(
+                columns
+                    .iter()
+                    .map(|column| Cell::new(column.get(row).map(String::as_str).unwrap_or("")))
+                    .collect(),
+            ));
+        }
+        outln!("{}", FormattedTable::new(command, table));
+
+        let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+        if failed > 0 {
+            return Err(anyhow!("{} of {} devices failed", failed, results.len()));
+        }
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name == ALL_DEVICES {
+        return Err(anyhow!("'{}' is reserved for addressing every device", ALL_DEVICES));
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!("Device names may only contain letters, digits, '-' and '_'"));
+    }
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedMutexGuard};
+
+/// Final line of a successful response.
+pub const END_OK: &str = "OK";
+
+/// Prefix of the final line of a failed response, e.g. `ERR 3 bad channel`.
+pub const END_ERR: &str = "ERR";
+
+/// One piece of a device response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponsePart {
+    /// A result line; a response may carry any number of them.
+    Item(String),
+    /// `OK`: the response is complete.
+    Done,
+    /// `ERR <code> [message]`: the command failed.
+    Failed { code: String, message: String },
+}
+
+impl ResponsePart {
+    /// Classify a response payload (the line without its `#<id>` tag).
+    pub fn parse(payload: &str) -> Self {
+        let trimmed = payload.trim();
+
+        if trimmed == END_OK {
+            return ResponsePart::Done;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix(END_ERR) {
+            if rest.is_empty() || rest.starts_with(' ') {
+                let rest = rest.trim_start();
+                let (code, message) = rest.split_once(' ').unwrap_or((rest, ""));
+                return ResponsePart::Failed {
+                    code: code.to_string(),
+                    message: message.trim().to_string(),
+                };
+            }
+        }
+
+        ResponsePart::Item(payload.to_string())
+    }
+
+    pub fn is_final(&self) -> bool {
+        !matches!(self, ResponsePart::Item(_))
+    }
+}
+
+/// The device answered `ERR`: it received the command and refused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceError {
+    pub code: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "Device error {}", self.code)
+        } else {
+            write!(f, "Device error {}: {}", self.code, self.message)
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+/// Requests waiting for a response, keyed by sequence ID.
+pub type PendingMap = Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<ResponsePart>>>>;
+
+/// Forward a response payload to the request it belongs to.
+///
+/// The request is forgotten once its final line arrives.
+pub fn deliver_response(id: u32, payload: &str, pending: &PendingMap) {
+    let part = ResponsePart::parse(payload);
+    let Ok(mut map) = pending.lock() else {
+        return;
+    };
+
+    let Some(tx) = map.get(&id) else {
+        log::warn!("Dropping response to unknown or expired request #{}", id);
+        return;
+    };
+
+    let is_final = part.is_final();
+    if tx.send(part).is_err() || is_final {
+        map.remove(&id);
+    }
+}
+
+/// The lines a device sends back for one command, as they arrive.
+pub struct ResponseStream {
+    id: u32,
+    rx: mpsc::UnboundedReceiver<ResponsePart>,
+    pending: PendingMap,
+    idle_timeout: Duration,
+    finished: bool,
+    /// Held while the request has the link to itself, in untagged mode.
+    turn: Option<OwnedMutexGuard<()>>,
+    /// Silence after a line that ends the response, for firmware that never
+    /// sends `OK`/`ERR`.
+    quiet_gap: Option<Duration>,
+    /// Whether any line has arrived yet.
+    answered: bool,
+}
+
+impl ResponseStream {
+    pub(crate) fn new(
+        id: u32,
+        rx: mpsc::UnboundedReceiver<ResponsePart>,
+        pending: PendingMap,
+        idle_timeout: Duration,
+    ) -> Self {
+        Self {
+            id,
+            rx,
+            pending,
+            idle_timeout,
+            finished: false,
+            turn: None,
+            quiet_gap: None,
+            answered: false,
+        }
+    }
+
+    /// Keep other requests off the link until this response is complete.
+    pub(crate) fn holding(mut self, turn: OwnedMutexGuard<()>) -> Self {
+        self.turn = Some(turn);
+        self
+    }
+
+    /// Also end the response once nothing more arrives for `quiet_gap` after a line.
+    pub(crate) fn ending_after(mut self, quiet_gap: Duration) -> Self {
+        self.quiet_gap = Some(quiet_gap);
+        self
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Change how long to wait between two lines before giving up.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Wait for the next result line.
+    ///
+    /// Returns `None` once the device sends `OK` (or falls quiet after a
+    /// line, if a quiet gap is set), and an error on `ERR`, when the link
+    /// closes, or when no line arrives within the idle timeout.
+    pub async fn next(&mut self) -> Option<Result<String>> {
+        let line = self.next_part().await;
+        if self.finished {
+            self.turn = None;
+        }
+        line
+    }
+
+    async fn next_part(&mut self) -> Option<Result<String>> {
+        if self.finished {
+            return None;
+        }
+
+        let quiet_gap = self.quiet_gap.filter(|_| self.answered);
+        let wait = quiet_gap.unwrap_or(self.idle_timeout);
+
+        let part = match tokio::time::timeout(wait, self.rx.recv()).await {
+            Ok(Some(part)) => part,
+            Ok(None) => {
+                self.finished = true;
+                return Some(Err(anyhow!(
+                    "Connection closed before response to request #{} completed",
+                    self.id
+                )));
+            }
+            Err(_) if quiet_gap.is_some() => {
+                self.finished = true;
+                return None;
+            }
+            Err(_) => {
+                self.finished = true;
+                return Some(Err(anyhow!(
+                    "Timeout waiting for response to request #{}",
+                    self.id
+                )));
+            }
+        };
+
+        match part {
+            ResponsePart::Item(line) => {
+                self.answered = true;
+                Some(Ok(line))
+            }
+            ResponsePart::Done => {
+                self.finished = true;
+                None
+            }
+            ResponsePart::Failed { code, message } => {
+                self.finished = true;
+                Some(Err(DeviceError { code, message }.into()))
+            }
+        }
+    }
+
+    /// Wait for the whole response and return its lines.
+    pub async fn collect(mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.next().await {
+            lines.push(line?);
+        }
+        Ok(lines)
+    }
+
+    /// Wait for the whole response and return it as one newline-joined string.
+    pub async fn text(self) -> Result<String> {
+        Ok(self.collect().await?.join("\n"))
+    }
+}
+
+impl Drop for ResponseStream {
+    fn drop(&mut self) {
+        // Forget the request so a late reply is not mistaken for anything else
+        if let Ok(mut map) = self.pending.lock() {
+            map.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Typed parsing of scan results reported by the device.
+//!
+//! Scans answer either with JSON (one array, or one object per line) or with
+//! one comma-separated record per line:
+//!
+//! ```text
+//! HomeWiFi,00:11:22:33:44:55,6,-65,WPA2
+//! ,DE:AD:BE:EF:00:01,6,-80,WPA
+//! ```
+//!
+//! WiFi records are `ssid,bssid,channel,rssi,encryption`; an empty SSID marks
+//! a hidden network. SSIDs may themselves contain commas.
+//!
+//! MQTT records are an address followed by `yes`/`no` flags:
+//!
+//! ```text
+//! 192.168.1.20:1883 auth=no tls=no open=yes
+//! ```
+
+use anyhow::{anyhow, Context, Result};
+use crate::models::network::{MqttBroker, WiFiNetwork};
+use serde::de::DeserializeOwned;
+use std::cmp::Reverse;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// Port assumed when an MQTT record gives only an address.
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Column a WiFi scan table is ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WifiSort {
+    /// Strongest signal first.
+    #[default]
+    Rssi,
+    Channel,
+    Encryption,
+    Ssid,
+}
+
+impl FromStr for WifiSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rssi" | "signal" => Ok(WifiSort::Rssi),
+            "channel" => Ok(WifiSort::Channel),
+            "encryption" | "auth" => Ok(WifiSort::Encryption),
+            "ssid" => Ok(WifiSort::Ssid),
+            _ => Err(anyhow!("Cannot sort by '{}'; use rssi, channel, encryption or ssid", s)),
+        }
+    }
+}
+
+/// Order networks by `sort`, strongest signal first within equal keys.
+pub fn sort_wifi_networks(networks: &mut [WiFiNetwork], sort: WifiSort) {
+    match sort {
+        WifiSort::Rssi => networks.sort_by_key(|n| Reverse(n.rssi)),
+        WifiSort::Channel => networks.sort_by_key(|n| (n.channel, Reverse(n.rssi))),
+        WifiSort::Encryption => networks.sort_by(|a, b| {
+            a.encryption
+                .cmp(&b.encryption)
+                .then_with(|| b.rssi.cmp(&a.rssi))
+        }),
+        WifiSort::Ssid => networks.sort_by(|a, b| {
+            a.ssid
+                .to_lowercase()
+                .cmp(&b.ssid.to_lowercase())
+                .then_with(|| b.rssi.cmp(&a.rssi))
+        }),
+    }
+}
+
+/// Parse the lines of a `scan wifi` response.
+///
+/// Lines that can't be parsed are skipped with a warning rather than failing
+/// the whole scan.
+pub fn parse_wifi_networks(lines: &[String]) -> Result<Vec<WiFiNetwork>> {
+    let mut networks: Vec<WiFiNetwork> = parse_records(lines, parse_wifi_line)?;
+    for network in &mut networks {
+        network.hidden |= network.ssid.is_empty();
+    }
+    Ok(networks)
+}
+
+/// Parse `ssid,bssid,channel,rssi,encryption`.
+pub fn parse_wifi_line(line: &str) -> Result<WiFiNetwork> {
+    // Split from the right so commas in the SSID survive
+    let mut fields = line.rsplitn(4, ',');
+    let (encryption, rssi, channel, rest) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(encryption), Some(rssi), Some(channel), Some(rest)) => (encryption, rssi, channel, rest),
+        _ => return Err(anyhow!("Expected ssid,bssid,channel,rssi,encryption")),
+    };
+    let (ssid, bssid) = rest
+        .rsplit_once(',')
+        .ok_or_else(|| anyhow!("Expected ssid,bssid,channel,rssi,encryption"))?;
+
+    Ok(WiFiNetwork {
+        ssid: ssid.to_string(),
+        bssid: bssid.trim().to_string(),
+        channel: channel.trim().parse().context("Invalid channel")?,
+        rssi: rssi.trim().parse().context("Invalid RSSI")?,
+        encryption: encryption.trim().to_string(),
+        hidden: ssid.is_empty(),
+    })
+}
+
+/// Parse the lines of a `scan mqtt` response.
+pub fn parse_mqtt_brokers(lines: &[String]) -> Result<Vec<MqttBroker>> {
+    parse_records(lines, parse_mqtt_line)
+}
+
+/// Parse `<ip>[:<port>] auth=<yes|no> tls=<yes|no> open=<yes|no>`.
+pub fn parse_mqtt_line(line: &str) -> Result<MqttBroker> {
+    let mut fields = line.split_whitespace();
+    let address = fields.next().ok_or_else(|| anyhow!("Missing broker address"))?;
+
+    let (ip, port) = match address.parse::<SocketAddr>() {
+        Ok(socket) => (socket.ip(), socket.port()),
+        Err(_) => (
+            address.parse::<IpAddr>().context("Invalid broker address")?,
+            DEFAULT_MQTT_PORT,
+        ),
+    };
+
+    let mut broker = MqttBroker {
+        ip,
+        port,
+        requires_auth: false,
+        supports_tls: false,
+        is_accessible: false,
+    };
+
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=value, got '{}'", field))?;
+        let flag = parse_flag(value).ok_or_else(|| anyhow!("Invalid value for {}: '{}'", key, value))?;
+
+        match key {
+            "auth" => broker.requires_auth = flag,
+            "tls" => broker.supports_tls = flag,
+            "open" => broker.is_accessible = flag,
+            _ => log::debug!("Ignoring unknown broker field '{}'", key),
+        }
+    }
+
+    Ok(broker)
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a response that is either JSON or one line-format record per line.
+pub(crate) fn parse_records<T: DeserializeOwned>(lines: &[String], parse_line: fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    let text = lines.join("\n");
+    let trimmed = text.trim();
+
+    // A single JSON document, possibly spread over several lines
+    if trimmed.starts_with('[') {
+        return serde_json::from_str(trimmed).context("Invalid JSON scan result");
+    }
+
+    let mut records = Vec::new();
+    for line in lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        let record = if line.starts_with('{') {
+            serde_json::from_str(line).map_err(anyhow::Error::from)
+        } else {
+            parse_line(line)
+        };
+
+        match record {
+            Ok(record) => records.push(record),
+            Err(e) => log::warn!("Skipping unparseable scan result '{}': {}", line, e),
+        }
+    }
+
+    Ok(records)
+}
@@ -1,34 +1,203 @@
 use anyhow::{anyhow, Result};
-use crate::serial::serial_connection::SerialConnection;
-use serde_json::{json, Value};
+use crate::outln;
+use crate::audit::AuditLog;
+use crate::error::CliError;
+use crate::models::attack::AttackKind;
+use crate::models::network::{CheckedMqttBroker, MqttBroker, WiFiNetwork};
+use crate::mqtt::broker_utils::test_mqtt_broker;
+use crate::output::formatter::{print_error, print_event, print_info, print_success, print_warning};
+use crate::serial::events::EventBus;
+use crate::serial::firmware::FirmwareInfo;
+use crate::serial::link::{next_backoff, LinkHealth, LinkState, HOTPLUG_POLL_INTERVAL, INITIAL_BACKOFF};
+use crate::output::table::{create_table, FormattedTable};
+use crate::serial::response::{DeviceError, ResponseStream};
+use crate::serial::params::{builtin_schema, ParamSchema, ParamSpec, SchemaSource};
+use crate::serial::scan_results::{parse_mqtt_brokers, parse_records, parse_wifi_networks, sort_wifi_networks, WifiSort};
+use futures::future::join_all;
+use crate::serial::serial_connection::{SerialConnection, DEFAULT_RESPONSE_TIMEOUT};
+use crate::serial::watchdog::{self, ActiveAttack, AttackTracker, DEFAULT_MAX_ATTACK_DURATION};
+use prettytable::{Cell, Row, Table};
+use serde_json::json;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Scans report results as they find them, so allow longer gaps between lines.
+pub const SCAN_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct SerialCommands {
     connection: Arc<Mutex<SerialConnection>>,
+    supervisor: std::sync::Mutex<Option<JoinHandle<()>>>,
+    /// Parameter schemas by attack, fetched once per connection.
+    schemas: std::sync::Mutex<HashMap<String, ParamSchema>>,
+    /// Attacks started from here that haven't finished yet.
+    attacks: AttackTracker,
+    attack_limit: std::sync::Mutex<Duration>,
 }
 
 impl SerialCommands {
     pub fn new(connection: Arc<Mutex<SerialConnection>>) -> Self {
-        Self { connection }
+        Self {
+            connection,
+            supervisor: std::sync::Mutex::new(None),
+            schemas: std::sync::Mutex::new(HashMap::new()),
+            attacks: AttackTracker::default(),
+            attack_limit: std::sync::Mutex::new(DEFAULT_MAX_ATTACK_DURATION),
+        }
+    }
+
+    /// A disconnected device whose unsolicited lines go to `events`.
+    pub fn for_bus(events: EventBus, audit: AuditLog) -> Self {
+        Self::new(Arc::new(Mutex::new(SerialConnection::new(events, audit))))
     }
 
     pub async fn connect_to_port(&self, port_name: &str, baud_rate: u32) -> Result<()> {
         let mut conn = self.connection.lock().await;
-        conn.connect(port_name, baud_rate).await
+        conn.connect(port_name, baud_rate).await?;
+        self.forget_schemas();
+        self.start_supervisor();
+        Ok(())
     }
 
-    pub async fn auto_connect(&self) -> Result<String> {
+    pub async fn auto_connect(&self, preferred: Option<&str>) -> Result<String> {
         let mut conn = self.connection.lock().await;
-        conn.auto_connect().await
+        let port = conn.auto_connect(preferred).await?;
+        self.forget_schemas();
+        self.start_supervisor();
+        Ok(port)
     }
 
     pub async fn disconnect(&self) -> Result<()> {
+        self.stop_supervisor();
         let mut conn = self.connection.lock().await;
         conn.disconnect();
         Ok(())
     }
 
+    /// Watch the link in the background and bring it back after a reset or unplug.
+    fn start_supervisor(&self) {
+        self.stop_supervisor();
+        let handle = tokio::spawn(supervise_link(self.connection.clone(), self.attacks.clone()));
+        if let Ok(mut supervisor) = self.supervisor.lock() {
+            *supervisor = Some(handle);
+        }
+    }
+
+    fn stop_supervisor(&self) {
+        if let Some(handle) = self.supervisor.lock().ok().and_then(|mut s| s.take()) {
+            handle.abort();
+        }
+    }
+
+    /// Record all serial traffic to `path` until `stop_recording` is called.
+    pub async fn start_recording(&self, path: &str) -> Result<()> {
+        let conn = self.connection.lock().await;
+        conn.start_capture(path)
+    }
+
+    /// Stop recording, returning the file and the number of chunks captured.
+    pub async fn stop_recording(&self) -> Result<(String, u64)> {
+        let conn = self.connection.lock().await;
+        conn.stop_capture().ok_or_else(|| anyhow!("Not recording serial traffic"))
+    }
+
+    /// Replace the link with a recorded session read from `path`.
+    pub async fn replay(&self, path: &str, realtime: bool) -> Result<()> {
+        // A recording has no bus to watch and nothing to reconnect to
+        self.stop_supervisor();
+        let mut conn = self.connection.lock().await;
+        conn.replay(path, realtime)
+    }
+
+    /// Show the health of the serial link.
+    pub async fn show_status(&self, output_format: &str) -> Result<()> {
+        let (health, wire_mode, pending, recording, firmware) = {
+            let conn = self.connection.lock().await;
+            (
+                conn.health(),
+                conn.wire_mode(),
+                conn.pending_requests(),
+                conn.capture_path(),
+                conn.firmware().cloned(),
+            )
+        };
+
+        if output_format == "json" {
+            let mut status = serde_json::to_value(&health)?;
+            status["wire_mode"] = json!(wire_mode.to_string());
+            status["pending_requests"] = json!(pending);
+            status["recording"] = json!(recording);
+            status["firmware"] = json!(firmware);
+            outln!("{}", serde_json::to_string_pretty(&status)?);
+            return Ok(());
+        }
+
+        match health.state {
+            LinkState::Disconnected => {
+                print_info("Not connected to any serial port");
+                if let Some(error) = &health.last_error {
+                    outln!("  Last error:  {}", error);
+                }
+                return Ok(());
+            }
+            LinkState::Connected => print_success(&format!("Connected to {}", health.port_name)),
+            LinkState::Reconnecting => print_warning(&format!("Reconnecting to {}...", health.port_name)),
+            LinkState::Lost => print_error(&format!("Link to {} lost, waiting for the device", health.port_name)),
+        }
+
+        if let Some(usb) = &health.usb {
+            outln!("  USB device:  {}", usb);
+        }
+        outln!("  Protocol:    {}", wire_mode);
+        match &firmware {
+            Some(info) => {
+                outln!("  Firmware:    {}", info);
+                if let Some(mac) = &info.mac {
+                    outln!("  MAC:         {}", mac);
+                }
+                if !info.attacks.is_empty() {
+                    outln!("  Attacks:     {}", info.attacks.join(", "));
+                }
+                if !info.params.is_empty() {
+                    outln!("  Parameters:  {}", info.params.len());
+                }
+            }
+            None => outln!("  Firmware:    unknown (no 'info' support)"),
+        }
+        if let Some(since) = health.connected_since {
+            outln!("  Connected:   since {}", since.format("%Y-%m-%d %H:%M:%S"));
+        }
+        match health.last_rx {
+            Some(last_rx) => outln!("  Last RX:     {}", last_rx.format("%H:%M:%S")),
+            None => outln!("  Last RX:     never"),
+        }
+        outln!("  Pending:     {} request(s)", pending);
+        if let Some(path) = &recording {
+            outln!("  Recording:   {}", path);
+        }
+        outln!(
+            "  Reconnects:  {} ({} failed attempt(s) since last loss)",
+            health.reconnects, health.reconnect_attempts
+        );
+        if let Some(error) = &health.last_error {
+            outln!("  Last error:  {}", error);
+        }
+
+        Ok(())
+    }
+
+    pub async fn health(&self) -> LinkHealth {
+        self.connection.lock().await.health()
+    }
+
+    /// Firmware details reported on connect, if the device supports `info`.
+    pub async fn firmware(&self) -> Option<FirmwareInfo> {
+        self.connection.lock().await.firmware().cloned()
+    }
+
     pub async fn is_connected(&self) -> Result<bool> {
         let conn = self.connection.lock().await;
         Ok(conn.is_connected())
@@ -39,11 +208,51 @@ impl SerialCommands {
         Ok(conn.get_port_name().to_string())
     }
 
+    pub async fn events(&self) -> EventBus {
+        let conn = self.connection.lock().await;
+        conn.events().clone()
+    }
+
+    /// Show the most recent device events.
+    pub async fn show_events(&self, count: usize, output_format: &str) -> Result<()> {
+        let events = self.events().await.recent(count);
+
+        if output_format == "json" {
+            outln!("{}", serde_json::to_string_pretty(&events)?);
+        } else if events.is_empty() {
+            outln!("No device events yet");
+        } else {
+            for event in events {
+                print_event(&event.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print device events as they arrive, until the command is cancelled
+    /// with Ctrl-C.
+    pub async fn follow_events(&self, output_format: &str) -> Result<()> {
+        let (mut rx, _guard) = self.events().await.follow();
+        print_info("Following device events, press Ctrl-C to stop...");
+
+        loop {
+            match rx.recv().await {
+                Ok(event) if output_format == "json" => outln!("{}", serde_json::to_string(&event)?),
+                Ok(event) => print_event(&event.to_string()),
+                Err(RecvError::Lagged(missed)) => print_warning(&format!("Missed {} events", missed)),
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn list_ports(&self, output_format: &str) -> Result<()> {
         let ports = SerialConnection::list_available_ports()?;
         
         if ports.is_empty() {
-            println!("No serial ports found");
+            outln!("No serial ports found");
             return Ok(());
         }
 
@@ -56,104 +265,418 @@ impl SerialCommands {
                 });
                 port_list.push(port_info);
             }
-            println!("{}", serde_json::to_string_pretty(&port_list)?);
+            outln!("{}", serde_json::to_string_pretty(&port_list)?);
         } else {
-            println!("Available serial ports:");
+            outln!("Available serial ports:");
             for port in ports {
-                println!("  {} - {:?}", port.port_name, port.port_type);
+                outln!("  {} - {:?}", port.port_name, port.port_type);
             }
         }
 
         Ok(())
     }
 
-    pub async fn send_command(&self, command: &str) -> Result<String> {
+    /// Send a command and get its response lines as they arrive.
+    ///
+    /// `idle_timeout` bounds the wait between two lines, not the whole response.
+    pub async fn stream_command(&self, command: &str, idle_timeout: Duration) -> Result<ResponseStream> {
+        // Only hold the lock while submitting so other commands can be in flight
         let conn = self.connection.lock().await;
-        if !conn.is_connected() {
-            return Err(anyhow!("Not connected to a serial port"));
+        match conn.link_state() {
+            LinkState::Connected => {
+                // Don't send the firmware what it already said it can't do
+                if let Some(firmware) = conn.firmware() {
+                    firmware.check_command(command)?;
+                }
+                conn.submit(command, idle_timeout).await
+            }
+            LinkState::Disconnected => Err(CliError::NotConnected("Not connected to a serial port".to_string()).into()),
+            LinkState::Lost | LinkState::Reconnecting => Err(CliError::NotConnected(format!(
+                "Serial link to {} lost, waiting for the device to come back",
+                conn.get_port_name()
+            ))
+            .into()),
         }
-        conn.send_command(command).await
+    }
+
+    pub async fn send_command(&self, command: &str) -> Result<String> {
+        self.stream_command(command, DEFAULT_RESPONSE_TIMEOUT)
+            .await?
+            .text()
+            .await
     }
 
     // SECoT specific commands
-    pub async fn scan_wifi(&self, output_format: &str) -> Result<()> {
-        let response = self.send_command("scan wifi").await?;
-        
+
+    /// Run a WiFi scan on the device and parse the networks it reports.
+    pub async fn wifi_networks(&self) -> Result<Vec<WiFiNetwork>> {
+        let lines = self
+            .stream_command("scan wifi", SCAN_IDLE_TIMEOUT)
+            .await?
+            .collect()
+            .await?;
+        parse_wifi_networks(&lines)
+    }
+
+    /// Scan for WiFi networks and show them as a table ordered by `sort`.
+    pub async fn scan_wifi(&self, output_format: &str, sort: WifiSort) -> Result<Vec<WiFiNetwork>> {
+        let mut networks = self.wifi_networks().await?;
+        sort_wifi_networks(&mut networks, sort);
+
         if output_format == "json" {
-            // Try to parse the response as JSON
-            if let Ok(json_value) = serde_json::from_str::<Value>(&response) {
-                println!("{}", serde_json::to_string_pretty(&json_value)?);
-            } else {
-                println!("{}", response);
-            }
+            outln!("{}", serde_json::to_string_pretty(&networks)?);
+        } else if networks.is_empty() {
+            outln!("No WiFi networks found");
         } else {
-            println!("{}", response);
+            let headers = ["ssid", "bssid", "channel", "rssi", "encryption", "hidden"];
+            let table = create_table(&networks, &headers).map_err(|e| anyhow!(e))?;
+            let title = format!("WiFi Networks ({})", networks.len());
+            outln!("{}", FormattedTable::new(&title, table));
         }
-        
-        Ok(())
+
+        Ok(networks)
     }
 
-    pub async fn scan_mqtt(&self, output_format: &str) -> Result<()> {
-        let response = self.send_command("scan mqtt").await?;
-        
+    /// Run an MQTT broker scan on the device and parse the brokers it reports.
+    pub async fn mqtt_brokers(&self) -> Result<Vec<MqttBroker>> {
+        let lines = self
+            .stream_command("scan mqtt", SCAN_IDLE_TIMEOUT)
+            .await?
+            .collect()
+            .await?;
+        parse_mqtt_brokers(&lines)
+    }
+
+    /// Scan for MQTT brokers and show them as a table.
+    ///
+    /// With `verify`, every broker the device found is also tried from this
+    /// host, so both views can be compared.
+    pub async fn scan_mqtt(&self, output_format: &str, verify: bool) -> Result<Vec<CheckedMqttBroker>> {
+        let brokers = self.mqtt_brokers().await?;
+
+        let host_results = if verify {
+            print_info(&format!("Checking {} broker(s) from this host...", brokers.len()));
+            join_all(brokers.iter().map(|broker| async move {
+                test_mqtt_broker(&broker.ip.to_string(), broker.port)
+                    .await
+                    .unwrap_or(false)
+            }))
+            .await
+            .into_iter()
+            .map(Some)
+            .collect()
+        } else {
+            vec![None; brokers.len()]
+        };
+
+        let checked: Vec<CheckedMqttBroker> = brokers
+            .into_iter()
+            .zip(host_results)
+            .map(|(broker, host_accessible)| CheckedMqttBroker { broker, host_accessible })
+            .collect();
+
         if output_format == "json" {
-            // Try to parse the response as JSON
-            if let Ok(json_value) = serde_json::from_str::<Value>(&response) {
-                println!("{}", serde_json::to_string_pretty(&json_value)?);
-            } else {
-                println!("{}", response);
-            }
+            outln!("{}", serde_json::to_string_pretty(&checked)?);
+        } else if checked.is_empty() {
+            outln!("No MQTT brokers found");
         } else {
-            println!("{}", response);
+            let mut headers = vec!["ip", "port", "requires_auth", "supports_tls", "is_accessible"];
+            if verify {
+                headers.push("host_accessible");
+            }
+            let table = create_table(&checked, &headers).map_err(|e| anyhow!(e))?;
+            let title = format!("MQTT Brokers ({})", checked.len());
+            outln!("{}", FormattedTable::new(&title, table));
         }
-        
-        Ok(())
+
+        Ok(checked)
     }
 
-    pub async fn start_attack(&self, attack_type: &str, duration: Option<u32>) -> Result<()> {
-        let command = match duration {
-            Some(dur) => format!("attack {} {}", attack_type, dur),
-            None => format!("attack {}", attack_type),
+    /// Start `attack`, for `duration` if given, once its required parameters are set.
+    pub async fn start_attack(&self, attack: &AttackKind, duration: Option<Duration>) -> Result<()> {
+        for param in attack.required_params() {
+            if self.param_value(attack.name(), param).await.is_none() {
+                return Err(anyhow!(
+                    "{} needs '{}' set first: secot set {} {} <value>",
+                    attack,
+                    param,
+                    attack,
+                    param
+                ));
+            }
+        }
+
+        // Never ask for longer than the limit, and bound open-ended attacks by it
+        let limit = self.attack_limit();
+        let duration = match duration {
+            Some(duration) if duration > limit => {
+                return Err(anyhow!(
+                    "{} s is longer than the maximum attack duration of {} s (safety.max_attack_secs in config.json)",
+                    duration.as_secs(),
+                    limit.as_secs()
+                ));
+            }
+            Some(duration) => duration,
+            None => limit,
         };
-        
-        let response = self.send_command(&command).await?;
-        println!("{}", response);
-        
+
+        // Track it before asking: if the reply is lost the attack may still be
+        // running, and only a refusal from the device means it isn't
+        let (token, replaced) = self.attacks.track(attack.name(), duration);
+        let command = format!("attack {} {}", attack, duration.as_secs());
+        let response = match self.send_command(&command).await {
+            Err(e) if e.downcast_ref::<DeviceError>().is_some() => {
+                self.attacks.refused(attack.name(), token, replaced);
+                return Err(e);
+            }
+            response => response,
+        };
+
+        tokio::spawn(watchdog::watch_attack(
+            self.connection.clone(),
+            self.attacks.clone(),
+            attack.name().to_string(),
+            token,
+            duration,
+        ));
+
+        outln!("{}", response?);
         Ok(())
     }
 
-    pub async fn stop_attack(&self, attack_type: Option<&str>) -> Result<()> {
+    pub async fn stop_attack(&self, attack_type: Option<&AttackKind>) -> Result<()> {
         let command = match attack_type {
             Some(attack) => format!("stop {}", attack),
             None => "stop".to_string(),
         };
         
         let response = self.send_command(&command).await?;
-        println!("{}", response);
-        
+        outln!("{}", response);
+
+        match attack_type {
+            Some(attack) => self.attacks.finish(attack.name()),
+            None => {
+                self.attacks.clear();
+            }
+        }
+
         Ok(())
     }
 
+    /// Longest an attack started from here may run.
+    pub fn attack_limit(&self) -> Duration {
+        self.attack_limit
+            .lock()
+            .map(|limit| *limit)
+            .unwrap_or(DEFAULT_MAX_ATTACK_DURATION)
+    }
+
+    pub fn set_attack_limit(&self, limit: Duration) {
+        if let Ok(mut attack_limit) = self.attack_limit.lock() {
+            *attack_limit = limit;
+        }
+    }
+
+    /// Attacks started from here that the device hasn't reported finished.
+    pub fn active_attacks(&self) -> Vec<ActiveAttack> {
+        self.attacks.list()
+    }
+
+    /// Stop every attack on the device without waiting long for it to answer.
+    ///
+    /// Unless `force` is set, devices nothing was started on are left alone.
+    /// Returns whether `stop` was sent.
+    pub async fn emergency_stop(&self, force: bool) -> Result<bool> {
+        if !force && self.attacks.is_empty() {
+            return Ok(false);
+        }
+        if force && self.health().await.state != LinkState::Connected {
+            return Ok(false);
+        }
+
+        watchdog::send_stop(&self.connection, "stop").await?;
+        self.attacks.clear();
+        Ok(true)
+    }
+
     pub async fn get_status(&self) -> Result<()> {
         let response = self.send_command("status").await?;
-        println!("{}", response);
+        outln!("{}", response);
         
         Ok(())
     }
 
+    /// Parameters of `attack`, asked from the firmware or else taken from the built-in table.
+    pub async fn param_schema(&self, attack: &str) -> Result<ParamSchema> {
+        if let Some(schema) = self.schemas.lock().ok().and_then(|s| s.get(attack).cloned()) {
+            return Ok(schema);
+        }
+
+        let schema = match self.fetch_schema(attack).await {
+            Ok(schema) => schema,
+            Err(e) => {
+                log::info!("Using built-in parameters for {}: {}", attack, e);
+                builtin_schema(attack).ok_or_else(|| anyhow!("No parameter schema known for '{}'", attack))?
+            }
+        };
+
+        // Only firmware answers are worth keeping; the built-in table is always at hand
+        if schema.source == SchemaSource::Firmware {
+            if let Ok(mut schemas) = self.schemas.lock() {
+                schemas.insert(attack.to_string(), schema.clone());
+            }
+        }
+        Ok(schema)
+    }
+
+    async fn fetch_schema(&self, attack: &str) -> Result<ParamSchema> {
+        let lines = self
+            .stream_command(&format!("params {}", attack), DEFAULT_RESPONSE_TIMEOUT)
+            .await?
+            .collect()
+            .await?;
+        let params: Vec<ParamSpec> = parse_records(&lines, ParamSpec::parse_line)?;
+        if params.is_empty() {
+            return Err(anyhow!("Firmware reported no parameters for {}", attack));
+        }
+
+        Ok(ParamSchema {
+            attack: attack.to_string(),
+            source: SchemaSource::Firmware,
+            params,
+        })
+    }
+
+    fn forget_schemas(&self) {
+        if let Ok(mut schemas) = self.schemas.lock() {
+            schemas.clear();
+        }
+    }
+
+    /// Attack names known without asking the device, for completion.
+    pub fn known_attacks(&self) -> Vec<String> {
+        // Don't wait for the connection; a command may be holding it
+        if let Ok(conn) = self.connection.try_lock() {
+            if let Some(firmware) = conn.firmware().filter(|f| !f.attacks.is_empty()) {
+                return firmware.attacks.clone();
+            }
+        }
+        AttackKind::ALL.iter().map(AttackKind::to_string).collect()
+    }
+
+    /// Parameter names of `attack` known without asking the device, for completion.
+    pub fn known_params(&self, attack: &str) -> Vec<String> {
+        self.schemas
+            .lock()
+            .ok()
+            .and_then(|schemas| schemas.get(attack).map(ParamSchema::names))
+            .or_else(|| builtin_schema(attack).map(|schema| schema.names()))
+            .unwrap_or_default()
+    }
+
+    /// The parameter `param` of `attack`, if known without asking the device.
+    pub fn known_param(&self, attack: &str, param: &str) -> Option<ParamSpec> {
+        self.schemas
+            .lock()
+            .ok()
+            .and_then(|schemas| schemas.get(attack).and_then(|schema| schema.get(param).cloned()))
+            .or_else(|| builtin_schema(attack).and_then(|schema| schema.get(param).cloned()))
+    }
+
+    /// Show the parameters of `attack` with their types, ranges and defaults.
+    pub async fn show_params(&self, attack: &str, output_format: &str) -> Result<()> {
+        let schema = self.param_schema(attack).await?;
+
+        if output_format == "json" {
+            outln!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.add_row(Row::new(
+            ["name", "type", "range", "default", "description"].iter().map(|h| Cell::new(h)).collect(),
+        ));
+        for spec in &schema.params {
+            table.add_row(Row::new(vec![
+                Cell::new(&spec.name),
+                Cell::new(&spec.kind.to_string()),
+                Cell::new(&spec.range()),
+                Cell::new(spec.default.as_deref().unwrap_or("")),
+                Cell::new(&spec.description),
+            ]));
+        }
+        let title = format!("{} parameters ({})", attack, schema.source);
+        outln!("{}", FormattedTable::new(&title, table));
+
+        Ok(())
+    }
+
+    /// Validate `value` against the parameter schema, then set it on the device.
     pub async fn set_parameter(&self, attack: &str, param: &str, value: &str) -> Result<()> {
+        let value = match self.param_schema(attack).await {
+            Ok(schema) => schema.validate(param, value)?,
+            // Nothing to check against; let the firmware decide
+            Err(_) => value.to_string(),
+        };
+
         let command = format!("set {} {} {}", attack, param, value);
         let response = self.send_command(&command).await?;
-        println!("{}", response);
+        outln!("{}", response);
         
         Ok(())
     }
 
+    /// The value of a parameter on the device, or `None` if it is unset or can't be read.
+    pub async fn param_value(&self, attack: &str, param: &str) -> Option<String> {
+        let response = self.send_command(&format!("get {} {}", attack, param)).await.ok()?;
+        let (_, value) = response.split_once('=')?;
+        let value = value.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
+
     pub async fn get_parameter(&self, attack: &str, param: &str) -> Result<()> {
         let command = format!("get {} {}", attack, param);
         let response = self.send_command(&command).await?;
-        println!("{}", response);
+        outln!("{}", response);
         
         Ok(())
     }
 }
+
+/// Poll the USB bus while the link is up, and reconnect with backoff once it drops.
+///
+/// Runs until the user disconnects.
+async fn supervise_link(connection: Arc<Mutex<SerialConnection>>, attacks: AttackTracker) {
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        tokio::time::sleep(HOTPLUG_POLL_INTERVAL).await;
+
+        let health = connection.lock().await.health();
+        match health.state {
+            LinkState::Disconnected => return,
+            LinkState::Connected => {
+                backoff = INITIAL_BACKOFF;
+                if !health.is_present() {
+                    connection.lock().await.mark_lost("device removed from the USB bus");
+                }
+            }
+            LinkState::Lost | LinkState::Reconnecting => {
+                // Wait for the device to be enumerated again
+                let Some(port) = health.locate() else {
+                    continue;
+                };
+
+                let result = connection.lock().await.reconnect(&port).await;
+                match result {
+                    Ok(()) => watchdog::stop_after_link_loss(&connection, &attacks).await,
+                    Err(e) => {
+                        log::warn!("Reconnect to {} failed: {}", port, e);
+                        tokio::time::sleep(backoff).await;
+                        backoff = next_backoff(backoff);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,246 +1,573 @@
 use anyhow::{anyhow, Result};
-use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+use chrono::Local;
+use crate::audit::{AuditAction, AuditLog, AuditRecord};
+use crate::serial::capture::{self, Capture, CaptureHandle, CaptureHeader, Direction, Recording};
+use crate::serial::events::{DeviceEvent, EventBus, EventKind};
+use crate::serial::firmware::{FirmwareInfo, INFO_COMMAND};
+use crate::serial::link::{LinkHealth, LinkState, UsbIdentity};
+use crate::serial::protocol::{Frame, FrameDecoder, MessageType, WireMode, PROTOCOL_VERSION};
+use crate::serial::response::{deliver_response, PendingMap, ResponseStream};
+use serialport::{SerialPortInfo, SerialPortType};
+use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::mpsc;
-
-// Global static for response handling
-static mut LAST_RESPONSE_TX: Option<tokio::sync::oneshot::Sender<String>> = None;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
 
 const DEFAULT_BAUD_RATE: u32 = 115200;
-const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 const SECOT_IDENTIFIER: &str = "SECoT";
 
+/// How long a request waits for the next line of its response before giving up.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prefix that marks a line as belonging to a request, e.g. `#12 status`.
+const REQUEST_TAG: char = '#';
+
+/// Older firmware may not answer `proto` or `info` at all, so don't wait long for them.
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Untagged firmware doesn't end its answers with `OK`/`ERR`; this much
+/// silence after a line does instead.
+const UNTAGGED_QUIET_GAP: Duration = Duration::from_millis(500);
+
 pub struct SerialConnection {
-    port: Option<Arc<Mutex<Box<dyn SerialPort>>>>,
+    port: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
     port_name: String,
     baud_rate: u32,
-    connected: bool,
-    rx_sender: mpsc::Sender<String>,  // For sending commands to the device
-    tx_sender: mpsc::Sender<String>,  // For sending responses back to the application
+    health: Arc<Mutex<LinkHealth>>,
+    write_tx: Option<mpsc::Sender<Vec<u8>>>, // For sending encoded commands to the device
+    events: EventBus,                        // For device lines that answer no request
+    pending: PendingMap,
+    next_request_id: AtomicU32,
+    shutdown: Arc<AtomicBool>,
+    framed: Arc<AtomicBool>,
+    tagged: Arc<AtomicBool>, // Cleared for firmware that answers without `#<id>` tags
+    turn: Arc<AsyncMutex<()>>, // Taken by the one request an untagged link allows
+    capture: CaptureHandle,
+    firmware: Option<FirmwareInfo>,
+    audit: AuditLog,
 }
 
 impl SerialConnection {
-    pub fn new() -> (Self, mpsc::Sender<String>, mpsc::Receiver<String>) {
-        let (tx_to_serial, _rx_from_app) = mpsc::channel::<String>(100);
-        let (tx_to_app, rx_from_serial) = mpsc::channel::<String>(100);
-
-        (
-            Self {
-                port: None,
-                port_name: String::new(),
-                baud_rate: DEFAULT_BAUD_RATE,
-                connected: false,
-                rx_sender: tx_to_serial.clone(),
-                tx_sender: tx_to_app.clone(),
-            },
-            tx_to_serial,
-            rx_from_serial,
-        )
+    /// Create a disconnected serial connection.
+    ///
+    /// Device lines that are not tagged with a request ID (boot messages,
+    /// asynchronous notifications, ...) are published on `events`. Every
+    /// request written to the device is recorded in `audit`.
+    pub fn new(events: EventBus, audit: AuditLog) -> Self {
+        Self {
+            port: None,
+            port_name: String::new(),
+            baud_rate: DEFAULT_BAUD_RATE,
+            health: Arc::new(Mutex::new(LinkHealth::default())),
+            write_tx: None,
+            events,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_request_id: AtomicU32::new(1),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            framed: Arc::new(AtomicBool::new(false)),
+            tagged: Arc::new(AtomicBool::new(true)),
+            turn: Arc::new(AsyncMutex::new(())),
+            capture: Arc::new(Mutex::new(None)),
+            firmware: None,
+            audit,
+        }
     }
 
     pub async fn connect(&mut self, port_name: &str, baud_rate: u32) -> Result<()> {
-        self.port_name = port_name.to_string();
         self.baud_rate = baud_rate;
 
+        if let Err(e) = self.open_link(port_name).await {
+            self.disconnect();
+            self.update_health(|health| health.last_error = Some(e.to_string()));
+            return Err(e);
+        }
+
+        self.update_health(|health| {
+            health.reconnects = 0;
+            health.reconnect_attempts = 0;
+        });
+
+        Ok(())
+    }
+
+    /// Re-open a lost link, possibly under a new port name after re-enumeration.
+    pub async fn reconnect(&mut self, port_name: &str) -> Result<()> {
+        self.update_health(|health| health.state = LinkState::Reconnecting);
+
+        if let Err(e) = self.open_link(port_name).await {
+            self.close_link();
+            self.update_health(|health| {
+                health.state = LinkState::Lost;
+                health.reconnect_attempts += 1;
+                health.last_error = Some(e.to_string());
+            });
+            return Err(e);
+        }
+
+        self.update_health(|health| {
+            health.reconnects += 1;
+            health.reconnect_attempts = 0;
+        });
+        self.events.publish(DeviceEvent::new(
+            port_name,
+            EventKind::LinkRestored { port: port_name.to_string() },
+        ));
+
+        Ok(())
+    }
+
+    /// Open the port, start the I/O threads and check the device answers.
+    async fn open_link(&mut self, port_name: &str) -> Result<()> {
+        self.close_link();
+        self.port_name = port_name.to_string();
+
+        // serialport treats a baud rate of 0 as "pseudo-terminal, leave the line settings alone"
+        let baud_rate = if is_pseudo_terminal(port_name) { 0 } else { self.baud_rate };
         let port = serialport::new(port_name, baud_rate)
             .timeout(DEFAULT_TIMEOUT)
             .open()?;
-
-        self.port = Some(Arc::new(Mutex::new(port)));
-        self.connected = true;
+        let reader = port.try_clone()?;
+
+        self.port = Some(Arc::new(Mutex::new(Box::new(port))));
+        self.shutdown = Arc::new(AtomicBool::new(false));
+        self.framed = Arc::new(AtomicBool::new(false));
+        self.tagged = Arc::new(AtomicBool::new(true));
+
+        let usb = UsbIdentity::of_port(port_name);
+        self.update_health(|health| {
+            health.state = LinkState::Connected;
+            health.port_name = port_name.to_string();
+            health.usb = usb;
+            health.connected_since = Some(Local::now());
+            health.last_error = None;
+        });
 
         // Start the read and write tasks
-        self.start_read_task();
+        self.start_read_task(Box::new(reader));
         self.start_write_task();
 
-        // Send a ping to verify it's a SECoT device
-        self.send_command("ping").await?;
+        // Upgrade to the framed protocol when the firmware supports it
+        self.negotiate_wire_mode().await;
+
+        // Find out what the firmware can do; older firmware only answers ping
+        self.firmware = self.identify().await;
+        if self.firmware.is_none() {
+            self.send_command("ping").await?;
+        }
 
         Ok(())
     }
 
-    pub async fn auto_connect(&mut self) -> Result<String> {
-        let available_ports = serialport::available_ports()?;
+    /// Query the firmware version and capabilities.
+    async fn identify(&self) -> Option<FirmwareInfo> {
+        let response = match self.submit(INFO_COMMAND, NEGOTIATION_TIMEOUT).await {
+            Ok(stream) => stream.text().await,
+            Err(e) => Err(e),
+        };
+
+        match response.and_then(|response| FirmwareInfo::parse(&response)) {
+            Ok(info) => {
+                log::info!("Device on {} runs firmware {}", self.port_name, info);
+                Some(info)
+            }
+            Err(e) => {
+                log::info!("Device on {} did not identify itself: {}", self.port_name, e);
+                None
+            }
+        }
+    }
 
-        if available_ports.is_empty() {
+    /// Probe `preferred` (if any) and then every USB serial port for a SECoT device.
+    pub async fn auto_connect(&mut self, preferred: Option<&str>) -> Result<String> {
+        let mut candidates: Vec<String> = preferred.map(str::to_string).into_iter().collect();
+
+        for port_info in serialport::available_ports()? {
+            if let SerialPortType::UsbPort(_) = &port_info.port_type {
+                if !candidates.contains(&port_info.port_name) {
+                    candidates.push(port_info.port_name);
+                }
+            }
+        }
+
+        if candidates.is_empty() {
             return Err(anyhow!("No serial ports found"));
         }
 
-        for port_info in available_ports {
-            match &port_info.port_type {
-                SerialPortType::UsbPort(_) => {
-                    // Try to connect to this port
-                    if let Ok(()) = self.connect(&port_info.port_name, DEFAULT_BAUD_RATE).await {
-                        // Wait a moment for the device to respond
-                        tokio::time::sleep(Duration::from_millis(500)).await;
-
-                        // Send a ping and check if it's a SECoT device
-                        if let Ok(response) = self.send_command("ping").await {
-                            if response.contains(SECOT_IDENTIFIER) {
-                                return Ok(port_info.port_name);
-                            }
-                        }
+        for port_name in candidates {
+            // Try to connect to this port
+            if let Ok(()) = self.connect(&port_name, DEFAULT_BAUD_RATE).await {
+                // Wait a moment for the device to respond
+                tokio::time::sleep(Duration::from_millis(500)).await;
 
-                        // Not a SECoT device, disconnect
-                        self.disconnect();
+                // A device that identified itself is a SECoT; otherwise check the ping banner
+                if self.firmware.is_some() {
+                    return Ok(port_name);
+                }
+                if let Ok(response) = self.send_command("ping").await {
+                    if response.contains(SECOT_IDENTIFIER) {
+                        return Ok(port_name);
                     }
                 }
-                _ => continue,
+
+                // Not a SECoT device, disconnect
+                self.disconnect();
             }
         }
 
         Err(anyhow!("No SECoT device found"))
     }
 
+    /// Drive the connection from a capture file instead of a device.
+    ///
+    /// Every command written releases the device output recorded after the
+    /// matching command, so the rest of the CLI behaves as if the recorded
+    /// device were attached. Replays as fast as possible unless `realtime`.
+    pub fn replay(&mut self, path: &str, realtime: bool) -> Result<()> {
+        let recording = Recording::load(path)?;
+        let header = recording.header.clone();
+        let (reader, writer) = recording.into_ports(realtime)?;
+
+        self.close_link();
+        self.port_name = format!("replay:{}", path);
+        self.baud_rate = header.baud_rate;
+        self.port = Some(Arc::new(Mutex::new(Box::new(writer))));
+        self.shutdown = Arc::new(AtomicBool::new(false));
+        self.framed = Arc::new(AtomicBool::new(header.wire_mode.starts_with("framed")));
+        self.tagged = Arc::new(AtomicBool::new(header.wire_mode != WireMode::Untagged.to_string()));
+        // Request IDs must line up with the recorded ones
+        self.next_request_id.store(header.next_request_id, Ordering::SeqCst);
+
+        let port_name = self.port_name.clone();
+        self.update_health(|health| {
+            health.state = LinkState::Connected;
+            health.port_name = port_name;
+            health.usb = None;
+            health.connected_since = Some(Local::now());
+            health.last_error = None;
+            health.reconnects = 0;
+            health.reconnect_attempts = 0;
+        });
+
+        self.start_read_task(Box::new(reader));
+        self.start_write_task();
+
+        Ok(())
+    }
+
+    /// Start recording all traffic on the link to `path`, replacing any running capture.
+    pub fn start_capture(&self, path: &str) -> Result<()> {
+        let header = CaptureHeader {
+            format: capture::CAPTURE_FORMAT.to_string(),
+            version: capture::CAPTURE_VERSION,
+            started: Local::now(),
+            port: self.port_name.clone(),
+            baud_rate: self.baud_rate,
+            wire_mode: self.wire_mode().to_string(),
+            next_request_id: self.next_request_id.load(Ordering::SeqCst),
+        };
+        let recording = Capture::create(path, &header)?;
+
+        let mut capture = self
+            .capture
+            .lock()
+            .map_err(|_| anyhow!("Capture state is poisoned"))?;
+        *capture = Some(recording);
+        Ok(())
+    }
+
+    /// Stop recording. Returns the file and the number of chunks written, if a capture was running.
+    pub fn stop_capture(&self) -> Option<(String, u64)> {
+        let capture = self.capture.lock().ok()?.take()?;
+        Some((capture.path().to_string(), capture.chunks()))
+    }
+
+    /// File the link is currently being recorded to.
+    pub fn capture_path(&self) -> Option<String> {
+        let capture = self.capture.lock().ok()?;
+        capture.as_ref().map(|capture| capture.path().to_string())
+    }
+
     pub fn disconnect(&mut self) {
+        self.close_link();
+        self.update_health(|health| {
+            health.state = LinkState::Disconnected;
+            health.connected_since = None;
+        });
+    }
+
+    /// Stop the I/O threads and release the port without changing the link state.
+    fn close_link(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.write_tx = None;
         self.port = None;
-        self.connected = false;
+        self.firmware = None;
+
+        // Fail every request still waiting on this link
+        if let Ok(mut map) = self.pending.lock() {
+            map.clear();
+        }
+    }
+
+    /// Treat the link as dropped, e.g. because the device left the USB bus.
+    pub fn mark_lost(&mut self, reason: &str) {
+        self.close_link();
+        mark_link_lost(&self.health, &self.events, &self.port_name, reason);
     }
 
     pub fn is_connected(&self) -> bool {
-        self.connected
+        self.link_state() == LinkState::Connected
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.health
+            .lock()
+            .map(|health| health.state)
+            .unwrap_or(LinkState::Disconnected)
+    }
+
+    /// Snapshot of the link health for `serial status`.
+    pub fn health(&self) -> LinkHealth {
+        self.health
+            .lock()
+            .map(|health| health.clone())
+            .unwrap_or_default()
+    }
+
+    /// The live link health, which follows the device to a new port after a reconnect.
+    pub(crate) fn health_handle(&self) -> Arc<Mutex<LinkHealth>> {
+        self.health.clone()
+    }
+
+    fn update_health(&self, update: impl FnOnce(&mut LinkHealth)) {
+        if let Ok(mut health) = self.health.lock() {
+            update(&mut health);
+        }
     }
 
     pub fn get_port_name(&self) -> &str {
         &self.port_name
     }
 
-    pub async fn send_command(&self, command: &str) -> Result<String> {
-        if !self.connected {
-            return Err(anyhow!("Not connected to a serial port"));
-        }
+    /// What the device reported about itself on connect, if it supports `info`.
+    pub fn firmware(&self) -> Option<&FirmwareInfo> {
+        self.firmware.as_ref()
+    }
 
-        // Create a one-shot channel for the response
-        let (response_tx, response_rx) = tokio::sync::oneshot::channel::<String>();
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Protocol currently used on the link.
+    pub fn wire_mode(&self) -> WireMode {
+        if self.framed.load(Ordering::SeqCst) {
+            WireMode::Framed
+        } else if self.tagged.load(Ordering::SeqCst) {
+            WireMode::Text
+        } else {
+            WireMode::Untagged
+        }
+    }
 
-        // Store the response channel in a static map
-        // This is a simplified approach - in a real implementation, you would use a proper
-        // request/response tracking mechanism
-        unsafe {
-            LAST_RESPONSE_TX = Some(response_tx);
+    /// Ask the device to switch to framed packets, staying in text mode if it can't.
+    ///
+    /// Firmware that doesn't answer a tagged `ping` either is spoken to
+    /// without tags.
+    async fn negotiate_wire_mode(&mut self) {
+        let request = format!("proto framed {}", PROTOCOL_VERSION);
+        let expected = format!("framed {}", PROTOCOL_VERSION);
+
+        let accepted = match self.submit(&request, NEGOTIATION_TIMEOUT).await {
+            Ok(stream) => matches!(
+                stream.text().await,
+                Ok(response) if response.trim() == expected
+            ),
+            Err(_) => false,
+        };
+
+        self.framed.store(accepted, Ordering::SeqCst);
+        if !accepted {
+            let tagged = match self.submit("ping", NEGOTIATION_TIMEOUT).await {
+                Ok(stream) => stream.text().await.is_ok(),
+                Err(_) => false,
+            };
+            self.tagged.store(tagged, Ordering::SeqCst);
         }
+        log::info!("Serial link on {} using {} protocol", self.port_name, self.wire_mode());
+    }
 
-        // Send the command
-        let command_with_newline = format!("{}\n", command);
-        self.rx_sender.send(command_with_newline).await?;
+    /// Number of requests currently waiting for a response.
+    pub fn pending_requests(&self) -> usize {
+        self.pending.lock().map(|map| map.len()).unwrap_or(0)
+    }
 
-        // Wait for response with timeout
-        match tokio::time::timeout(Duration::from_secs(5), response_rx).await {
-            Ok(Ok(response)) => Ok(response),
-            Ok(Err(_)) => Err(anyhow!("Channel closed")),
-            Err(_) => Err(anyhow!("Timeout waiting for response")),
+    /// Tag `command` with a fresh sequence ID and write it to the device.
+    ///
+    /// The returned stream yields every line or frame the device sends back
+    /// with the same ID until `OK`/`ERR`, so several commands can be in flight
+    /// at once. `idle_timeout` bounds the wait between two lines.
+    ///
+    /// In untagged mode the command goes out bare and waits until no other
+    /// request is in flight; every untagged line until the device falls quiet
+    /// (or sends `OK`/`ERR`) is its answer.
+    pub async fn submit(&self, command: &str, idle_timeout: Duration) -> Result<ResponseStream> {
+        if self.write_tx.is_none() {
+            return Err(anyhow!("Not connected to a serial port"));
         }
+
+        let write_tx = self
+            .write_tx
+            .as_ref()
+            .ok_or_else(|| anyhow!("Serial writer is not running"))?;
+
+        let wire_mode = self.wire_mode();
+        let turn = match wire_mode {
+            WireMode::Untagged => Some(self.turn.clone().lock_owned().await),
+            _ => None,
+        };
+
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (response_tx, response_rx) = mpsc::unbounded_channel();
+
+        self.pending
+            .lock()
+            .map_err(|_| anyhow!("Pending request table is poisoned"))?
+            .insert(id, response_tx);
+
+        let encoded = match wire_mode {
+            WireMode::Framed => Frame::new(MessageType::Command, id, command).encode(),
+            WireMode::Text => format!("{}{} {}\n", REQUEST_TAG, id, command).into_bytes(),
+            WireMode::Untagged => format!("{}\n", command).into_bytes(),
+        };
+
+        let record = AuditRecord::new(AuditAction::Serial, command)
+            .target(&self.port_name)
+            .bytes(capture::hex_encode(&encoded));
+
+        if write_tx.send(encoded).await.is_err() {
+            if let Ok(mut map) = self.pending.lock() {
+                map.remove(&id);
+            }
+            self.audit.record(record.result("error: serial writer has stopped"));
+            return Err(anyhow!("Serial writer has stopped"));
+        }
+        self.audit.record(record.result("sent"));
+
+        let stream = ResponseStream::new(id, response_rx, self.pending.clone(), idle_timeout);
+        Ok(match turn {
+            Some(turn) => stream.holding(turn).ending_after(UNTAGGED_QUIET_GAP),
+            None => stream,
+        })
     }
 
-    fn start_read_task(&self) {
-        let port_clone = self.port.as_ref().unwrap().clone();
-        let tx_sender = self.tx_sender.clone();
+    /// Send `command` and wait for its complete response, lines joined by `\n`.
+    pub async fn send_command(&self, command: &str) -> Result<String> {
+        self.submit(command, DEFAULT_RESPONSE_TIMEOUT).await?.text().await
+    }
 
-        // Spawn a thread for serial reading to avoid Send issues with MutexGuard
+    fn start_read_task(&self, mut port: Box<dyn Read + Send>) {
+        let pending = self.pending.clone();
+        let events = self.events.clone();
+        let source = self.port_name.clone();
+        let shutdown = self.shutdown.clone();
+        let framed = self.framed.clone();
+        let tagged = self.tagged.clone();
+        let health = self.health.clone();
+        let capture = self.capture.clone();
+
+        // Reading blocks, so it lives on its own thread with its own port handle
         std::thread::spawn(move || {
             let mut buffer = [0u8; 1024];
-            let mut response = String::new();
+            let mut line_buffer: Vec<u8> = Vec::new();
+            let mut decoder = FrameDecoder::new();
+
+            while !shutdown.load(Ordering::SeqCst) {
+                let bytes_read = match port.read(&mut buffer) {
+                    Ok(bytes_read) if bytes_read > 0 => bytes_read,
+                    Ok(_) => continue,
+                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                    Err(e) => {
+                        // Closing the port on purpose also ends up here
+                        if !shutdown.load(Ordering::SeqCst) {
+                            mark_link_lost(&health, &events, &source, &e.to_string());
+                        }
+                        break;
+                    }
+                };
 
-            // Create a runtime for async operations within this thread
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
+                if let Ok(mut health) = health.lock() {
+                    health.last_rx = Some(Local::now());
+                }
+                capture::record(&capture, Direction::Rx, &buffer[..bytes_read]);
 
-            loop {
-                // Use a separate block to limit the lifetime of the MutexGuard
-                {
-                    let mut port_guard = match port_clone.lock() {
-                        Ok(guard) => guard,
-                        Err(_) => {
-                            eprintln!("Failed to acquire lock on serial port");
-                            std::thread::sleep(Duration::from_millis(100));
-                            continue;
-                        }
-                    };
+                if framed.load(Ordering::SeqCst) {
+                    // Anything left over from text mode belongs to the first frame
+                    let mut chunk = std::mem::take(&mut line_buffer);
+                    chunk.extend_from_slice(&buffer[..bytes_read]);
 
-                    match port_guard.read(&mut buffer) {
-                        Ok(bytes_read) if bytes_read > 0 => {
-                            if let Ok(data) = String::from_utf8(buffer[..bytes_read].to_vec()) {
-                                response.push_str(&data);
-                            }
+                    for frame in decoder.push(&chunk) {
+                        match frame {
+                            Ok(frame) => route_frame(frame, &pending, &events, &source),
+                            Err(e) => log::warn!("Discarding corrupt frame: {}", e),
                         }
-                        Err(e) => {
-                            eprintln!("Error reading from serial port: {}", e);
-                            break;
-                        }
-                        _ => {}
                     }
-                } // MutexGuard is dropped here
+                    continue;
+                }
 
-                // Check if we have a complete response (ending with newline)
-                if response.ends_with('\n') {
-                    // Send the response to the channel
-                    let response_clone = response.clone();
-                    rt.block_on(async {
-                        if let Err(e) = tx_sender.send(response_clone).await {
-                            eprintln!("Error sending response: {}", e);
-                        }
-                    });
+                line_buffer.extend_from_slice(&buffer[..bytes_read]);
 
-                    // Also check if there's a waiting oneshot channel
-                    unsafe {
-                        if let Some(tx) = LAST_RESPONSE_TX.take() {
-                            let _ = tx.send(response.clone());
-                        }
+                // Dispatch every complete line in the buffer
+                while let Some(pos) = line_buffer.iter().position(|&b| b == b'\n') {
+                    let raw: Vec<u8> = line_buffer.drain(..=pos).collect();
+                    let line = String::from_utf8_lossy(&raw).trim_end().to_string();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if tagged.load(Ordering::SeqCst) {
+                        route_line(&line, &pending, &events, &source);
+                    } else {
+                        route_untagged_line(&line, &pending, &events, &source);
                     }
-
-                    response.clear();
                 }
+            }
 
-                std::thread::sleep(Duration::from_millis(10));
+            // Nothing more will arrive on this link
+            if let Ok(mut map) = pending.lock() {
+                map.clear();
             }
         });
     }
 
-    fn start_write_task(&self) {
+    fn start_write_task(&mut self) {
         let port_clone = self.port.as_ref().unwrap().clone();
+        let capture = self.capture.clone();
 
-        // Create a channel for receiving commands to send to the device
-        let (tx, mut rx) = mpsc::channel::<String>(100);
-
-        // Store the sender in a thread-local static
-        thread_local! {
-            static COMMAND_SENDER: std::cell::RefCell<Option<mpsc::Sender<String>>> = std::cell::RefCell::new(None);
-        }
-
-        COMMAND_SENDER.with(|cell| {
-            *cell.borrow_mut() = Some(tx);
-        });
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(100);
+        self.write_tx = Some(tx);
 
         // Spawn a thread for serial writing to avoid Send issues with MutexGuard
         std::thread::spawn(move || {
-            // Create a runtime for async operations within this thread
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-
-            rt.block_on(async {
-                while let Some(data) = rx.recv().await {
-                    // Use a separate block to limit the lifetime of the MutexGuard
-                    {
-                        let mut port_guard = match port_clone.lock() {
-                            Ok(guard) => guard,
-                            Err(_) => {
-                                eprintln!("Failed to acquire lock on serial port");
-                                continue;
-                            }
-                        };
-
-                        if let Err(e) = port_guard.write_all(data.as_bytes()) {
-                            eprintln!("Error writing to serial port: {}", e);
+            // Ends once the connection drops its sender
+            while let Some(data) = rx.blocking_recv() {
+                // Use a separate block to limit the lifetime of the MutexGuard
+                {
+                    let mut port_guard = match port_clone.lock() {
+                        Ok(guard) => guard,
+                        Err(_) => {
+                            eprintln!("Failed to acquire lock on serial port");
+                            continue;
                         }
-                    } // MutexGuard is dropped here
-                }
-            });
+                    };
+
+                    if let Err(e) = port_guard.write_all(&data) {
+                        eprintln!("Error writing to serial port: {}", e);
+                        continue;
+                    }
+                    capture::record(&capture, Direction::Tx, &data);
+                } // MutexGuard is dropped here
+            }
         });
     }
 
@@ -248,3 +575,71 @@ impl SerialConnection {
         Ok(serialport::available_ports()?)
     }
 }
+
+/// Whether `port_name` (or what it links to) is a pty, e.g. one served by `secot-sim`.
+fn is_pseudo_terminal(port_name: &str) -> bool {
+    let path = std::fs::canonicalize(port_name).unwrap_or_else(|_| port_name.into());
+    let path = path.to_string_lossy();
+    path.starts_with("/dev/pts/") || path.starts_with("/dev/ttys")
+}
+
+/// Record an unexpected link drop and tell whoever is listening.
+fn mark_link_lost(health: &Mutex<LinkHealth>, events: &EventBus, source: &str, reason: &str) {
+    if let Ok(mut health) = health.lock() {
+        // Only report the first failure; later ones are the same outage
+        if health.state != LinkState::Connected {
+            return;
+        }
+        health.state = LinkState::Lost;
+        health.last_error = Some(reason.to_string());
+    }
+
+    events.publish(DeviceEvent::new(
+        source,
+        EventKind::LinkLost { reason: reason.to_string() },
+    ));
+}
+
+/// Split a `#<id> <payload>` line into its request ID and payload.
+fn parse_tagged_line(line: &str) -> Option<(u32, &str)> {
+    let rest = line.strip_prefix(REQUEST_TAG)?;
+    let (id, payload) = match rest.split_once(' ') {
+        Some((id, payload)) => (id, payload),
+        None => (rest, ""),
+    };
+    Some((id.parse().ok()?, payload))
+}
+
+/// Hand a device line to the request it answers, or publish it as an event.
+fn route_line(line: &str, pending: &PendingMap, events: &EventBus, source: &str) {
+    match parse_tagged_line(line) {
+        Some((id, payload)) => deliver_response(id, payload, pending),
+        None => events.publish(DeviceEvent::from_line(source, line)),
+    }
+}
+
+/// Untagged counterpart of [`route_line`]: anything but an event or boot
+/// message answers the one request in flight.
+fn route_untagged_line(line: &str, pending: &PendingMap, events: &EventBus, source: &str) {
+    let event = DeviceEvent::from_line(source, line);
+    let in_flight = match event.kind {
+        EventKind::Message { .. } => pending.lock().ok().and_then(|map| map.keys().next().copied()),
+        _ => None,
+    };
+
+    match in_flight {
+        Some(id) => deliver_response(id, line, pending),
+        None => events.publish(event),
+    }
+}
+
+/// Framed counterpart of [`route_line`].
+fn route_frame(frame: Frame, pending: &PendingMap, events: &EventBus, source: &str) {
+    let payload = String::from_utf8_lossy(&frame.payload).into_owned();
+
+    match frame.msg_type {
+        MessageType::Response => deliver_response(frame.request_id, &payload, pending),
+        MessageType::Event => events.publish(DeviceEvent::from_line(source, &payload)),
+        MessageType::Command => log::warn!("Ignoring command frame sent by the device"),
+    }
+}
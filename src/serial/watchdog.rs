@@ -0,0 +1,230 @@
+//! Client-side safety net for attacks.
+//!
+//! The firmware keeps transmitting until it is told to stop, so the host
+//! tracks every attack it starts and stops it when
+//!
+//! * the attack outlives the maximum duration (even if the firmware ignores
+//!   the duration it was given),
+//! * the serial link comes back after being lost, since the device may have
+//!   carried on while nobody could reach it,
+//! * the CLI exits, is interrupted, terminated or panics (see `main`).
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use crate::serial::events::{DeviceEvent, EventKind};
+use crate::serial::link::LinkState;
+use crate::serial::serial_connection::SerialConnection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Longest an attack may run unless the config says otherwise.
+pub const DEFAULT_MAX_ATTACK_DURATION: Duration = Duration::from_secs(600);
+
+/// How long after its deadline an attack is stopped by the host, giving the
+/// firmware the chance to finish it by itself first.
+const DEADLINE_GRACE: Duration = Duration::from_secs(2);
+
+/// How long an emergency stop waits for the device to confirm.
+pub const EMERGENCY_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// An attack this session started and hasn't seen finish.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveAttack {
+    pub attack: String,
+    pub started: DateTime<Local>,
+    /// Seconds after which the host stops the attack itself.
+    pub limit_secs: u64,
+    #[serde(skip)]
+    token: u64,
+}
+
+/// Attacks running on one device, by name.
+///
+/// Each start gets a token so a watchdog for an earlier run of the same
+/// attack can't end a later one.
+#[derive(Clone, Default)]
+pub struct AttackTracker {
+    attacks: Arc<Mutex<HashMap<String, ActiveAttack>>>,
+    next_token: Arc<AtomicU64>,
+}
+
+impl AttackTracker {
+    /// Record that `attack` is being started with `limit`, returning its
+    /// token and the run it replaces, if any.
+    pub fn track(&self, attack: &str, limit: Duration) -> (u64, Option<ActiveAttack>) {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let mut replaced = None;
+        if let Ok(mut attacks) = self.attacks.lock() {
+            replaced = attacks.insert(
+                attack.to_string(),
+                ActiveAttack {
+                    attack: attack.to_string(),
+                    started: Local::now(),
+                    limit_secs: limit.as_secs(),
+                    token,
+                },
+            );
+        }
+        (token, replaced)
+    }
+
+    /// Undo [`track`](Self::track) for a start the device refused, putting
+    /// back the run it replaced.
+    pub(crate) fn refused(&self, attack: &str, token: u64, replaced: Option<ActiveAttack>) {
+        let Ok(mut attacks) = self.attacks.lock() else {
+            return;
+        };
+        if attacks.get(attack).is_some_and(|active| active.token == token) {
+            match replaced {
+                Some(replaced) => attacks.insert(attack.to_string(), replaced),
+                None => attacks.remove(attack),
+            };
+        }
+    }
+
+    /// Whether `attack` is still the run identified by `token`.
+    fn is_run(&self, attack: &str, token: u64) -> bool {
+        self.attacks
+            .lock()
+            .map(|attacks| attacks.get(attack).is_some_and(|active| active.token == token))
+            .unwrap_or(false)
+    }
+
+    /// Forget `attack` if it is still the run identified by `token`.
+    fn finish_run(&self, attack: &str, token: u64) -> bool {
+        let Ok(mut attacks) = self.attacks.lock() else {
+            return false;
+        };
+        match attacks.get(attack) {
+            Some(active) if active.token == token => attacks.remove(attack).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Forget `attack`, whichever run it is.
+    pub fn finish(&self, attack: &str) {
+        if let Ok(mut attacks) = self.attacks.lock() {
+            attacks.remove(attack);
+        }
+    }
+
+    /// Forget every attack, returning them.
+    pub fn clear(&self) -> Vec<ActiveAttack> {
+        self.attacks
+            .lock()
+            .map(|mut attacks| attacks.drain().map(|(_, active)| active).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn list(&self) -> Vec<ActiveAttack> {
+        let mut list: Vec<ActiveAttack> = self
+            .attacks
+            .lock()
+            .map(|attacks| attacks.values().cloned().collect())
+            .unwrap_or_default();
+        list.sort_by_key(|active| active.started);
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attacks.lock().map(|attacks| attacks.is_empty()).unwrap_or(true)
+    }
+}
+
+/// Send `command` and wait for the answer, holding the connection only to submit it.
+pub(crate) async fn send_stop(connection: &tokio::sync::Mutex<SerialConnection>, command: &str) -> Result<()> {
+    let stream = {
+        let conn = connection.lock().await;
+        if conn.link_state() != LinkState::Connected {
+            return Err(anyhow!("{} is not connected", conn.get_port_name()));
+        }
+        conn.submit(command, EMERGENCY_STOP_TIMEOUT).await?
+    };
+    stream.text().await.map(|_| ())
+}
+
+/// Stop `attack` once `limit` has passed, unless the device reports it finished first.
+pub(crate) async fn watch_attack(
+    connection: Arc<tokio::sync::Mutex<SerialConnection>>,
+    tracker: AttackTracker,
+    attack: String,
+    token: u64,
+    limit: Duration,
+) {
+    let (events, health) = {
+        let conn = connection.lock().await;
+        (conn.events().clone(), conn.health_handle())
+    };
+    // Events carry the port, which changes if the device comes back under a new name
+    let port = || health.lock().map(|health| health.port_name.clone()).unwrap_or_default();
+    let mut rx = events.subscribe();
+
+    let deadline = tokio::time::sleep(limit + DEADLINE_GRACE);
+    tokio::pin!(deadline);
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            event = rx.recv() => match event {
+                Ok(DeviceEvent { source: from, kind: EventKind::AttackFinished { attack: finished, .. }, .. })
+                    if from == port() && finished == attack =>
+                {
+                    tracker.finish_run(&attack, token);
+                    return;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    // Stopped by hand, or started again since
+    if !tracker.is_run(&attack, token) {
+        return;
+    }
+
+    // Keep tracking it until the device confirms, so exiting still stops it
+    let reason = format!("ran past the {} s limit", limit.as_secs());
+    let reason = match send_stop(&connection, &format!("stop {}", attack)).await {
+        Ok(()) => {
+            tracker.finish_run(&attack, token);
+            reason
+        }
+        Err(e) => format!("{}, but stopping it failed: {}", reason, e),
+    };
+    events.publish(DeviceEvent::new(&port(), EventKind::SafetyStop { attack, reason }));
+}
+
+/// After the link comes back, stop whatever was running when it dropped.
+pub(crate) async fn stop_after_link_loss(connection: &tokio::sync::Mutex<SerialConnection>, tracker: &AttackTracker) {
+    if tracker.is_empty() {
+        return;
+    }
+
+    let stopped = tracker.list();
+    let (events, source) = {
+        let conn = connection.lock().await;
+        (conn.events().clone(), conn.get_port_name().to_string())
+    };
+
+    let reason = match send_stop(connection, "stop").await {
+        Ok(()) => {
+            tracker.clear();
+            "the serial link was lost while it ran".to_string()
+        }
+        Err(e) => format!("the serial link was lost while it ran, but stopping it failed: {}", e),
+    };
+    for active in stopped {
+        events.publish(DeviceEvent::new(
+            &source,
+            EventKind::SafetyStop {
+                attack: active.attack,
+                reason: reason.clone(),
+            },
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use crate::serial::params::{builtin_schema, ParamSpec};
+
+/// Canned behaviour of the simulated device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    /// Answer to `ping`; must contain `SECoT` for auto-connect to accept it.
+    pub identity: String,
+    /// Reported by `info`; leave `firmware` empty to emulate firmware without `info`.
+    pub firmware: String,
+    pub build: String,
+    pub chip: String,
+    pub mac: String,
+    /// Answer `params <attack>` with the stock parameter table.
+    pub param_schema: bool,
+    /// Accept `proto framed 1` and switch to the framed protocol.
+    pub framed: bool,
+    /// Understand `#<id>` request tags; off emulates the oldest firmware.
+    pub tagged: bool,
+    /// Delay before every response line.
+    pub delay_ms: u64,
+    /// Delay between two scan results.
+    pub scan_delay_ms: u64,
+    pub wifi_networks: Vec<String>,
+    pub mqtt_brokers: Vec<String>,
+    pub attacks: Vec<String>,
+    /// Default attack parameters, keyed `<attack>.<param>`.
+    pub parameters: HashMap<String, String>,
+    /// Full command line → response lines; overrides the built-in handlers.
+    pub responses: HashMap<String, Vec<String>>,
+    pub faults: SimFaults,
+}
+
+/// Misbehaviour to inject, for exercising error paths.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimFaults {
+    /// Probability (0.0-1.0) of ignoring a command entirely.
+    pub drop_rate: f64,
+    /// Probability (0.0-1.0) of flipping a bit in an outgoing line or frame.
+    pub corrupt_rate: f64,
+    /// Command prefix → `<code> [message]` to answer with `ERR`.
+    pub error_commands: HashMap<String, String>,
+    /// Simulate a reset after this many commands.
+    pub reboot_after: Option<u32>,
+    /// Seed for the fault RNG; taken from the clock when unset.
+    pub seed: Option<u64>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        let parameters = [
+            ("deauth.channel", "1"),
+            ("deauth.target", "FF:FF:FF:FF:FF:FF"),
+            ("deauth.reason", "7"),
+            ("beaconFlood.ssid_count", "20"),
+            ("beaconFlood.channel", "1"),
+            ("probeSpam.rate", "10"),
+            ("arpSpoof.target", "192.168.1.10"),
+            ("arpSpoof.gateway", "192.168.1.1"),
+            ("mqttSpoof.broker", "192.168.1.20"),
+            ("mqttSpoof.topic", "#"),
+            ("evilTwin.ssid", "HomeWiFi"),
+            ("evilTwin.channel", "6"),
+            ("passiveSniff.channel", "1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        Self {
+            identity: "SECoT simulator 1.0.0".to_string(),
+            firmware: "1.0.0-sim".to_string(),
+            build: "0000000".to_string(),
+            chip: "ESP32-D0WD-V3".to_string(),
+            mac: "24:6F:28:00:00:01".to_string(),
+            param_schema: true,
+            framed: true,
+            tagged: true,
+            delay_ms: 0,
+            scan_delay_ms: 200,
+            wifi_networks: vec![
+                "HomeWiFi,00:11:22:33:44:55,6,-65,WPA2".to_string(),
+                "GuestAccess,AA:BB:CC:DD:EE:FF,11,-72,OPEN".to_string(),
+                "IoT-Lab,10:20:30:40:50:60,1,-48,WPA2".to_string(),
+                ",DE:AD:BE:EF:00:01,6,-80,WPA".to_string(),
+            ],
+            mqtt_brokers: vec![
+                "192.168.1.20:1883 auth=no tls=no open=yes".to_string(),
+                "192.168.1.30:8883 auth=yes tls=yes open=no".to_string(),
+            ],
+            attacks: [
+                "deauth",
+                "beaconFlood",
+                "probeSpam",
+                "arpSpoof",
+                "mqttSpoof",
+                "evilTwin",
+                "passiveSniff",
+                "bluetoothScan",
+            ]
+            .iter()
+            .map(|a| a.to_string())
+            .collect(),
+            parameters,
+            responses: HashMap::new(),
+            faults: SimFaults::default(),
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let config_str = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read simulator config: {}", path))?;
+        serde_json::from_str(&config_str)
+            .with_context(|| format!("Failed to parse simulator config: {}", path))
+    }
+}
+
+/// Something the device does after answering a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SideEffect {
+    /// Emit `EVENT <text>` right after the response.
+    Event(String),
+    /// Finish `attack` after the delay unless it was stopped before.
+    AttackTimer { after: Duration, attack: String },
+    /// Switch to the framed protocol once the host sends a framed packet.
+    EnableFraming,
+    /// Print a boot banner and fall back to text mode.
+    Reboot,
+}
+
+/// The complete answer to one command.
+#[derive(Debug, Default)]
+pub struct SimReply {
+    pub lines: Vec<String>,
+    /// Delay before each line.
+    pub line_delay: Duration,
+    /// `None` for `OK`, `Some("<code> [message]")` for `ERR`.
+    pub error: Option<String>,
+    /// `true` when the command is dropped on the floor.
+    pub dropped: bool,
+    pub effects: Vec<SideEffect>,
+}
+
+impl SimReply {
+    fn ok(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            ..Self::default()
+        }
+    }
+
+    fn err(error: &str) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Command interpreter of the simulated firmware.
+pub struct SimDevice {
+    config: SimConfig,
+    parameters: HashMap<String, String>,
+    active_attacks: HashMap<String, Instant>,
+    booted_at: Instant,
+    commands_handled: u32,
+    rng: XorShift,
+}
+
+impl SimDevice {
+    pub fn new(config: SimConfig) -> Self {
+        let seed = config.faults.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1)
+        });
+
+        Self {
+            parameters: config.parameters.clone(),
+            config,
+            active_attacks: HashMap::new(),
+            booted_at: Instant::now(),
+            commands_handled: 0,
+            rng: XorShift::new(seed),
+        }
+    }
+
+    /// Whether to corrupt the next outgoing line or frame.
+    pub fn should_corrupt(&mut self) -> bool {
+        self.rng.chance(self.config.faults.corrupt_rate)
+    }
+
+    pub fn random_index(&mut self, len: usize) -> usize {
+        (self.rng.next() % len.max(1) as u64) as usize
+    }
+
+    pub fn handle(&mut self, command: &str) -> SimReply {
+        let command = command.trim();
+        self.commands_handled += 1;
+
+        if self.rng.chance(self.config.faults.drop_rate) {
+            return SimReply {
+                dropped: true,
+                ..SimReply::default()
+            };
+        }
+
+        if let Some(limit) = self.config.faults.reboot_after {
+            if self.commands_handled > limit {
+                self.reboot();
+                return SimReply {
+                    dropped: true,
+                    effects: vec![SideEffect::Reboot],
+                    ..SimReply::default()
+                };
+            }
+        }
+
+        let error = self
+            .config
+            .faults
+            .error_commands
+            .iter()
+            .find(|(prefix, _)| command.starts_with(prefix.as_str()))
+            .map(|(_, error)| error.clone());
+        if let Some(error) = error {
+            return SimReply::err(&error);
+        }
+
+        let mut reply = match self.config.responses.get(command) {
+            Some(lines) => SimReply::ok(lines.clone()),
+            None => self.dispatch(command),
+        };
+        if reply.line_delay.is_zero() {
+            reply.line_delay = Duration::from_millis(self.config.delay_ms);
+        }
+        reply
+    }
+
+    fn dispatch(&mut self, command: &str) -> SimReply {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+
+        match parts.as_slice() {
+            ["ping"] => SimReply::ok(vec![self.config.identity.clone()]),
+            ["info"] if !self.config.firmware.is_empty() => SimReply::ok(self.info_lines()),
+            ["params", attack] if self.config.param_schema => match builtin_schema(attack) {
+                Some(schema) => SimReply::ok(schema.params.iter().map(ParamSpec::to_line).collect()),
+                None => SimReply::err(&format!("3 unknown attack {}", attack)),
+            },
+            ["proto", "framed", version] => {
+                if self.config.framed && *version == "1" {
+                    SimReply {
+                        lines: vec!["framed 1".to_string()],
+                        effects: vec![SideEffect::EnableFraming],
+                        ..SimReply::default()
+                    }
+                } else {
+                    SimReply::err("1 unknown command")
+                }
+            }
+            ["status"] => SimReply::ok(self.status_lines()),
+            ["scan", "wifi"] => SimReply {
+                lines: self.config.wifi_networks.clone(),
+                line_delay: Duration::from_millis(self.config.scan_delay_ms),
+                ..SimReply::default()
+            },
+            ["scan", "mqtt"] => SimReply {
+                lines: self.config.mqtt_brokers.clone(),
+                line_delay: Duration::from_millis(self.config.scan_delay_ms),
+                ..SimReply::default()
+            },
+            ["attack", attack] => self.start_attack(attack, None),
+            ["attack", attack, duration] => match duration.parse::<u64>() {
+                Ok(secs) => self.start_attack(attack, Some(secs)),
+                Err(_) => SimReply::err("2 invalid duration"),
+            },
+            ["stop"] => {
+                let stopped: Vec<String> = self.active_attacks.drain().map(|(a, _)| a).collect();
+                let mut reply = SimReply::ok(vec![format!("stopped {}", stopped.len())]);
+                reply.effects = stopped
+                    .into_iter()
+                    .map(|attack| SideEffect::Event(format!("attack_finished {} stopped", attack)))
+                    .collect();
+                reply
+            }
+            ["stop", attack] => match self.active_attacks.remove(*attack) {
+                Some(_) => SimReply {
+                    lines: vec![format!("stopped {}", attack)],
+                    effects: vec![SideEffect::Event(format!("attack_finished {} stopped", attack))],
+                    ..SimReply::default()
+                },
+                None => SimReply::err(&format!("4 {} is not running", attack)),
+            },
+            ["set", attack, param, value] => {
+                if !self.knows_attack(attack) {
+                    return SimReply::err(&format!("3 unknown attack {}", attack));
+                }
+                self.parameters
+                    .insert(format!("{}.{}", attack, param), value.to_string());
+                SimReply::ok(vec![format!("{}.{}={}", attack, param, value)])
+            }
+            ["get", attack, param] => match self.parameters.get(&format!("{}.{}", attack, param)) {
+                Some(value) => SimReply::ok(vec![format!("{}.{}={}", attack, param, value)]),
+                None => SimReply::err(&format!("5 unknown parameter {}.{}", attack, param)),
+            },
+            _ => SimReply::err("1 unknown command"),
+        }
+    }
+
+    fn knows_attack(&self, attack: &str) -> bool {
+        self.config.attacks.iter().any(|a| a == attack)
+    }
+
+    fn start_attack(&mut self, attack: &str, duration: Option<u64>) -> SimReply {
+        if !self.knows_attack(attack) {
+            return SimReply::err(&format!("3 unknown attack {}", attack));
+        }
+
+        self.active_attacks.insert(attack.to_string(), Instant::now());
+
+        let mut effects = vec![SideEffect::Event(format!("attack_started {}", attack))];
+        if let Some(secs) = duration {
+            effects.push(SideEffect::AttackTimer {
+                after: Duration::from_secs(secs),
+                attack: attack.to_string(),
+            });
+        }
+
+        SimReply {
+            lines: vec![format!("started {}", attack)],
+            effects,
+            ..SimReply::default()
+        }
+    }
+
+    /// Forget an attack whose timer ran out. Returns `false` if it was stopped earlier.
+    pub fn finish_attack(&mut self, attack: &str) -> bool {
+        self.active_attacks.remove(attack).is_some()
+    }
+
+    fn info_lines(&self) -> Vec<String> {
+        let mut params: Vec<&str> = self.config.parameters.keys().map(String::as_str).collect();
+        params.sort();
+
+        vec![
+            format!("firmware {}", self.config.firmware),
+            format!("build {}", self.config.build),
+            format!("chip {}", self.config.chip),
+            format!("mac {}", self.config.mac),
+            "protocol 1".to_string(),
+            format!("attacks {}", self.config.attacks.join(",")),
+            format!("params {}", params.join(",")),
+        ]
+    }
+
+    fn status_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("uptime {}s", self.booted_at.elapsed().as_secs())];
+        if self.active_attacks.is_empty() {
+            lines.push("state idle".to_string());
+        } else {
+            lines.push("state attacking".to_string());
+            for (attack, started) in &self.active_attacks {
+                lines.push(format!("attack {} running {}s", attack, started.elapsed().as_secs()));
+            }
+        }
+        lines
+    }
+
+    fn reboot(&mut self) {
+        self.active_attacks.clear();
+        self.parameters = self.config.parameters.clone();
+        self.booted_at = Instant::now();
+        self.commands_handled = 0;
+    }
+}
+
+/// Tiny deterministic RNG so fault injection is reproducible with a seed.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && (self.next() as f64 / u64::MAX as f64) < probability
+    }
+}
@@ -0,0 +1,254 @@
+//! Virtual SECoT device that speaks the serial protocol over a pseudo-terminal,
+//! so the CLI can be exercised end to end without a board.
+
+pub mod device;
+
+pub use device::{SimConfig, SimDevice};
+
+#[cfg(unix)]
+pub use pty::run;
+
+#[cfg(unix)]
+mod pty {
+    use super::device::{SideEffect, SimConfig, SimDevice};
+    use crate::output::formatter::{print_info, print_success};
+    use crate::serial::protocol::{Frame, FrameDecoder, MessageType};
+    use anyhow::{anyhow, Result};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Lines the ESP32 ROM and firmware print after a reset.
+    const BOOT_BANNER: &[&str] = &[
+        "ets Jun  8 2016 00:22:57",
+        "rst:0x1 (POWERON_RESET),boot:0x13 (SPI_FAST_FLASH_BOOT)",
+        "SECoT ready",
+    ];
+
+    struct Simulator {
+        device: Mutex<SimDevice>,
+        writer: Mutex<TTYPort>,
+        /// The host announced framing; parse incoming bytes as frames.
+        framed_in: AtomicBool,
+        /// The host sent its first frame; answer with frames too.
+        framed_out: AtomicBool,
+        /// Treat `#<id>` as part of the command and end replies without
+        /// `OK`/`ERR`, like the oldest firmware.
+        untagged: bool,
+    }
+
+    impl Simulator {
+        fn write(&self, mut bytes: Vec<u8>) {
+            let corrupt = self.device.lock().map(|mut d| d.should_corrupt()).unwrap_or(false);
+            if corrupt && !bytes.is_empty() {
+                let index = self
+                    .device
+                    .lock()
+                    .map(|mut d| d.random_index(bytes.len()))
+                    .unwrap_or(0);
+                bytes[index] ^= 0x20;
+            }
+
+            if let Ok(mut writer) = self.writer.lock() {
+                if let Err(e) = writer.write_all(&bytes) {
+                    eprintln!("Error writing to pty: {}", e);
+                }
+            }
+        }
+
+        fn respond(&self, id: Option<u32>, payload: &str) {
+            let bytes = if self.framed_out.load(Ordering::SeqCst) {
+                Frame::new(MessageType::Response, id.unwrap_or(0), payload).encode()
+            } else {
+                match id {
+                    Some(id) => format!("#{} {}\n", id, payload).into_bytes(),
+                    None => format!("{}\n", payload).into_bytes(),
+                }
+            };
+            self.write(bytes);
+        }
+
+        fn event(&self, text: &str) {
+            let payload = format!("EVENT {}", text);
+            let bytes = if self.framed_out.load(Ordering::SeqCst) {
+                Frame::new(MessageType::Event, 0, payload).encode()
+            } else {
+                format!("{}\n", payload).into_bytes()
+            };
+            self.write(bytes);
+        }
+
+        fn reboot(&self) {
+            self.framed_in.store(false, Ordering::SeqCst);
+            self.framed_out.store(false, Ordering::SeqCst);
+            let banner: String = BOOT_BANNER.iter().map(|line| format!("{}\r\n", line)).collect();
+            self.write(banner.into_bytes());
+        }
+
+        /// Answer one command on its own thread so slow scans don't block others.
+        fn serve(self: &Arc<Self>, id: Option<u32>, command: String) {
+            let sim = self.clone();
+
+            thread::spawn(move || {
+                let reply = match sim.device.lock() {
+                    Ok(mut device) => device.handle(&command),
+                    Err(_) => return,
+                };
+
+                // The host may send its first frame as soon as it reads the answer
+                if reply.effects.contains(&SideEffect::EnableFraming) {
+                    sim.framed_in.store(true, Ordering::SeqCst);
+                }
+
+                if !reply.dropped {
+                    for line in &reply.lines {
+                        thread::sleep(reply.line_delay);
+                        sim.respond(id, line);
+                    }
+                    // The oldest firmware prints its error and nothing after a reply
+                    match &reply.error {
+                        None if sim.untagged => {}
+                        None => sim.respond(id, "OK"),
+                        Some(error) if sim.untagged => {
+                            let message = error.split_once(' ').map_or(error.as_str(), |(_, message)| message);
+                            sim.respond(id, &format!("Error: {}", message));
+                        }
+                        Some(error) => sim.respond(id, &format!("ERR {}", error)),
+                    }
+                }
+
+                for effect in reply.effects {
+                    match effect {
+                        SideEffect::Event(text) => sim.event(&text),
+                        SideEffect::AttackTimer { after, attack } => {
+                            let sim = sim.clone();
+                            thread::spawn(move || {
+                                thread::sleep(after);
+                                let finished = sim
+                                    .device
+                                    .lock()
+                                    .map(|mut d| d.finish_attack(&attack))
+                                    .unwrap_or(false);
+                                if finished {
+                                    sim.event(&format!("attack_finished {} duration elapsed", attack));
+                                }
+                            });
+                        }
+                        SideEffect::EnableFraming => {}
+                        SideEffect::Reboot => sim.reboot(),
+                    }
+                }
+            });
+        }
+
+        fn handle_line(self: &Arc<Self>, line: &str) {
+            let line = line.trim();
+            if line.is_empty() {
+                return;
+            }
+
+            // Tagged `#<id> <command>`, or a bare command from a terminal
+            let tagged = line.strip_prefix('#').filter(|_| !self.untagged).and_then(|rest| {
+                let (id, command) = rest.split_once(' ')?;
+                Some((id.parse::<u32>().ok()?, command.to_string()))
+            });
+
+            match tagged {
+                Some((id, command)) => self.serve(Some(id), command),
+                None => self.serve(None, line.to_string()),
+            }
+        }
+
+        fn handle_frame(self: &Arc<Self>, frame: Frame) {
+            self.framed_out.store(true, Ordering::SeqCst);
+
+            if frame.msg_type != MessageType::Command {
+                eprintln!("Ignoring {:?} frame from host", frame.msg_type);
+                return;
+            }
+
+            let command = String::from_utf8_lossy(&frame.payload).into_owned();
+            self.serve(Some(frame.request_id), command);
+        }
+    }
+
+    /// Open a pty pair and answer SECoT commands on it until the process exits.
+    ///
+    /// When `link` is given, a symlink to the pty is created there so a fixed
+    /// path can be put in `config.json`.
+    pub fn run(config: SimConfig, link: Option<&Path>) -> Result<()> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let pty_name = slave
+            .name()
+            .ok_or_else(|| anyhow!("Could not determine the pty name"))?;
+        master.set_timeout(Duration::from_millis(100))?;
+
+        if let Some(link) = link {
+            if link.is_symlink() {
+                std::fs::remove_file(link)?;
+            }
+            std::os::unix::fs::symlink(&pty_name, link)?;
+            print_info(&format!("Linked {} -> {}", link.display(), pty_name));
+        }
+
+        let untagged = !config.tagged;
+        let sim = Arc::new(Simulator {
+            device: Mutex::new(SimDevice::new(config)),
+            writer: Mutex::new(master.try_clone_native()?),
+            framed_in: AtomicBool::new(false),
+            framed_out: AtomicBool::new(false),
+            untagged,
+        });
+
+        print_success(&format!("Simulated SECoT device listening on {}", pty_name));
+        print_info(&format!("Connect with 'serial connect {}'", pty_name));
+
+        // Keep the slave open so reads on the master don't fail between clients
+        let _slave = slave;
+
+        let mut buffer = [0u8; 1024];
+        let mut line_buffer: Vec<u8> = Vec::new();
+        let mut decoder = FrameDecoder::new();
+
+        loop {
+            let bytes_read = match master.read(&mut buffer) {
+                Ok(0) => continue,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(anyhow!("Error reading from pty: {}", e)),
+            };
+
+            // A new client starts over in text mode, e.g. `#1 proto framed 1`;
+            // frames never contain a newline without also containing a zero
+            let chunk = &buffer[..bytes_read];
+            if sim.framed_in.load(Ordering::SeqCst) && chunk.starts_with(b"#") && chunk.ends_with(b"\n") && !chunk.contains(&0) {
+                sim.framed_in.store(false, Ordering::SeqCst);
+                sim.framed_out.store(false, Ordering::SeqCst);
+                decoder = FrameDecoder::new();
+            }
+
+            if sim.framed_in.load(Ordering::SeqCst) {
+                let mut chunk = std::mem::take(&mut line_buffer);
+                chunk.extend_from_slice(&buffer[..bytes_read]);
+
+                for frame in decoder.push(&chunk) {
+                    match frame {
+                        Ok(frame) => sim.handle_frame(frame),
+                        Err(e) => eprintln!("Discarding corrupt frame from host: {}", e),
+                    }
+                }
+                continue;
+            }
+
+            line_buffer.extend_from_slice(&buffer[..bytes_read]);
+            while let Some(pos) = line_buffer.iter().position(|&b| b == b'\n' || b == b'\r') {
+                let raw: Vec<u8> = line_buffer.drain(..=pos).collect();
+                sim.handle_line(&String::from_utf8_lossy(&raw));
+            }
+        }
+    }
+}